@@ -1,37 +1,191 @@
-use std::fmt::Display;
+use std::{
+    cell::OnceCell,
+    cmp::Ordering,
+    fmt::Display,
+    rc::{Rc, Weak},
+};
 
-use crate::Value;
+#[cfg(feature = "num-bigint")]
+use num_bigint::BigInt;
+use num_rational::Rational64;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
+use crate::{Symbol, Value};
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub struct Program {
     pub statements: Box<[Statement]>,
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum Statement {
     Expression(Expression),
-    Builtin(Builtin),
+    Builtin(Builtin, Span),
     Value(Value),
     Definition {
-        identifier: String,
+        identifier: Symbol,
+        effect: Option<StackEffect>,
         procedure: Procedure,
     },
-    Word(String),
+    Word(Symbol, Span),
+    /// Runs another source file, written `import "path"`.
+    Import(String),
+    /// Declares a type with named fields, written `struct name x y`. This
+    /// defines the constructor `name ( x y -- name )` and the accessors
+    /// `name-x ( name -- x )` and `name-y ( name -- y )`. The fields end
+    /// with the line or before a keyword like `def`.
+    Struct {
+        name: String,
+        fields: Box<[String]>,
+    },
+    /// Pops one value per field and pushes a struct. Only inserted by the
+    /// interpreter.
+    Construct {
+        name: String,
+        fields: Box<[String]>,
+    },
+    /// Replaces a struct by one of its fields. Only inserted by the
+    /// interpreter.
+    Field {
+        name: String,
+        field: String,
+    },
+    /// Declares a variable, written `var name`. The word `name` then pushes
+    /// a reference to it for `set` and `get`.
+    Var(String),
+    /// Puts the following definitions of the current file into a
+    /// vocabulary, written `vocab name`. They can be referred to from
+    /// outside as `name.word`.
+    Vocab(String),
+    /// Pops one value per name and runs `body` with the names bound to them,
+    /// written `{| x y | body }`.
+    Locals {
+        names: Box<[String]>,
+        body: Procedure,
+    },
+    /// Runs the procedure literal it was written in again, written
+    /// `recurse`. Only inserted by the interpreter.
+    Recurse(Rc<Recursion>),
+}
+
+impl Statement {
+    /// Where the statement was written, if it's known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Builtin(_, span) | Self::Word(_, span) if span.line > 0 =>
+                Some(*span),
+            _ => None,
+        }
+    }
 }
 
 impl Display for Statement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Expression(s) => write!(f, "{s}"),
-            Self::Builtin(s) => write!(f, "{s}"),
+            Self::Builtin(s, _) => write!(f, "{s}"),
             Self::Value(v) => write!(f, "{v}"),
-            Self::Definition { identifier, procedure } =>
-                write!(f, "def {identifier} {procedure:#}"),
-            Self::Word(s) => write!(f, "{s}"),
+            Self::Definition { identifier, effect: None, procedure } =>
+                write!(f, "def {} {procedure:#}", identifier.as_str()),
+            Self::Definition { identifier, effect: Some(e), procedure } =>
+                write!(f, "def {} {e} {procedure:#}", identifier.as_str()),
+            Self::Word(s, _) => write!(f, "{}", s.as_str()),
+            Self::Import(s) => write!(f, "import {s:?}"),
+            Self::Struct { name, fields } => {
+                write!(f, "struct {name}")?;
+
+                for x in fields.iter() {
+                    write!(f, " {x}")?;
+                }
+
+                Ok(())
+            }
+            Self::Construct { name, .. } => write!(f, "<construct {name}>"),
+            Self::Field { name, field } => write!(f, "<field {name}-{field}>"),
+            Self::Var(s) => write!(f, "var {s}"),
+            Self::Vocab(s) => write!(f, "vocab {s}"),
+            Self::Recurse(_) => write!(f, "recurse"),
+            Self::Locals { names, body } => {
+                write!(f, "|")?;
+
+                for x in names.iter() {
+                    write!(f, " {x}")?;
+                }
+
+                write!(f, " |")?;
+
+                for s in body.0.iter() {
+                    write!(f, " {s}")?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Where a statement starts in its source, counting lines and columns from 1.
+/// The default span at line 0 is unknown, like for statements the
+/// interpreter inserts.
+///
+/// Spans never affect comparisons, so the same code is equal wherever it is
+/// written.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl PartialOrd for Span {
+    fn partial_cmp(&self, _: &Self) -> Option<Ordering> {
+        Some(Ordering::Equal)
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A declared stack effect like `( a b -- c )`. Only the number of names on
+/// each side matters.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct StackEffect {
+    pub inputs: Box<[String]>,
+    pub outputs: Box<[String]>,
+}
+
+impl Display for StackEffect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(")?;
+
+        for x in self.inputs.iter() {
+            write!(f, " {x}")?;
+        }
+
+        write!(f, " --")?;
+
+        for x in self.outputs.iter() {
+            write!(f, " {x}")?;
         }
+
+        write!(f, " )")
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum Expression {
     Literal(Literal),
@@ -61,8 +215,11 @@ impl Display for Expression {
     }
 }
 
+/// A sequence of statements. Clones share them, so calling a word doesn't
+/// copy its body.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
-pub struct Procedure(pub Box<[Statement]>);
+pub struct Procedure(pub Rc<[Statement]>);
 
 impl Display for Procedure {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -80,26 +237,89 @@ impl Display for Procedure {
     }
 }
 
+/// The procedure literal a `recurse` was written in. Recursions compare by
+/// the literal.
+pub struct Recursion {
+    pub literal: Procedure,
+    /// The literal with `recurse` bound, once it's built. This is weak as
+    /// the bound literal refers back to the recursion.
+    pub bound: OnceCell<Weak<[Statement]>>,
+}
+
+impl std::fmt::Debug for Recursion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Recursion").field(&self.literal).finish()
+    }
+}
+
+impl PartialEq for Recursion {
+    fn eq(&self, other: &Self) -> bool {
+        self.literal == other.literal
+    }
+}
+
+impl PartialOrd for Recursion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.literal.partial_cmp(&other.literal)
+    }
+}
+
+/// Recursions are serialized as the literal as written. The interpreter binds
+/// it again the first time a deserialized `recurse` runs.
+#[cfg(feature = "serde")]
+impl Serialize for Recursion {
+    fn serialize<S: serde::Serializer>(&self, serializer: S)
+    -> Result<S::Ok, S::Error> {
+        self.literal.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Recursion {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D)
+    -> Result<Self, D::Error> {
+        Procedure::deserialize(deserializer).map(|literal| Self {
+            literal,
+            bound: OnceCell::new(),
+        })
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum Literal {
+    Nil,
     Bool(bool),
-    Number(f32),
+    Int(i64),
+    /// An integer literal too large for an `i64`.
+    #[cfg(feature = "num-bigint")]
+    BigInt(BigInt),
+    Rational(Rational64),
+    Number(f64),
     String(String),
+    Symbol(Symbol),
 }
 
 impl Display for Literal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::Nil => write!(f, "nil"),
             Self::Bool(b) => write!(f, "{b}"),
+            Self::Int(a) => write!(f, "{a}"),
+            #[cfg(feature = "num-bigint")]
+            Self::BigInt(a) => write!(f, "{a}"),
+            Self::Rational(a) => write!(f, "{a}"),
             Self::Number(a) => write!(f, "{a}"),
             Self::String(s) => write!(f, "{s:?}"),
+            Self::Symbol(s) => write!(f, "{s}"),
         }
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Builtin {
-    /// Add the top two elements on the stack.
+    /// Add the top two elements on the stack, or concatenate two strings.
     Add,
     /// Subtract the top element from the second element on the stack.
     Sub,
@@ -135,22 +355,401 @@ pub enum Builtin {
     Over,
     /// `x y -- x x y`
     Dupd,
+    /// `x y -- y`
+    Nip,
+    /// `x y -- y x y`
+    Tuck,
     /// Rotates the top three elements of the stack left.
     Rotl,
     /// Rotates the top three elements of the stack right.
     Rotr,
+    /// Copies the element `n` places below the top to the top, so `0 pick`
+    /// is `dup`. ( ... n -- ... x )
+    Pick,
+    /// Moves the element `n` places below the top to the top, so `1 roll`
+    /// is `swap`. ( ... n -- ... x )
+    Roll,
+    /// Push the number of elements on the stack. ( -- n )
+    Depth,
+    /// Remove all elements from the stack. ( ... -- )
+    Clear,
     /// Evaluate a procedure and restore the top element on the stack.
     Keep,
+    /// Apply two procedures to the same value.
+    /// ( x p q -- )
+    Bi,
+    /// Apply three procedures to the same value.
+    /// ( x p q r -- )
+    Tri,
+    /// Apply each procedure in a list to the same value.
+    /// ( x quots -- )
+    Cleave,
     /// Evaluate the top element on the stack.
     Eval,
     /// Print the top element on the stack and append a newline.
     Println,
+    /// Print the top element on the stack without a newline.
+    Print,
+    /// Print the top element on the stack to stderr and append a newline.
+    Eprintln,
+    /// Print the top element on the stack to stderr without a newline.
+    Eprint,
+    /// Read a line from the input without its line ending, or nil at the end
+    /// of the input. ( -- s/nil )
+    ReadLine,
+    /// Read the contents of a file. ( path -- s )
+    ReadFile,
+    /// Write a string to a file, replacing its contents. ( s path -- )
+    WriteFile,
+    /// Append a string to a file, creating it if needed. ( s path -- )
+    AppendFile,
+    /// `path -- bool`
+    FileExists,
+    /// Delete a file. ( path -- )
+    DeleteFile,
+    /// The sorted names of the entries of a directory. ( path -- list )
+    ListDir,
+    /// Create a directory and its missing parents. ( path -- )
+    Mkdir,
+    /// The value of an environment variable, or nil if it is unset.
+    /// ( name -- s/nil )
+    Env,
+    /// Set an environment variable. ( s name -- )
+    SetEnv,
+    /// The command-line arguments passed to the program. ( -- list )
+    Args,
+    /// Stop the program with an exit status, which `try` can't catch.
+    /// ( n -- )
+    Exit,
+    /// Seconds since the Unix epoch. ( -- seconds )
+    Now,
+    /// Milliseconds since the interpreter was created, which never go
+    /// backwards. ( -- ms )
+    Monotonic,
+    /// Pause for a number of milliseconds. ( ms -- )
+    Sleep,
     /// Evaluate the second to top item on the stack if the third is true, else
     /// the top item.
     If,
-    /// Extract the nth item from a list.
-    /// ( n list -- item )
+    /// Evaluate a procedure only if the flag is true. ( bool quot -- )
+    When,
+    /// Evaluate a procedure only if the flag is false. ( bool quot -- )
+    Unless,
+    /// Extract the nth item from a list or the nth character from a string.
+    /// ( n seq -- item )
     Nth,
+    /// `a b -- min(a, b)`, `NaN` if either operand is `NaN`.
+    Min,
+    /// `a b -- max(a, b)`, `NaN` if either operand is `NaN`.
+    Max,
+    /// `a -- |a|`
+    Abs,
+    /// Apply a procedure to every element of a list and collect the results.
+    /// ( list quot -- list )
+    Map,
+    /// Keep the elements of a list for which a procedure leaves `true`.
+    /// ( list quot -- list )
+    Filter,
+    /// Combine the elements of a list into an accumulator, calling a
+    /// procedure with `acc elem` on the stack for each element.
+    /// ( list init quot -- result )
+    Fold,
+    /// Call a procedure with each element of a list on top of the stack.
+    /// ( list quot -- )
+    Each,
+    /// Make a procedure that pushes `x` before running `quot`.
+    /// ( x quot -- quot' )
+    Curry,
+    /// Concatenate two procedures.
+    /// ( p q -- pq )
+    Compose,
+    /// Call a procedure n times.
+    /// ( n quot -- )
+    Times,
+    /// Call `body` for as long as `cond` leaves `true`.
+    /// ( cond body -- )
+    While,
+    /// The numbers from `start` up to, but not including, `end`.
+    /// ( start end -- list )
+    Range,
+    /// The numbers from `start` up to, but not including, `end` in
+    /// increments of `step`.
+    /// ( start end step -- list )
+    RangeStep,
+    /// The number of elements in a list or characters in a string.
+    /// ( seq -- n )
+    Len,
+    /// Concatenate two lists.
+    /// ( list list -- list )
+    Append,
+    /// The first element of a list.
+    /// ( list -- x )
+    Head,
+    /// All but the first element of a list.
+    /// ( list -- rest )
+    Tail,
+    /// Split a list into its first element and the rest.
+    /// ( list -- rest x )
+    Uncons,
+    /// Prepend an element to a list.
+    /// ( x list -- list )
+    Cons,
+    /// Append an element to a list.
+    /// ( list x -- list )
+    Push,
+    /// Sort a list in ascending order.
+    /// ( list -- list )
+    Sort,
+    /// Sort a list by the keys a procedure computes for its elements.
+    /// ( list quot -- list )
+    SortBy,
+    /// Pair up the elements of two lists, stopping at the shorter one.
+    /// ( list list -- list-of-pairs )
+    Zip,
+    /// Splice the elements of nested lists into the containing list, one
+    /// level deep.
+    /// ( list -- list )
+    Flatten,
+    /// Check if a list contains an element or a string contains a substring.
+    /// ( x seq -- bool )
+    Contains,
+    /// The index of the first occurrence of an element in a list.
+    /// ( x list -- n )
+    IndexOf,
+    /// The elements of a list or characters of a string from `start` up to,
+    /// but not including, `end`. Negative indices count from the end.
+    /// ( seq start end -- seq )
+    Slice,
+    /// Replace the nth element of a list.
+    /// ( x n list -- list )
+    SetNth,
+    /// Split a string on a separator, or into characters if the separator is
+    /// empty.
+    /// ( string sep -- list )
+    Split,
+    /// Join the elements of a list into a string with a separator.
+    /// ( list sep -- string )
+    Join,
+    /// Convert a string to upper case.
+    /// ( s -- s )
+    Upper,
+    /// Convert a string to lower case.
+    /// ( s -- s )
+    Lower,
+    /// The `len` characters of a string starting at character `start`.
+    /// ( s start len -- s )
+    Substring,
+    /// Strip leading and trailing whitespace from a string.
+    /// ( s -- s )
+    Trim,
+    /// Strip leading whitespace from a string.
+    /// ( s -- s )
+    TrimStart,
+    /// Strip trailing whitespace from a string.
+    /// ( s -- s )
+    TrimEnd,
+    /// Parse a string as a number.
+    /// ( s -- n )
+    StringToNumber,
+    /// Convert a value to its display representation.
+    /// ( v -- s )
+    ToString,
+    /// Replace each `{}` in a template with the next element of a list.
+    /// `{{` and `}}` stand for literal braces.
+    /// ( args-list template -- string )
+    Format,
+    /// The code point of a character, i.e. a single-character string.
+    /// ( c -- n )
+    Ord,
+    /// The character with the given code point.
+    /// ( n -- c )
+    Chr,
+    /// The remainder of dividing `a` by `b`, with the sign of `a`.
+    /// ( a b -- a%b )
+    Mod,
+    /// `a b -- a^b`
+    Pow,
+    /// `a -- √a`
+    Sqrt,
+    /// Round a number down.
+    Floor,
+    /// Round a number up.
+    Ceil,
+    /// Round a number to the nearest integer, away from zero on ties.
+    Round,
+    /// Round a number towards zero.
+    Trunc,
+    /// The sine of an angle in radians.
+    Sin,
+    /// The cosine of an angle in radians.
+    Cos,
+    /// The tangent of an angle in radians.
+    Tan,
+    /// The angle of the point `(x, y)` in radians.
+    /// ( y x -- angle )
+    Atan2,
+    /// The natural logarithm.
+    Ln,
+    /// The base 10 logarithm.
+    Log10,
+    /// `a -- e^a`
+    Exp,
+    /// `-- π`
+    Pi,
+    /// `-- e`
+    E,
+    /// `a b -- a ∧ b`
+    And,
+    /// `a b -- a ∨ b`
+    Or,
+    /// `a b -- a ⊕ b`
+    Xor,
+    /// Evaluate a procedure which has to leave a bool only if `a` is true.
+    /// ( a quot -- a ∧ quot )
+    AndThen,
+    /// Evaluate a procedure which has to leave a bool only if `a` is false.
+    /// ( a quot -- a ∨ quot )
+    OrElse,
+    /// The bitwise and of two integers. ( a b -- c )
+    BitAnd,
+    /// The bitwise or of two integers. ( a b -- c )
+    BitOr,
+    /// The bitwise exclusive or of two integers. ( a b -- c )
+    BitXor,
+    /// Shift an integer left by `n` bits. ( a n -- b )
+    Shl,
+    /// Shift an integer right by `n` bits, preserving the sign.
+    /// ( a n -- b )
+    Shr,
+    /// Store a value in a variable declared with `var`. ( x var -- )
+    Set,
+    /// Push the value of a variable declared with `var`. ( var -- x )
+    Get,
+    /// `list -- set`
+    ListToSet,
+    /// `set -- list`
+    SetToList,
+    /// `set x -- set`
+    SetAdd,
+    /// `set x -- bool`
+    SetHas,
+    /// The statements of a procedure, with words and builtins as symbols and
+    /// pushed symbols like `:foo` as `[:quote :foo]`. ( quot -- list )
+    QuotToList,
+    /// The procedure with the items of a list as statements, with symbols as
+    /// words and builtins and `[:quote x]` pushing `x`. ( list -- quot )
+    ListToQuot,
+    /// Push the items of a list. ( list -- x1 ... xn )
+    Spread,
+    /// Push the items of a list and evaluate a procedure.
+    /// ( list quot -- ... )
+    Apply,
+    /// `a b -- a ∪ b` on sets.
+    Union,
+    /// `a b -- a ∩ b` on sets.
+    Intersect,
+    /// `a b -- a \ b` on sets.
+    Difference,
+    /// `x -- x = nil`
+    IsNil,
+    /// Replace `v` by `fallback` if it is nil. ( v fallback -- v )
+    Default,
+    /// Like `nth`, but push nil if the index is out of bounds.
+    /// ( n seq -- item/nil )
+    NthOrNil,
+    /// Raise an error with any value. ( x -- )
+    Throw,
+    /// Evaluate a procedure, and if it fails, restore the stack, push the
+    /// error and evaluate the handler. ( quot handler -- )
+    Try,
+    /// Fail with a message unless the flag is true. ( bool msg -- )
+    Assert,
+    /// Evaluate the body of the first clause whose predicate holds for `x`,
+    /// with `x` on the stack. A clause is either `[pred body]` or a lone
+    /// body which always matches. ( x clauses -- ... )
+    Cond,
+    /// Check if a string matches a regular expression.
+    /// ( s pattern -- bool )
+    #[cfg(feature = "regex")]
+    ReMatch,
+    /// All matches of a regular expression in a string.
+    /// ( s pattern -- list )
+    #[cfg(feature = "regex")]
+    ReFind,
+    /// Replace all matches of a regular expression in a string.
+    /// ( s pattern replacement -- s )
+    #[cfg(feature = "regex")]
+    ReReplace,
+    /// Run a shell command and push its output and exit status, which is nil
+    /// if it was killed by a signal. ( cmd -- stdout status )
+    #[cfg(feature = "sh")]
+    Sh,
+    /// Format seconds since the Unix epoch as UTC with a `strftime`-like
+    /// format. ( epoch fmt -- s )
+    #[cfg(feature = "chrono")]
+    FormatTime,
+    /// Parse a time, taken as UTC unless the format has an offset, into
+    /// seconds since the Unix epoch. ( s fmt -- epoch )
+    #[cfg(feature = "chrono")]
+    ParseTime,
+    /// Parse JSON. Objects become `[:object pairs]` with a list of
+    /// `[key value]` pairs. ( s -- x )
+    #[cfg(feature = "serde_json")]
+    JsonParse,
+    /// Encode a value as JSON. Structs and `[:object pairs]` with string
+    /// keys become objects. ( x -- s )
+    #[cfg(feature = "serde_json")]
+    JsonEncode,
+    /// Save all definitions and variables to an image file.
+    /// ( path -- )
+    #[cfg(feature = "image")]
+    SaveImage,
+    /// Add the definitions and variables in an image file, replacing words
+    /// of the same name. ( path -- )
+    #[cfg(feature = "image")]
+    LoadImage,
+    /// Parse comma-separated values into a list of rows of strings.
+    /// ( s -- rows )
+    #[cfg(feature = "csv")]
+    CsvParse,
+    /// Parse values separated by `sep`. With `headers`, the first line names
+    /// the fields and each row becomes a list of `[name value]` pairs.
+    /// ( s sep headers -- rows )
+    #[cfg(feature = "csv")]
+    CsvParseWith,
+    /// Encode a list of rows as comma-separated values. Rows of `[name
+    /// value]` pairs are preceded by a line of names.
+    /// ( rows -- s )
+    #[cfg(feature = "csv")]
+    CsvEncode,
+    /// Encode a list of rows as values separated by `sep`.
+    /// ( rows sep -- s )
+    #[cfg(feature = "csv")]
+    CsvEncodeWith,
+    /// Fetch a URL. Error statuses are pushed rather than raised.
+    /// ( url -- body status )
+    #[cfg(feature = "ureq")]
+    HttpGet,
+    /// Send a string to a URL.
+    /// ( url body -- body status )
+    #[cfg(feature = "ureq")]
+    HttpPost,
+    /// Open a TCP connection. ( host port -- conn )
+    #[cfg(feature = "tcp")]
+    TcpConnect,
+    /// Listen for TCP connections. ( host port -- listener )
+    #[cfg(feature = "tcp")]
+    TcpListen,
+    /// Wait for a connection to a listener. ( listener -- conn )
+    #[cfg(feature = "tcp")]
+    TcpAccept,
+    /// Send a string over a connection. ( s conn -- )
+    #[cfg(feature = "tcp")]
+    TcpSend,
+    /// Receive a line without its line ending, or nil once the connection is
+    /// closed. ( conn -- s/nil )
+    #[cfg(feature = "tcp")]
+    TcpRecv,
 }
 
 impl Builtin {
@@ -174,15 +773,276 @@ impl Builtin {
             Self::Drop3 => "3drop",
             Self::Over => "over",
             Self::Dupd => "dupd",
+            Self::Nip => "nip",
+            Self::Tuck => "tuck",
             Self::Rotl => "rotl",
             Self::Rotr => "rotr",
+            Self::Pick => "pick",
+            Self::Roll => "roll",
+            Self::Depth => "depth",
+            Self::Clear => "clear",
             Self::Keep => "keep",
+            Self::Bi => "bi",
+            Self::Tri => "tri",
+            Self::Cleave => "cleave",
             Self::Eval => "eval",
             Self::Println => "println",
+            Self::Print => "print",
+            Self::Eprintln => "eprintln",
+            Self::Eprint => "eprint",
+            Self::ReadLine => "read-line",
+            Self::ReadFile => "read-file",
+            Self::WriteFile => "write-file",
+            Self::AppendFile => "append-file",
+            Self::FileExists => "file-exists?",
+            Self::DeleteFile => "delete-file",
+            Self::ListDir => "list-dir",
+            Self::Mkdir => "mkdir",
+            Self::Env => "env",
+            Self::SetEnv => "set-env",
+            Self::Args => "args",
+            Self::Exit => "exit",
+            Self::Now => "now",
+            Self::Monotonic => "monotonic",
+            Self::Sleep => "sleep",
             Self::If => "if",
+            Self::When => "when",
+            Self::Unless => "unless",
             Self::Nth => "nth",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Abs => "abs",
+            Self::Map => "map",
+            Self::Filter => "filter",
+            Self::Fold => "fold",
+            Self::Each => "each",
+            Self::Curry => "curry",
+            Self::Compose => "compose",
+            Self::Times => "times",
+            Self::While => "while",
+            Self::Range => "range",
+            Self::RangeStep => "range-step",
+            Self::Len => "len",
+            Self::Append => "append",
+            Self::Head => "head",
+            Self::Tail => "tail",
+            Self::Uncons => "uncons",
+            Self::Cons => "cons",
+            Self::Push => "push",
+            Self::Sort => "sort",
+            Self::SortBy => "sort-by",
+            Self::Zip => "zip",
+            Self::Flatten => "flatten",
+            Self::Contains => "contains?",
+            Self::IndexOf => "index-of",
+            Self::Slice => "slice",
+            Self::SetNth => "set-nth",
+            Self::Split => "split",
+            Self::Join => "join",
+            Self::Upper => "upper",
+            Self::Lower => "lower",
+            Self::Substring => "substring",
+            Self::Trim => "trim",
+            Self::TrimStart => "trim-start",
+            Self::TrimEnd => "trim-end",
+            Self::StringToNumber => "string->number",
+            Self::ToString => "to-string",
+            Self::Format => "format",
+            Self::Ord => "ord",
+            Self::Chr => "chr",
+            Self::Mod => "mod",
+            Self::Pow => "pow",
+            Self::Sqrt => "sqrt",
+            Self::Floor => "floor",
+            Self::Ceil => "ceil",
+            Self::Round => "round",
+            Self::Trunc => "trunc",
+            Self::Sin => "sin",
+            Self::Cos => "cos",
+            Self::Tan => "tan",
+            Self::Atan2 => "atan2",
+            Self::Ln => "ln",
+            Self::Log10 => "log10",
+            Self::Exp => "exp",
+            Self::Pi => "pi",
+            Self::E => "e",
+            Self::And => "and",
+            Self::Or => "or",
+            Self::Xor => "xor",
+            Self::AndThen => "and-then",
+            Self::OrElse => "or-else",
+            Self::BitAnd => "band",
+            Self::BitOr => "bor",
+            Self::BitXor => "bxor",
+            Self::Shl => "shl",
+            Self::Shr => "shr",
+            Self::Set => "set",
+            Self::Get => "get",
+            Self::ListToSet => "list->set",
+            Self::SetToList => "set->list",
+            Self::SetAdd => "set-add",
+            Self::SetHas => "set-has?",
+            Self::QuotToList => "quot->list",
+            Self::ListToQuot => "list->quot",
+            Self::Spread => "spread",
+            Self::Apply => "apply",
+            Self::Union => "union",
+            Self::Intersect => "intersect",
+            Self::Difference => "difference",
+            Self::IsNil => "nil?",
+            Self::Default => "default",
+            Self::NthOrNil => "?nth",
+            Self::Throw => "throw",
+            Self::Try => "try",
+            Self::Assert => "assert",
+            Self::Cond => "cond",
+            #[cfg(feature = "regex")]
+            Self::ReMatch => "re-match?",
+            #[cfg(feature = "regex")]
+            Self::ReFind => "re-find",
+            #[cfg(feature = "regex")]
+            Self::ReReplace => "re-replace",
+            #[cfg(feature = "sh")]
+            Self::Sh => "sh",
+            #[cfg(feature = "chrono")]
+            Self::FormatTime => "format-time",
+            #[cfg(feature = "chrono")]
+            Self::ParseTime => "parse-time",
+            #[cfg(feature = "serde_json")]
+            Self::JsonParse => "json-parse",
+            #[cfg(feature = "serde_json")]
+            Self::JsonEncode => "json-encode",
+            #[cfg(feature = "image")]
+            Self::SaveImage => "save-image",
+            #[cfg(feature = "image")]
+            Self::LoadImage => "load-image",
+            #[cfg(feature = "csv")]
+            Self::CsvParse => "csv-parse",
+            #[cfg(feature = "csv")]
+            Self::CsvParseWith => "csv-parse-with",
+            #[cfg(feature = "csv")]
+            Self::CsvEncode => "csv-encode",
+            #[cfg(feature = "csv")]
+            Self::CsvEncodeWith => "csv-encode-with",
+            #[cfg(feature = "ureq")]
+            Self::HttpGet => "http-get",
+            #[cfg(feature = "ureq")]
+            Self::HttpPost => "http-post",
+            #[cfg(feature = "tcp")]
+            Self::TcpConnect => "tcp-connect",
+            #[cfg(feature = "tcp")]
+            Self::TcpListen => "tcp-listen",
+            #[cfg(feature = "tcp")]
+            Self::TcpAccept => "tcp-accept",
+            #[cfg(feature = "tcp")]
+            Self::TcpSend => "tcp-send",
+            #[cfg(feature = "tcp")]
+            Self::TcpRecv => "tcp-recv",
         }
     }
+
+    /// The number of values this builtin pops and pushes, or `None` if it
+    /// depends on the procedures it evaluates.
+    pub fn stack_effect(&self) -> Option<(usize, usize)> {
+        match self {
+            Self::Add | Self::Sub | Self::Mul | Self::Div | Self::Eq
+                | Self::Lt | Self::Le | Self::Gt | Self::Ge | Self::Nth
+                | Self::Min | Self::Max | Self::Mod | Self::Pow | Self::Atan2
+                | Self::Map | Self::Filter | Self::Curry | Self::Compose
+                | Self::Range | Self::Append | Self::Cons | Self::Push
+                | Self::SortBy | Self::Zip | Self::Contains | Self::IndexOf
+                | Self::Split | Self::Join | Self::Format | Self::And | Self::Or
+                | Self::Xor | Self::AndThen | Self::OrElse | Self::BitAnd
+                | Self::BitOr | Self::BitXor | Self::Shl | Self::Shr
+                | Self::SetAdd | Self::SetHas | Self::Union | Self::Intersect
+                | Self::Difference | Self::Default | Self::NthOrNil =>
+                Some((2, 1)),
+            Self::Neg | Self::Abs | Self::Len | Self::Head | Self::Tail
+                | Self::Sort | Self::Flatten | Self::Upper | Self::Lower
+                | Self::Trim | Self::TrimStart | Self::TrimEnd
+                | Self::StringToNumber | Self::ToString | Self::Ord
+                | Self::Chr | Self::Sqrt | Self::Floor | Self::Ceil
+                | Self::Round | Self::Trunc | Self::Sin | Self::Cos
+                | Self::Tan | Self::Ln | Self::Log10 | Self::Exp | Self::Get
+                | Self::ListToSet | Self::SetToList | Self::IsNil
+                | Self::QuotToList | Self::ListToQuot | Self::ReadFile
+                | Self::FileExists | Self::ListDir | Self::Env =>
+                Some((1, 1)),
+            Self::Pi | Self::E | Self::Depth | Self::ReadLine
+                | Self::Args | Self::Now | Self::Monotonic =>
+                Some((0, 1)),
+            Self::Dup | Self::Uncons => Some((1, 2)),
+            Self::Dup2 => Some((2, 4)),
+            Self::Swap => Some((2, 2)),
+            Self::Drop | Self::Println | Self::Print | Self::Eprintln
+                | Self::Eprint | Self::Throw | Self::DeleteFile
+                | Self::Mkdir | Self::Exit | Self::Sleep => Some((1, 0)),
+            Self::Drop2 | Self::Set | Self::Assert | Self::WriteFile
+                | Self::AppendFile | Self::SetEnv => Some((2, 0)),
+            Self::Drop3 => Some((3, 0)),
+            Self::Over | Self::Dupd | Self::Tuck => Some((2, 3)),
+            Self::Nip => Some((2, 1)),
+            Self::Rotl | Self::Rotr => Some((3, 3)),
+            Self::Fold | Self::RangeStep | Self::Slice | Self::SetNth
+                | Self::Substring => Some((3, 1)),
+            #[cfg(feature = "regex")]
+            Self::ReMatch | Self::ReFind => Some((2, 1)),
+            #[cfg(feature = "regex")]
+            Self::ReReplace => Some((3, 1)),
+            #[cfg(feature = "sh")]
+            Self::Sh => Some((1, 2)),
+            #[cfg(feature = "chrono")]
+            Self::FormatTime | Self::ParseTime => Some((2, 1)),
+            #[cfg(feature = "serde_json")]
+            Self::JsonParse | Self::JsonEncode => Some((1, 1)),
+            #[cfg(feature = "image")]
+            Self::SaveImage | Self::LoadImage => Some((1, 0)),
+            #[cfg(feature = "csv")]
+            Self::CsvParse | Self::CsvEncode => Some((1, 1)),
+            #[cfg(feature = "csv")]
+            Self::CsvParseWith => Some((3, 1)),
+            #[cfg(feature = "csv")]
+            Self::CsvEncodeWith => Some((2, 1)),
+            #[cfg(feature = "ureq")]
+            Self::HttpGet => Some((1, 2)),
+            #[cfg(feature = "ureq")]
+            Self::HttpPost => Some((2, 2)),
+            #[cfg(feature = "tcp")]
+            Self::TcpConnect | Self::TcpListen => Some((2, 1)),
+            #[cfg(feature = "tcp")]
+            Self::TcpAccept | Self::TcpRecv => Some((1, 1)),
+            #[cfg(feature = "tcp")]
+            Self::TcpSend => Some((2, 0)),
+            Self::Keep | Self::Bi | Self::Tri | Self::Cleave | Self::Eval
+                | Self::If | Self::When | Self::Unless | Self::Pick
+                | Self::Roll | Self::Clear | Self::Try | Self::Cond
+                | Self::Spread | Self::Apply | Self::Each | Self::Times
+                | Self::While => None,
+        }
+    }
+
+    /// Whether the builtin only computes its outputs from its inputs, so it
+    /// can be evaluated ahead of time when they are constants.
+    pub fn is_pure(&self) -> bool {
+        matches!(
+            self,
+            Self::Add | Self::Sub | Self::Mul | Self::Div | Self::Mod
+                | Self::Pow | Self::Min | Self::Max | Self::Abs | Self::Neg
+                | Self::Eq | Self::Lt | Self::Le | Self::Gt | Self::Ge
+                | Self::And | Self::Or | Self::Xor | Self::BitAnd
+                | Self::BitOr | Self::BitXor | Self::Shl | Self::Shr
+                | Self::Sqrt | Self::Floor | Self::Ceil | Self::Round
+                | Self::Trunc | Self::Sin | Self::Cos | Self::Tan | Self::Ln
+                | Self::Log10 | Self::Exp | Self::Atan2 | Self::Pi | Self::E
+                | Self::IsNil | Self::Len | Self::Upper
+                | Self::Lower | Self::Trim | Self::TrimStart | Self::TrimEnd
+                | Self::StringToNumber | Self::ToString | Self::Ord
+                | Self::Chr | Self::Dup | Self::Dup2 | Self::Drop
+                | Self::Drop2 | Self::Drop3 | Self::Swap | Self::Over
+                | Self::Nip | Self::Tuck | Self::Dupd | Self::Rotl
+                | Self::Rotr
+        )
+    }
 }
 
 impl Display for Builtin {
@@ -0,0 +1,276 @@
+use std::{fmt::Display, rc::Rc};
+
+use crate::{
+    interpreter::evaluate_expression, Builtin, Span, Statement, Symbol, Value,
+};
+
+/// An instruction of compiled code.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    /// Pushes a value.
+    Push(Value),
+    /// Calls a word, resolved when it is called.
+    Call(Symbol, Span),
+    /// Runs a builtin.
+    Prim(Builtin, Span),
+    /// Continues at `target`.
+    Jump(usize),
+    /// Pops a flag and continues at `target` unless it equals `on`. This is
+    /// what `builtin`, one of `if`, `when` and `unless`, compiles to when its
+    /// procedures are literals.
+    JumpUnless {
+        on: bool,
+        target: usize,
+        builtin: Builtin,
+        span: Span,
+    },
+    /// Runs a statement without an instruction of its own, like a definition
+    /// or an import.
+    Statement(Statement),
+    /// Does nothing, but marks the next `len` instructions as the inlined
+    /// body of `word` for traces.
+    Inlined { word: Symbol, len: usize },
+}
+
+impl Op {
+    /// Where the instruction was written, if it's known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::Call(_, span)
+                | Self::Prim(_, span)
+                | Self::JumpUnless { span, .. } if span.line > 0 => Some(*span),
+            Self::Statement(s) => s.span(),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Push(v) => write!(f, "push {v}"),
+            Self::Call(w, _) => write!(f, "call {}", w.as_str()),
+            Self::Prim(b, _) => write!(f, "prim {b}"),
+            Self::Jump(target) => write!(f, "jump {target}"),
+            Self::JumpUnless { on, target, .. } =>
+                write!(f, "jump-unless {on} {target}"),
+            Self::Statement(s) => write!(f, "{s}"),
+            Self::Inlined { word, len } =>
+                write!(f, "inlined {} {len}", word.as_str()),
+        }
+    }
+}
+
+/// Compiles statements into a flat sequence of instructions.
+pub fn compile(statements: &[Statement]) -> Rc<[Op]> {
+    compile_with(statements, &mut |_| None)
+}
+
+/// Like [`compile`], but replaces calls to the words for which `inline`
+/// returns code by that code.
+pub fn compile_with(
+    statements: &[Statement],
+    inline: &mut dyn FnMut(Symbol) -> Option<Rc<[Op]>>,
+) -> Rc<[Op]> {
+    let mut compiler = Compiler { ops: vec![], label: 0, inline };
+
+    compiler.statements(statements);
+    compiler.ops.into()
+}
+
+struct Compiler<'a> {
+    ops: Vec<Op>,
+    /// Instructions before this may be jumped past, so they are never
+    /// rewritten.
+    label: usize,
+    inline: &'a mut dyn FnMut(Symbol) -> Option<Rc<[Op]>>,
+}
+
+impl Compiler<'_> {
+    fn statements(&mut self, statements: &[Statement]) {
+        statements.iter().for_each(|s| self.statement(s));
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let op = match statement {
+            Statement::Expression(e) => Op::Push(evaluate_expression(e)),
+            Statement::Value(v) => Op::Push(v.clone()),
+            Statement::Word(w, span) => match (self.inline)(*w) {
+                Some(code) => return self.splice(&code),
+                None => Op::Call(*w, *span),
+            },
+            Statement::Builtin(b @ Builtin::If, span) =>
+                match self.literals(2).as_deref() {
+                    Some([then, esle]) =>
+                        return self.branch(*b, *span, then, Some(esle)),
+                    _ => Op::Prim(*b, *span),
+                },
+            Statement::Builtin(b @ (Builtin::When | Builtin::Unless), span) =>
+                match self.literals(1).as_deref() {
+                    Some([body]) => return self.branch(*b, *span, body, None),
+                    _ => Op::Prim(*b, *span),
+                },
+            Statement::Builtin(b, span) => Op::Prim(*b, *span),
+            s => Op::Statement(s.clone()),
+        };
+
+        self.ops.push(op);
+    }
+
+    /// Takes the last `n` instructions if they push procedures, which nothing
+    /// jumps between.
+    fn literals(&mut self, n: usize) -> Option<Vec<Rc<[Statement]>>> {
+        let start = self.ops.len().checked_sub(n)
+            .filter(|&start| start >= self.label)?;
+        let procedures = self.ops[start..].iter()
+            .map(|op| match op {
+                Op::Push(Value::Procedure(p)) => Some(p.0.clone()),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+
+        self.ops.truncate(start);
+
+        Some(procedures)
+    }
+
+    /// Compiles `builtin` with its procedures inlined, `then` running if the
+    /// flag matches and `esle` otherwise.
+    fn branch(
+        &mut self,
+        builtin: Builtin,
+        span: Span,
+        then: &[Statement],
+        esle: Option<&[Statement]>,
+    ) {
+        let jump = self.ops.len();
+
+        self.ops.push(Op::JumpUnless {
+            on: builtin != Builtin::Unless,
+            target: 0,
+            builtin,
+            span,
+        });
+        self.statements(then);
+
+        if let Some(esle) = esle {
+            let skip = self.ops.len();
+
+            self.ops.push(Op::Jump(0));
+            self.patch(jump);
+            self.statements(esle);
+            self.patch(skip);
+        } else {
+            self.patch(jump);
+        }
+    }
+
+    /// Appends compiled code, moving its jumps along with it.
+    fn splice(&mut self, code: &[Op]) {
+        let offset = self.ops.len();
+
+        self.ops.extend(code.iter().map(|op| match op {
+            Op::Jump(target) => Op::Jump(target + offset),
+            Op::JumpUnless { on, target, builtin, span } => Op::JumpUnless {
+                on: *on,
+                target: target + offset,
+                builtin: *builtin,
+                span: *span,
+            },
+            op => op.clone(),
+        }));
+
+        self.label = self.ops.len();
+    }
+
+    /// Points the jump at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let next = self.ops.len();
+
+        match &mut self.ops[at] {
+            Op::Jump(target) | Op::JumpUnless { target, .. } => *target = next,
+            op => unreachable!("{op} isn't a jump"),
+        }
+
+        self.label = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compile, compile_with, program, Builtin, Op, Span, Symbol, Value,
+    };
+
+    fn ops(input: &str) -> Vec<Op> {
+        compile(&program(input).unwrap().1.statements).to_vec()
+    }
+
+    #[test]
+    fn straight_line() {
+        assert_eq!(
+            ops("1 2 + f"),
+            vec![
+                Op::Push(Value::Int(1)),
+                Op::Push(Value::Int(2)),
+                Op::Prim(Builtin::Add, Span::default()),
+                Op::Call(Symbol::new("f"), Span::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn branches() {
+        assert_eq!(
+            ops("c { 1 } { 2 } if"),
+            vec![
+                Op::Call(Symbol::new("c"), Span::default()),
+                Op::JumpUnless {
+                    on: true,
+                    target: 4,
+                    builtin: Builtin::If,
+                    span: Span::default(),
+                },
+                Op::Push(Value::Int(1)),
+                Op::Jump(5),
+                Op::Push(Value::Int(2)),
+            ]
+        );
+        assert_eq!(
+            ops("c { 1 } unless"),
+            vec![
+                Op::Call(Symbol::new("c"), Span::default()),
+                Op::JumpUnless {
+                    on: false,
+                    target: 3,
+                    builtin: Builtin::Unless,
+                    span: Span::default(),
+                },
+                Op::Push(Value::Int(1)),
+            ]
+        );
+        // The body of `when` ends in a procedure, which the jump past it
+        // lands after, so it isn't taken for a branch of `if`.
+        assert!(matches!(
+            ops("{ 1 } c { { 2 } } when { 3 } if")[..],
+            [.., Op::Prim(Builtin::If, _)]
+        ));
+        assert!(matches!(
+            ops("p { 1 } if")[..],
+            [.., Op::Prim(Builtin::If, _)]
+        ));
+    }
+
+    #[test]
+    fn inlining() {
+        let f = ops("c { 1 } when");
+        let code = compile_with(
+            &program("f f").unwrap().1.statements,
+            &mut |w| (w == Symbol::new("f")).then(|| f.clone().into()),
+        );
+
+        assert_eq!(code.len(), 6);
+        assert!(matches!(code[1], Op::JumpUnless { target: 3, .. }));
+        assert!(matches!(code[4], Op::JumpUnless { target: 6, .. }));
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+
+use crate::{Builtin, Expression, Procedure, Statement, Symbol, Value};
+
+type Result<A> = std::result::Result<A, String>;
+
+/// Statically models the stack effect of a procedure.
+///
+/// Returns the minimum stack depth the procedure requires and the net change
+/// in stack depth after running it. Words are treated as unknown and cause an
+/// error; use [`check_stack_effect_with`] to resolve them.
+pub fn check_stack_effect(procedure: &Procedure) -> Result<(i32, i32)> {
+    check_stack_effect_with(procedure, &HashMap::new())
+}
+
+/// Like [`check_stack_effect`], but resolves words against `definitions`.
+pub fn check_stack_effect_with(
+    procedure: &Procedure,
+    definitions: &HashMap<Symbol, Procedure>,
+) -> Result<(i32, i32)> {
+    let mut checker = Checker::new(definitions);
+
+    checker.statements(&procedure.0)?;
+
+    Ok(checker.effect())
+}
+
+/// An abstract stack which only remembers the procedures pushed onto it, so
+/// `eval`, `if` and `keep` can be modeled when their operands are literals.
+struct Checker<'a> {
+    definitions: &'a HashMap<Symbol, Procedure>,
+    /// Words currently being expanded, to detect recursion.
+    visiting: Vec<Symbol>,
+    /// Names of the locals in scope, whose values are never known.
+    locals: Vec<String>,
+    stack: Vec<Option<Procedure>>,
+    /// Number of values taken from below the procedure's own stack.
+    required: usize,
+}
+
+impl<'a> Checker<'a> {
+    fn new(definitions: &'a HashMap<Symbol, Procedure>) -> Self {
+        Self {
+            definitions,
+            visiting: vec![],
+            locals: vec![],
+            stack: vec![],
+            required: 0,
+        }
+    }
+
+    fn effect(&self) -> (i32, i32) {
+        let required = self.required as i32;
+
+        (required, self.stack.len() as i32 - required)
+    }
+
+    fn statements(&mut self, statements: &[Statement]) -> Result<()> {
+        statements.iter().try_for_each(|s| self.statement(s))
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Expression(Expression::Procedure(p))
+                | Statement::Value(Value::Procedure(p)) =>
+                self.stack.push(Some(p.clone())),
+            Statement::Expression(_) | Statement::Value(_) =>
+                self.stack.push(None),
+            Statement::Definition { .. }
+                | Statement::Struct { .. }
+                | Statement::Var(_)
+                | Statement::Vocab(_) => {}
+            Statement::Builtin(b, _) => return self.builtin(*b),
+            Statement::Word(w, _) => return self.word(*w),
+            Statement::Construct { fields, .. } => self.apply(fields.len(), 1),
+            Statement::Field { .. } => self.apply(1, 1),
+            Statement::Import(_) =>
+                return Err("Can't model the stack effect of `import`".into()),
+            Statement::Recurse(_) => return Err(
+                "Can't model the stack effect of `recurse`".into()
+            ),
+            Statement::Locals { names, body } => {
+                let scope = self.locals.len();
+
+                self.apply(names.len(), 0);
+                self.locals.extend(names.iter().cloned());
+                self.statements(&body.0)?;
+                self.locals.truncate(scope);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn word(&mut self, word: Symbol) -> Result<()> {
+        let name = word.as_str();
+
+        if self.locals.iter().any(|l| l == name) {
+            self.stack.push(None);
+
+            return Ok(());
+        }
+
+        let Some(procedure) = self.definitions.get(&word) else {
+            return Err(format!("Couldn't resolve identifier {name:?}"));
+        };
+
+        if self.visiting.contains(&word) {
+            return Err(format!("Can't model the stack effect of recursive \
+                                word `{name}`"));
+        }
+
+        self.visiting.push(word);
+        self.statements(&procedure.0)?;
+        self.visiting.pop();
+
+        Ok(())
+    }
+
+    fn builtin(&mut self, builtin: Builtin) -> Result<()> {
+        if let Some((inputs, outputs)) = builtin.stack_effect() {
+            return match builtin {
+                Builtin::Dup => {
+                    let a = self.pop();
+
+                    self.stack.extend([a.clone(), a]);
+
+                    Ok(())
+                }
+                _ => {
+                    self.apply(inputs, outputs);
+
+                    Ok(())
+                }
+            };
+        }
+
+        match builtin {
+            Builtin::Eval => {
+                let p = self.pop_procedure(builtin)?;
+
+                self.statements(&p.0)
+            }
+            Builtin::Keep => {
+                let p = self.pop_procedure(builtin)?;
+                let a = self.pop();
+
+                self.stack.push(a.clone());
+                self.statements(&p.0)?;
+                self.stack.push(a);
+
+                Ok(())
+            }
+            Builtin::Bi | Builtin::Tri => {
+                let n = if builtin == Builtin::Bi { 2 } else { 3 };
+                let mut procedures = (0..n)
+                    .map(|_| self.pop_procedure(builtin))
+                    .collect::<Result<Vec<_>>>()?;
+                let x = self.pop();
+
+                procedures.reverse();
+
+                for p in procedures {
+                    self.stack.push(x.clone());
+                    self.statements(&p.0)?;
+                }
+
+                Ok(())
+            }
+            Builtin::If | Builtin::When | Builtin::Unless => {
+                let esle = match builtin {
+                    Builtin::If => self.pop_procedure(builtin)?,
+                    _ => Procedure([].into()),
+                };
+                let then = self.pop_procedure(builtin)?;
+
+                self.pop();
+
+                let (then_required, then_net) = self.branch(&then)?;
+                let (esle_required, esle_net) = self.branch(&esle)?;
+
+                if then_net != esle_net {
+                    return Err(format!(
+                        "Branches of `{builtin}` have different stack effects \
+                         ({then_net:+} and {esle_net:+})"
+                    ));
+                }
+
+                let required = then_required.max(esle_required) as usize;
+
+                self.apply(required, (required as i32 + then_net) as usize);
+
+                Ok(())
+            }
+            Builtin::Each => {
+                let body = self.pop_procedure(builtin)?;
+
+                self.pop();
+
+                let required = self.repeat(builtin, &body, 1)?;
+
+                self.apply(required, required);
+
+                Ok(())
+            }
+            Builtin::Times => {
+                let body = self.pop_procedure(builtin)?;
+
+                self.pop();
+
+                let required = self.repeat(builtin, &body, 0)?;
+
+                self.apply(required, required);
+
+                Ok(())
+            }
+            Builtin::While => {
+                let body = self.pop_procedure(builtin)?;
+                let cond = self.pop_procedure(builtin)?;
+                let (cond_required, cond_net) = self.branch(&cond)?;
+
+                if cond_net != 1 {
+                    return Err(format!(
+                        "Condition of `while` must push one value, changed \
+                         the stack by {cond_net:+}"
+                    ));
+                }
+
+                let required = (cond_required as usize)
+                    .max(self.repeat(builtin, &body, 0)?);
+
+                self.apply(required, required);
+
+                Ok(())
+            }
+            Builtin::Cleave | Builtin::Pick | Builtin::Roll | Builtin::Clear
+                | Builtin::Try | Builtin::Cond | Builtin::Spread
+                | Builtin::Apply =>
+                Err(format!("Can't model the stack effect of `{builtin}`")),
+            b => unreachable!("`{b}` has a static stack effect"),
+        }
+    }
+
+    /// Models `procedure` on its own, sharing the definitions and recursion
+    /// state with this checker.
+    fn branch(&mut self, procedure: &Procedure) -> Result<(i32, i32)> {
+        let mut checker = Checker {
+            definitions: self.definitions,
+            visiting: self.visiting.clone(),
+            locals: self.locals.clone(),
+            stack: vec![],
+            required: 0,
+        };
+
+        checker.statements(&procedure.0)?;
+
+        Ok(checker.effect())
+    }
+
+    /// Models `body` run any number of times by `builtin`, with `given`
+    /// values pushed for each run, and returns how many values it needs from
+    /// the stack below them. Each run has to consume what it is given and
+    /// otherwise leave the stack as deep as it found it.
+    fn repeat(&mut self, builtin: Builtin, body: &Procedure, given: i32)
+    -> Result<usize> {
+        let (required, net) = self.branch(body)?;
+
+        if net != -given {
+            return Err(format!(
+                "Body of `{builtin}` must leave the stack as deep as it found \
+                 it, changed it by {:+}",
+                net + given
+            ));
+        }
+
+        Ok((required - given).max(0) as usize)
+    }
+
+    fn apply(&mut self, inputs: usize, outputs: usize) {
+        for _ in 0..inputs {
+            self.pop();
+        }
+
+        self.stack.extend(std::iter::repeat_n(None, outputs));
+    }
+
+    fn pop(&mut self) -> Option<Procedure> {
+        match self.stack.pop() {
+            Some(p) => p,
+            None => {
+                self.required += 1;
+                None
+            }
+        }
+    }
+
+    fn pop_procedure(&mut self, builtin: Builtin) -> Result<Procedure> {
+        self.pop().ok_or_else(|| format!(
+            "Can't model the stack effect of `{builtin}` on a procedure that \
+             isn't known statically"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        check_stack_effect, check_stack_effect_with, procedure, Procedure,
+        Symbol,
+    };
+
+    fn parse(input: &str) -> Procedure {
+        procedure(input).expect("Couldn't parse procedure").1
+    }
+
+    #[test]
+    fn balanced() {
+        assert_eq!(check_stack_effect(&parse("{ dup * }")), Ok((1, 0)));
+        assert_eq!(check_stack_effect(&parse("{ 1 swap drop }")), Ok((1, 0)));
+    }
+
+    #[test]
+    fn underflow() {
+        assert_eq!(check_stack_effect(&parse("{ + + }")), Ok((3, -2)));
+        assert_eq!(check_stack_effect(&parse("{ 1 + }")), Ok((1, 0)));
+        assert_eq!(check_stack_effect(&parse("{ drop 1 + }")), Ok((2, -1)));
+    }
+
+    #[test]
+    fn net_positive() {
+        assert_eq!(check_stack_effect(&parse("{ 1 2 }")), Ok((0, 2)));
+        assert_eq!(check_stack_effect(&parse("{ 2dup }")), Ok((2, 2)));
+    }
+
+    #[test]
+    fn procedures() {
+        assert_eq!(check_stack_effect(&parse("{ { 1 + } eval }")), Ok((1, 0)));
+        assert_eq!(
+            check_stack_effect(&parse("{ dup 1 > { 1 - } { 1 + } if }")),
+            Ok((1, 0))
+        );
+        assert!(check_stack_effect(&parse("{ true { 1 } { } if }")).is_err());
+        assert!(check_stack_effect(&parse("{ eval }")).is_err());
+        assert_eq!(
+            check_stack_effect(&parse("{ dup 0 < { 0 swap - } when }")),
+            Ok((1, 0))
+        );
+        assert!(check_stack_effect(&parse("{ dup { 1 } unless }")).is_err());
+        assert_eq!(
+            check_stack_effect(&parse("{ { 1 + } { 2 * } bi }")),
+            Ok((1, 1))
+        );
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(
+            check_stack_effect(&parse("{ 0 swap { + } each }")),
+            Ok((1, 0))
+        );
+        assert_eq!(
+            check_stack_effect(&parse("{ { drop } each }")),
+            Ok((1, -1))
+        );
+        assert!(check_stack_effect(&parse("{ { dup } each }")).is_err());
+        assert!(check_stack_effect(&parse("{ each }")).is_err());
+        assert_eq!(
+            check_stack_effect(&parse("{ 3 { 2 * } times }")),
+            Ok((1, 0))
+        );
+        assert!(check_stack_effect(&parse("{ 3 { 1 } times }")).is_err());
+        assert_eq!(
+            check_stack_effect(&parse("{ { dup 0 > } { 1 - } while }")),
+            Ok((1, 0))
+        );
+        assert!(check_stack_effect(&parse("{ { true } { 1 } while }"))
+                .is_err());
+        assert!(check_stack_effect(&parse("{ { } { } while }")).is_err());
+    }
+
+    #[test]
+    fn locals() {
+        assert_eq!(
+            check_stack_effect(&parse("{ {| a b | a b a } eval }")),
+            Ok((2, 1))
+        );
+        assert_eq!(
+            check_stack_effect(&parse("{ {| f | 1 f eval } eval }")),
+            Err("Can't model the stack effect of `eval` on a procedure that \
+                 isn't known statically".into())
+        );
+    }
+
+    #[test]
+    fn words() {
+        let definitions =
+            HashMap::from([(Symbol::new("inc"), parse("{ 1 + }"))]);
+
+        assert!(check_stack_effect(&parse("{ inc }")).is_err());
+        assert_eq!(
+            check_stack_effect_with(&parse("{ inc inc }"), &definitions),
+            Ok((1, 0))
+        );
+
+        let definitions =
+            HashMap::from([(Symbol::new("loop"), parse("{ loop }"))]);
+
+        assert!(check_stack_effect_with(&parse("{ loop }"), &definitions)
+                .is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use thiserror::Error;
+
+use crate::Span;
+
+/// An error raised while running a program.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum InterpreterError {
+    /// An operation needed more values than were on the stack.
+    #[error("Operation `{word}` expected {expected} argument(s), got {got}")]
+    StackUnderflow {
+        word: String,
+        expected: usize,
+        got: usize,
+    },
+    /// An operation was applied to a value of the wrong type.
+    #[error("{0}")]
+    TypeError(String),
+    /// A word wasn't defined.
+    #[error("Couldn't resolve identifier {0:?}")]
+    UnresolvedWord(String),
+    /// An index was past either end of a sequence.
+    #[error("Index {index} out of bounds for length {len}")]
+    IndexOutOfBounds { index: i64, len: usize },
+    /// A value was thrown with `throw` and not caught. Holds the value as an
+    /// unquoted string.
+    #[error("{0}")]
+    Thrown(String),
+    /// The program called `exit` with this status.
+    #[error("Exited with status {0}")]
+    Exit(i32),
+    /// The program ran more steps than the limit set with
+    /// [`Interpreter::with_step_limit`](crate::Interpreter::with_step_limit).
+    #[error("Exceeded the limit of {0} steps")]
+    StepLimit(u64),
+    /// The program used more of `resource` than the interpreter allows, like
+    /// values on the stack.
+    #[error("Exceeded the limit of {limit} {resource}")]
+    ResourceExhausted { resource: &'static str, limit: usize },
+    /// The program was stopped through the flag set with
+    /// [`Interpreter::with_interrupt`](crate::Interpreter::with_interrupt).
+    #[error("Interrupted")]
+    Interrupted,
+    /// Any other failure, such as I/O errors.
+    #[error("{0}")]
+    Other(String),
+    /// An error raised by the statement at `span`, while calling the words
+    /// in `trace` from the outermost in.
+    #[error("{error}{}", location(.span, .trace))]
+    At {
+        span: Option<Span>,
+        trace: Box<[String]>,
+        error: Box<InterpreterError>,
+    },
+}
+
+fn location(span: &Option<Span>, trace: &[String]) -> String {
+    let mut s = String::new();
+
+    if let Some(span) = span {
+        s += &format!(" at {span}");
+    }
+
+    if !trace.is_empty() {
+        s += &format!(" (in {})", trace.join(" → in "));
+    }
+
+    s
+}
+
+impl InterpreterError {
+    /// The error without its location.
+    pub fn kind(&self) -> &Self {
+        match self {
+            Self::At { error, .. } => error.kind(),
+            e => e,
+        }
+    }
+
+    /// Whether `try` can handle the error. Exiting, exceeding limits and
+    /// interrupts always end the program.
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self.kind(),
+            Self::Exit(_)
+                | Self::StepLimit(_)
+                | Self::ResourceExhausted { .. }
+                | Self::Interrupted
+        )
+    }
+
+    /// Locates the error at `span` inside the calls in `trace`, unless it
+    /// already has a location or ends the program through `exit`.
+    pub(crate) fn at(self, span: Option<Span>, trace: &[String]) -> Self {
+        match self {
+            e @ (Self::At { .. } | Self::Exit(_)) => e,
+            e if span.is_none() && trace.is_empty() => e,
+            e => Self::At {
+                span,
+                trace: trace.into(),
+                error: Box::new(e),
+            },
+        }
+    }
+}
+
+impl From<String> for InterpreterError {
+    fn from(message: String) -> Self {
+        Self::Other(message)
+    }
+}
+
+impl From<&str> for InterpreterError {
+    fn from(message: &str) -> Self {
+        Self::Other(message.into())
+    }
+}
@@ -1,394 +1,5509 @@
-use std::collections::{HashMap, VecDeque};
+use std::{
+    cell::OnceCell,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fs,
+    io::{stdin, stdout, BufRead, Write},
+    ops::{BitAnd, BitOr, BitXor, Shl, Shr},
+    path::{Path, PathBuf},
+    rc::{Rc, Weak},
+    sync::{atomic::{AtomicBool, Ordering as AtomicOrdering}, Arc},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use crate::{Builtin, Expression, Procedure, Program, Statement, Value};
+use nom::error::convert_error;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-type Result<A> = std::result::Result<A, String>;
+#[cfg(feature = "tcp")]
+use crate::Socket;
+use crate::{
+    builtin, compile, compile_with, optimize::recurses, parser::KEYWORDS,
+    program, Builtin, Expression, InterpreterError, Op, Procedure, Program,
+    Recursion, Span, StackEffect, Statement, Symbol, Value,
+};
+
+type Result<A> = std::result::Result<A, InterpreterError>;
+
+/// The most statements a word may have to be inlined.
+pub const INLINE_LIMIT: usize = 8;
+
+/// The most elements `range` and `range-step` may make.
+pub const RANGE_LIMIT: usize = 1 << 24;
+
+/// The most values the stack may hold unless
+/// [`Interpreter::with_stack_limit`] sets another limit.
+pub const STACK_LIMIT: usize = 1 << 22;
+
+/// The most words and procedures that may run at once unless
+/// [`Interpreter::with_frame_limit`] sets another limit.
+pub const FRAME_LIMIT: usize = 1 << 20;
+
+/// The most procedures that builtins like `map` or `filter` may call inside
+/// each other. These calls nest on the native stack, so unlike frames they
+/// can't grow much deeper.
+pub const NESTING_LIMIT: usize = 1 << 7;
 
 pub struct Interpreter {
     pub stack: Vec<Value>,
-    pub statements: VecDeque<Statement>,
-    pub definitions: HashMap<String, Procedure>,
+    pub definitions: HashMap<Symbol, Procedure>,
+    /// Declared stack effects of definitions, checked around each call.
+    pub effects: HashMap<Symbol, StackEffect>,
+    /// Values of the variables declared with `var`.
+    pub variables: HashMap<String, Value>,
     pub verbose: bool,
+    /// Directories searched for imported files, after the directory of the
+    /// importing file.
+    pub search_path: Vec<PathBuf>,
+    /// Files currently being imported, to detect cycles.
+    importing: Vec<PathBuf>,
+    /// Files which have been imported already and are skipped.
+    imported: HashSet<PathBuf>,
+    /// The vocabulary new definitions of the current file are put into.
+    vocab: Option<String>,
+    /// The value passed to the last `throw`, handed to `try` handlers.
+    thrown: Option<Value>,
+    /// Where `read-line` reads from, stdin if unset.
+    input: Option<Box<dyn BufRead>>,
+    /// The command-line arguments pushed by `args`.
+    args: Vec<String>,
+    /// Whether words and programs are compiled to bytecode.
+    bytecode: bool,
+    /// Whether programs are optimized before they run.
+    optimize: bool,
+    /// Whether small words are inlined into the words calling them.
+    inline: bool,
+    /// The most steps `run_program` may run.
+    step_limit: Option<u64>,
+    /// The steps run since `run_program` was called.
+    steps: u64,
+    /// The most values the stack may hold.
+    stack_limit: Option<usize>,
+    /// The most frames that may run at once.
+    frame_limit: Option<usize>,
+    /// Set from outside, like a signal handler, to stop the running program.
+    interrupt: Option<Arc<AtomicBool>>,
+    /// The compiled bodies of words which have been called.
+    compiled: HashMap<Symbol, Rc<[Op]>>,
+    /// The names words called in the current vocabulary are looked up under
+    /// before their own, so they are only built once.
+    qualified: HashMap<Symbol, Names>,
+    /// The code being run, innermost last.
+    frames: Vec<Frame>,
+    /// The number of frames belonging to outer calls of `call`, which are
+    /// left alone until it returns.
+    base: usize,
+    /// The number of calls of `call` running inside each other.
+    nesting: usize,
+    /// When the interpreter was created, for `monotonic`.
+    started: Instant,
 }
 
-impl Interpreter {
-    #[allow(clippy::new_without_default)]
-    pub fn new(verbose: bool) -> Self {
-        Self {
-            stack: vec![],
-            statements: VecDeque::new(),
-            definitions: HashMap::new(),
-            verbose,
+/// Code being run, like the body of a called word.
+struct Frame {
+    code: Code,
+    /// The index of the next statement or instruction to run.
+    pc: usize,
+    /// The word this frame runs, for traces.
+    word: Option<Symbol>,
+    /// The depth the word's declared stack effect requires on return.
+    depth: Option<usize>,
+}
+
+impl Frame {
+    /// Whether nothing but jumps to the end is left to run.
+    fn finished(&self) -> bool {
+        match &self.code {
+            Code::Statements(s) => self.pc >= s.len(),
+            Code::Ops(ops) => runs_out(ops, self.pc, ops.len()),
         }
     }
 
-    pub fn run_program(&mut self, program: Program)
-    -> Result<Option<Value>> {
-        self.statements.append(&mut program.statements.to_vec().into());
-
-        self.run_statements()?;
-
-        Ok(self.stack.last().cloned())
-    }
+    /// The words this frame runs from the outermost in: its own and those
+    /// inlined around the running instruction. Like a call, a word inlined
+    /// at the end of another replaces it.
+    fn words(&self) -> Vec<Symbol> {
+        let mut words = Vec::from_iter(self.word);
+        // The counter has already moved past the running instruction.
+        let pc = self.pc.saturating_sub(1);
+        let Code::Ops(ops) = &self.code else {
+            return words;
+        };
+        let (mut end, mut replaceable) = (ops.len(), self.depth.is_none());
 
-    fn run_statements(&mut self) -> Result<()> {
-        while let Some(statement) = self.statements.pop_front() {
-            self.statement(statement)?;
+        for (i, op) in ops.iter().enumerate().take(pc) {
+            match op {
+                Op::Inlined { word, len } if i + len >= pc && pc < end => {
+                    if replaceable && runs_out(ops, i + len + 1, end) {
+                        words.pop();
+                    }
 
-            if self.verbose {
-                println!(
-                    "DEBUG: Stack: {}\nDEBUG: Statements: {}",
-                    self.stack.iter().map(ToString::to_string)
-                        .collect::<Vec<_>>().join(" "),
-                    self.statements.iter().map(ToString::to_string)
-                        .collect::<Vec<_>>().join(" "),
-                );
+                    words.push(*word);
+                    (end, replaceable) = (i + len + 1, true);
+                }
+                _ => {}
             }
         }
 
-        Ok(())
+        words
     }
+}
 
-    fn def(&mut self, identifier: String, procedure: Procedure) -> Result<()> {
-        self.definitions.insert(identifier, procedure);
-
-        Ok(())
+/// Whether nothing but jumps to `end` or past it is left to run from `pc`.
+fn runs_out(ops: &[Op], mut pc: usize, end: usize) -> bool {
+    while let Some(Op::Jump(target)) = ops.get(pc).filter(|_| pc < end) {
+        pc = *target;
     }
 
-    fn word(&mut self, word: &str) -> Result<()> {
-        let p = self.resolve(word)?.clone();
+    pc >= end
+}
 
-        self.prepend_statements(&p.0);
+/// The state of an interpreter between runs, taken with
+/// [`Interpreter::snapshot`] and put back with [`Interpreter::restore`]. No
+/// statements are pending between runs, so this is everything a program
+/// left behind.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Snapshot {
+    pub stack: Vec<Value>,
+    pub definitions: HashMap<Symbol, Procedure>,
+    pub effects: HashMap<Symbol, StackEffect>,
+    pub variables: HashMap<String, Value>,
+    /// The vocabulary new definitions go into.
+    pub vocab: Option<String>,
+    /// Files which have been imported already.
+    pub imported: HashSet<PathBuf>,
+}
 
-        Ok(())
-    }
+/// The contents of an image file.
+#[cfg(feature = "image")]
+#[derive(Serialize, Deserialize)]
+struct Image {
+    definitions: HashMap<Symbol, Procedure>,
+    effects: HashMap<Symbol, StackEffect>,
+    /// The values of the variables declared with `var`. Missing in images
+    /// saved before they were kept.
+    #[serde(default)]
+    variables: HashMap<String, Value>,
+}
 
-    fn statement(&mut self, statement: Statement) -> Result<()> {
-        match statement {
-            Statement::Expression(e) => self.push(self.evaluate_expression(e)?),
-            Statement::Builtin(b) => self.evaluate_builtin(b),
-            Statement::Value(v) => self.push(v),
-            Statement::Definition { identifier, procedure } =>
-                self.def(identifier, procedure),
-            Statement::Word(w) => self.word(&w),
+/// Statements run by the tree-walker, or compiled instructions.
+#[derive(Clone)]
+enum Code {
+    Statements(Rc<[Statement]>),
+    Ops(Rc<[Op]>),
+}
+
+/// The names a word is looked up under before and after its own.
+#[derive(Clone, Copy)]
+struct Names {
+    /// The word in the current vocabulary, if it is unqualified.
+    qualified: Option<Symbol>,
+    /// The word without its vocabulary, if it is qualified.
+    unqualified: Option<Symbol>,
+}
+
+impl Interpreter {
+    #[allow(clippy::new_without_default)]
+    pub fn new(verbose: bool) -> Self {
+        Self {
+            stack: vec![],
+            definitions: HashMap::new(),
+            effects: HashMap::new(),
+            variables: HashMap::new(),
+            verbose,
+            search_path: vec![],
+            importing: vec![],
+            imported: HashSet::new(),
+            vocab: None,
+            thrown: None,
+            bytecode: true,
+            optimize: false,
+            inline: false,
+            step_limit: None,
+            steps: 0,
+            stack_limit: Some(STACK_LIMIT),
+            frame_limit: Some(FRAME_LIMIT),
+            interrupt: None,
+            compiled: HashMap::new(),
+            qualified: HashMap::new(),
+            frames: vec![],
+            base: 0,
+            nesting: 0,
+            input: None,
+            args: vec![],
+            started: Instant::now(),
         }
     }
 
-    fn resolve(&self, identifier: &str) -> Result<&Procedure> {
-        match self.definitions.get(identifier) {
-            Some(p) => Ok(p),
-            None => Err(format!("Couldn't resolve identifier {identifier:?}"))
-        }
+    pub fn with_search_path(mut self, search_path: Vec<PathBuf>) -> Self {
+        self.search_path = search_path;
+        self
     }
 
-    fn evaluate_expression(&self, expression: Expression)
-    -> Result<Value> {
-        Ok(expression.into())
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
     }
 
-    fn evaluate_builtin(&mut self, builtin: Builtin) -> Result<()> {
-        match builtin {
-            Builtin::Add => self.add(),
-            Builtin::Sub => self.sub(),
-            Builtin::Mul => self.mul(),
-            Builtin::Div => self.div(),
-            Builtin::Eq => self.eq(),
-            Builtin::Neg => self.neg(),
-            Builtin::Lt => self.lt(),
-            Builtin::Le => self.le(),
-            Builtin::Gt => self.gt(),
-            Builtin::Ge => self.ge(),
-            Builtin::Dup => self.dup(),
-            Builtin::Dup2 => self.dup2(),
-            Builtin::Swap => self.swap(),
-            Builtin::Drop => self.drop(),
-            Builtin::Drop2 => self.drop2(),
-            Builtin::Drop3 => self.drop3(),
-            Builtin::Over => self.over(),
-            Builtin::Dupd => self.dupd(),
-            Builtin::Rotl => self.rotl(),
-            Builtin::Rotr => self.rotr(),
-            Builtin::Keep => self.keep(),
-            Builtin::Eval => self.eval(),
-            Builtin::Println => self.println(),
-            Builtin::If => self.evaluate_if(),
-            Builtin::Nth => self.nth(),
-        }
+    /// Compiles words and programs to bytecode if set, which is the default.
+    /// Otherwise, and always in verbose mode, they are run by walking their
+    /// statements. Procedures evaluated by builtins like `eval` and `map`
+    /// are never compiled.
+    pub fn with_bytecode(mut self, bytecode: bool) -> Self {
+        self.bytecode = bytecode;
+        self
     }
 
-    fn prepend_statements(&mut self, statements: &[Statement]) {
-        self.statements = VecDeque::from_iter(
-            statements.iter().cloned().chain(self.statements.iter().cloned())
-        );
+    /// Optimizes programs with [`Program::optimize`] before running them,
+    /// including imported ones.
+    pub fn with_optimize(mut self, optimize: bool) -> Self {
+        self.optimize = optimize;
+        self
     }
 
-    fn add(&mut self) -> Result<()> {
-        self.expect_args(2, "+")?;
+    /// Inlines words with at most [`INLINE_LIMIT`] statements into the
+    /// words calling them when those are compiled, unless they call
+    /// themselves or declare a stack effect. A word which is redefined while
+    /// a word it was inlined into runs isn't picked up by it.
+    pub fn with_inlining(mut self, inline: bool) -> Self {
+        self.inline = inline;
+        self
+    }
 
-        let (b, a) = (self.pop()?, self.pop()?);
-        let s = (a + b)?;
+    /// Fails with [`InterpreterError::StepLimit`] once a program has run
+    /// `limit` statements or instructions, so it can't loop forever. The
+    /// count starts over for each call of `run_program`.
+    pub fn with_step_limit(mut self, limit: u64) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
 
-        self.push(s)
+    /// Fails with [`InterpreterError::ResourceExhausted`] once the stack
+    /// holds more than `limit` values.
+    pub fn with_stack_limit(mut self, limit: usize) -> Self {
+        self.stack_limit = Some(limit);
+        self
     }
 
-    fn sub(&mut self) -> Result<()> {
-        self.expect_args(2, "-")?;
+    /// Fails with [`InterpreterError::ResourceExhausted`] once more than
+    /// `limit` words and procedures run at once, like in runaway recursion.
+    /// Calls at the end of a word or procedure replace it, so they don't
+    /// count.
+    pub fn with_frame_limit(mut self, limit: usize) -> Self {
+        self.frame_limit = Some(limit);
+        self
+    }
 
-        let (b, a) = (self.pop()?, self.pop()?);
-        let s = (a - b)?;
+    /// Fails with [`InterpreterError::Interrupted`] at the next step after
+    /// `flag` is set, clearing it again. The frames are dropped but the stack
+    /// is kept, so the interpreter can go on with the next program.
+    pub fn with_interrupt(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.interrupt = Some(flag);
+        self
+    }
 
-        self.push(s)
+    /// Makes `read-line` read from `input` instead of stdin.
+    pub fn with_input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
     }
 
-    fn mul(&mut self) -> Result<()> {
-        self.expect_args(2, "*")?;
+    pub fn run_program(&mut self, program: Program)
+    -> Result<Option<Value>> {
+        let program = if self.optimize { program.optimize() } else { program };
+        let code = self.code(program.statements.into(), None);
 
-        let (b, a) = (self.pop()?, self.pop()?);
-        let s = (a * b)?;
+        self.steps = 0;
 
-        self.push(s)
-    }
+        // An interrupt from before the program started isn't meant for it.
+        if let Some(flag) = &self.interrupt {
+            flag.store(false, AtomicOrdering::Relaxed);
+        }
 
-    fn div(&mut self) -> Result<()> {
-        self.expect_args(2, "/")?;
+        self.call_code(code)?;
 
-        let (b, a) = (self.pop()?, self.pop()?);
+        Ok(self.stack.last().cloned())
+    }
 
-        self.push((a / b)?)
+    /// Copies the stack, the definitions and the variables.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            stack: self.stack.clone(),
+            definitions: self.definitions.clone(),
+            effects: self.effects.clone(),
+            variables: self.variables.clone(),
+            vocab: self.vocab.clone(),
+            imported: self.imported.clone(),
+        }
     }
 
-    fn eq(&mut self) -> Result<()> {
-        self.expect_args(2, "=")?;
+    /// Replaces the state left by earlier runs with `snapshot`. Options like
+    /// the search path are kept.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.stack = snapshot.stack;
+        self.definitions = snapshot.definitions;
+        self.effects = snapshot.effects;
+        self.variables = snapshot.variables;
+        self.vocab = snapshot.vocab;
+        self.imported = snapshot.imported;
+        self.compiled.clear();
+        self.qualified.clear();
+    }
 
-        let (b, a) = (self.pop()?, self.pop()?);
-        let s = a == b;
+    /// Writes all definitions and the values of all variables to an image
+    /// file, to be loaded with [`Interpreter::load_image`].
+    #[cfg(feature = "image")]
+    pub fn save_image(&self, path: &Path) -> Result<()> {
+        let image = Image {
+            definitions: self.definitions.clone(),
+            effects: self.effects.clone(),
+            variables: self.variables.clone(),
+        };
+        let json = serde_json::to_string(&image)
+            .map_err(|e| format!("Couldn't encode image: {e}"))?;
 
-        self.push(Value::Bool(s))
+        fs::write(path, json)
+            .map_err(|e| format!("Couldn't write {path:?}: {e}").into())
     }
 
-    fn neg(&mut self) -> Result<()> {
-        self.expect_args(1, "!")?;
+    /// Adds the definitions and variables in an image file, replacing words
+    /// of the same name along with their stack effects.
+    #[cfg(feature = "image")]
+    pub fn load_image(&mut self, path: &Path) -> Result<()> {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format!("Couldn't read {path:?}: {e}"))?;
+        let image: Image = serde_json::from_str(&json)
+            .map_err(|e| format!("Couldn't load image {path:?}: {e}"))?;
 
-        let a = self.pop()?;
+        for (word, procedure) in image.definitions {
+            self.effects.remove(&word);
+            self.definitions.insert(word, procedure);
+        }
 
-        self.push((!a)?)
+        self.effects.extend(image.effects);
+        self.variables.extend(image.variables);
+        self.compiled.clear();
+
+        Ok(())
     }
 
-    fn lt(&mut self) -> Result<()> {
-        self.expect_args(2, "<")?;
+    /// Compiles statements unless the tree-walker is used. The code of
+    /// `word` is only compiled on its first call.
+    fn code(&mut self, statements: Rc<[Statement]>, word: Option<Symbol>)
+    -> Code {
+        if !self.bytecode || self.verbose {
+            return Code::Statements(statements);
+        }
 
-        let (b, a) = (self.pop()?, self.pop()?);
+        let Some(word) = word else {
+            return Code::Ops(compile(&statements));
+        };
 
-        self.push(Value::Bool(a < b))
-    }
+        if let Some(code) = self.compiled.get(&word) {
+            return Code::Ops(code.clone());
+        }
 
-    fn le(&mut self) -> Result<()> {
-        self.expect_args(2, "<=")?;
+        let code = match self.inline {
+            true => compile_with(
+                &statements,
+                &mut |w| self.inlined(w, &mut vec![word]),
+            ),
+            false => compile(&statements),
+        };
 
-        let (b, a) = (self.pop()?, self.pop()?);
+        self.compiled.insert(word, code.clone());
 
-        self.push(Value::Bool(a <= b))
+        Code::Ops(code)
     }
 
-    fn gt(&mut self) -> Result<()> {
-        self.expect_args(2, ">")?;
+    /// The code to inline for a call to `word` while compiling the words in
+    /// `visiting`, if it's small enough and doesn't call itself.
+    fn inlined(&self, word: Symbol, visiting: &mut Vec<Symbol>)
+    -> Option<Rc<[Op]>> {
+        let (&word, p) = self.resolve(word).ok()?;
+        let calls_itself = p.0.iter().any(|s| matches!(
+            s,
+            Statement::Word(w, _)
+                if self.resolve(*w).is_ok_and(|(&w, _)| w == word)
+        ));
 
-        let (b, a) = (self.pop()?, self.pop()?);
+        if p.0.len() > INLINE_LIMIT
+            || calls_itself
+            || visiting.contains(&word)
+            || self.effects.contains_key(&word)
+        {
+            return None;
+        }
 
-        self.push(Value::Bool(a > b))
-    }
+        visiting.push(word);
 
-    fn ge(&mut self) -> Result<()> {
-        self.expect_args(2, ">=")?;
+        let code = compile_with(&p.0, &mut |w| self.inlined(w, visiting));
 
-        let (b, a) = (self.pop()?, self.pop()?);
+        visiting.pop();
 
-        self.push(Value::Bool(a >= b))
+        // Keep the word in traces, though it runs in the caller's frame.
+        Some([Op::Inlined { word, len: code.len() }].into_iter()
+            .chain(code.iter().cloned())
+            .collect())
     }
 
-    fn dup(&mut self) -> Result<()> {
-        self.expect_args(1, "dup")?;
+    /// Switches the vocabulary new definitions go into and returns the
+    /// previous one.
+    fn set_vocab(&mut self, vocab: Option<String>) -> Option<String> {
+        // Inlined words were resolved in the previous vocabulary.
+        if self.inline {
+            self.compiled.clear();
+        }
 
-        let s = self.stack.last().unwrap();
+        self.qualified.clear();
 
-        self.push(s.clone())
+        std::mem::replace(&mut self.vocab, vocab)
     }
 
-    fn swap(&mut self) -> Result<()> {
-        self.expect_args(2, "swap")?;
+    /// Runs code until the frames above `self.base` have returned.
+    fn run_frames(&mut self) -> Result<()> {
+        while self.frames.len() > self.base {
+            let frame = self.frames.last_mut().expect("No frame to run");
+            let pc = frame.pc;
 
-        let n = self.stack.len();
+            frame.pc += 1;
 
-        self.stack.swap(n - 1, n - 2);
+            let res = match frame.code.clone() {
+                Code::Statements(statements) => match statements.get(pc) {
+                    Some(s) => self.step()
+                        .and_then(|()| self.statement(s))
+                        .and_then(|()| self.check_limits())
+                        .map_err(|e| (e, s.span())),
+                    None => self.leave().map_err(|e| (e, None)),
+                },
+                Code::Ops(ops) => match ops.get(pc) {
+                    Some(op) => self.step()
+                        .and_then(|()| self.op(op))
+                        .and_then(|()| self.check_limits())
+                        .map_err(|e| (e, op.span())),
+                    None => self.leave().map_err(|e| (e, None)),
+                },
+            };
+
+            res.map_err(|(e, span)| e.at(span, &self.trace()))?;
+
+            if self.verbose {
+                println!(
+                    "DEBUG: Stack: {}\nDEBUG: Statements: {}",
+                    self.stack.iter().map(ToString::to_string)
+                        .collect::<Vec<_>>().join(" "),
+                    self.frames.iter().rev()
+                        .flat_map(|f| match &f.code {
+                            Code::Statements(s) => &s[f.pc.min(s.len())..],
+                            Code::Ops(_) => &[],
+                        })
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>().join(" "),
+                );
+            }
+        }
 
         Ok(())
     }
 
-    fn drop(&mut self) -> Result<()> {
-        self.expect_args(1, "drop")?;
+    fn op(&mut self, op: &Op) -> Result<()> {
+        match op {
+            Op::Push(v) => self.push(v.clone()),
+            Op::Call(w, _) => self.word(*w),
+            Op::Prim(b, _) => self.evaluate_builtin(*b),
+            Op::Jump(target) => {
+                self.jump(*target);
 
-        self.pop()?;
+                Ok(())
+            }
+            Op::JumpUnless { on, target, builtin, .. } => {
+                // The procedures were compiled away, so only the flag is
+                // left on the stack.
+                let (name, args) = match builtin {
+                    Builtin::If => ("?", 3),
+                    b => (b.to_str(), 2),
+                };
 
-        Ok(())
-    }
+                if self.stack.is_empty() {
+                    return Err(InterpreterError::StackUnderflow {
+                        word: name.into(),
+                        expected: args,
+                        got: args - 1,
+                    });
+                }
 
-    fn drop2(&mut self) -> Result<()> {
-        self.expect_args(2, "2drop")?;
+                let flag = self.pop()?;
 
-        self.stack = self.stack.split_at(self.stack.len() - 2).0.to_vec();
+                if flag_of(flag, builtin.to_str())? != *on {
+                    self.jump(*target);
+                }
 
-        Ok(())
+                Ok(())
+            }
+            Op::Statement(s) => self.statement(s),
+            Op::Inlined { .. } => Ok(()),
+        }
     }
 
-    fn drop3(&mut self) -> Result<()> {
-        self.expect_args(3, "3drop")?;
+    /// Continues the innermost frame at `target`.
+    fn jump(&mut self, target: usize) {
+        self.frames.last_mut().expect("No frame to jump in").pc = target;
+    }
 
-        self.stack = self.stack.split_at(self.stack.len() - 3).0.to_vec();
+    /// Counts a step against the step limit and checks for an interrupt.
+    fn step(&mut self) -> Result<()> {
+        if self.interrupt.as_ref()
+            .is_some_and(|f| f.swap(false, AtomicOrdering::Relaxed)) {
+            return Err(InterpreterError::Interrupted);
+        }
 
-        Ok(())
+        match self.step_limit {
+            Some(limit) if self.steps >= limit =>
+                Err(InterpreterError::StepLimit(limit)),
+            _ => {
+                self.steps += 1;
+
+                Ok(())
+            }
+        }
     }
 
-    fn over(&mut self) -> Result<()> {
-        self.expect_args(2, "over")?;
+    /// Fails if the stack or the frames have outgrown their limits.
+    fn check_limits(&self) -> Result<()> {
+        let exhausted = |resource, limit| {
+            Err(InterpreterError::ResourceExhausted { resource, limit })
+        };
 
-        self.push(self.stack.iter().rev().nth(1).unwrap().clone())
+        match (self.stack_limit, self.frame_limit) {
+            (Some(limit), _) if self.stack.len() > limit =>
+                exhausted("values on the stack", limit),
+            (_, Some(limit)) if self.frames.len() > limit =>
+                exhausted("frames", limit),
+            _ => Ok(()),
+        }
     }
 
-    fn dupd(&mut self) -> Result<()> {
-        self.expect_args(2, "dupd")?;
+    /// Pops the innermost frame, checking the stack effect of its word.
+    fn leave(&mut self) -> Result<()> {
+        if let Some(Frame { word: Some(word), depth: Some(depth), .. }) =
+            self.frames.last()
+        {
+            self.check_depth(word.as_str(), *depth)?;
+        }
 
-        self.stack.insert(
-            self.stack.len() - 1,
-            self.stack.iter().rev().nth(1).unwrap().clone()
-        );
+        self.frames.pop();
 
         Ok(())
     }
 
-    fn rotl(&mut self) -> Result<()> {
-        self.expect_args(3, "rotl")?;
-
-        let x = self.stack.remove(self.stack.len() - 3);
-
-        self.push(x)
+    /// Runs `statements` next, before the rest of the current frame.
+    fn push_frame(&mut self, statements: impl Into<Rc<[Statement]>>) {
+        self.enter(Frame {
+            code: Code::Statements(statements.into()),
+            pc: 0,
+            word: None,
+            depth: None,
+        });
     }
 
-    fn rotr(&mut self) -> Result<()> {
-        self.expect_args(3, "rotr")?;
+    /// Pushes a frame. Finished frames on top are dropped first, so calls at
+    /// the end of a frame replace it and recursive loops don't pile up
+    /// frames. A frame that isn't a word's body takes over the word of the
+    /// frame it replaces, so it still shows up in traces.
+    fn enter(&mut self, mut frame: Frame) {
+        while let Some(top) = self.frames.last()
+            .filter(|_| self.frames.len() > self.base)
+        {
+            if !top.finished() || top.depth.is_some() {
+                break;
+            }
 
-        let z = self.pop()?;
+            let top = self.frames.pop().unwrap();
 
-        self.stack.insert(self.stack.len() - 2, z);
+            if frame.word.is_none() {
+                frame.word = top.word;
+            }
+        }
 
-        Ok(())
+        self.frames.push(frame);
     }
 
-    fn keep(&mut self) -> Result<()> {
-        self.expect_args(2, "keep")?;
+    /// The words being called from the outermost in.
+    fn trace(&self) -> Vec<String> {
+        self.frames.iter()
+            .flat_map(Frame::words)
+            .map(|w| w.as_str().to_string())
+            .collect()
+    }
+
+    fn def(
+        &mut self,
+        identifier: Symbol,
+        effect: Option<StackEffect>,
+        procedure: Procedure,
+    ) -> Result<()> {
+        // Builtins are parsed before words, so the word could never be called.
+        if let Ok(("", builtin)) = builtin(identifier.as_str()) {
+            return Err(
+                format!("Can't define `{builtin}`, which is a builtin").into()
+            );
+        }
 
-        let (b, a) = (self.pop()?, self.stack.last().unwrap().clone());
+        // Declarations are parsed before words too, and `recurse` is bound to
+        // the word being defined.
+        let name = identifier.as_str();
 
-        match b {
-            Value::Procedure(Procedure(s)) => {
-                self.statements.push_front(Statement::Value(a));
-                self.prepend_statements(&s);
-                Ok(())
-            }
-            _ => Err(format!("Can't evaluate {b}"))
+        if KEYWORDS.contains(&name) || name == "recurse" {
+            return Err(
+                format!("Can't define `{name}`, which is a keyword").into()
+            );
         }
-    }
 
-    /// Pops a value, dereferences it, takes it as a procedure and prepends the
-    /// contained statements to the statement buffer.
-    fn eval(&mut self) -> Result<()> {
-        self.expect_args(1, "eval")?;
+        let procedure = match recurses(&procedure.0) {
+            true => Procedure(bind_recurse(
+                &procedure.0,
+                &Statement::Word(identifier, Span::default()),
+            )),
+            false => procedure,
+        };
+
+        // Words in the body are looked up in the vocabulary first, no matter
+        // where the word is called from.
+        let (identifier, procedure) = match &self.vocab {
+            Some(v) => (
+                Symbol::new(&format!("{v}.{}", identifier.as_str())),
+                Procedure(map_words(&procedure.0, &|w, span| {
+                    (!w.contains('.')).then(|| Statement::Word(
+                        Symbol::new(&format!("{v}.{w}")),
+                        span,
+                    ))
+                })),
+            ),
+            None => (identifier, procedure),
+        };
+
+        match effect {
+            Some(effect) => self.effects.insert(identifier, effect),
+            None => self.effects.remove(&identifier),
+        };
 
-        let procedure = match self.pop()? {
-            Value::Procedure(s) => Ok(s),
-            v => Err(format!("Can't evaluate {v}"))
-        }?;
+        // Other words may have inlined the previous definition.
+        if self.inline {
+            self.compiled.clear();
+        } else {
+            self.compiled.remove(&identifier);
+        }
 
-        self.prepend_statements(&procedure.0);
+        self.definitions.insert(identifier, procedure);
 
         Ok(())
     }
 
-    fn println(&mut self) -> Result<()> {
-        self.expect_args(1, "println")?;
-
-        match self.pop()? {
-            Value::String(s) => println!("{s}"),
-            v => println!("{v}")
+    /// Defines the constructor and accessors of a struct.
+    fn structure(&mut self, name: String, fields: Box<[String]>)
+    -> Result<()> {
+        for field in fields.iter() {
+            self.def(
+                Symbol::new(&format!("{name}-{field}")),
+                None,
+                Procedure([Statement::Field {
+                    name: name.clone(),
+                    field: field.clone(),
+                }].into())
+            )?;
         }
 
-        Ok(())
+        self.def(
+            Symbol::new(&name),
+            None,
+            Procedure([Statement::Construct { name, fields }].into())
+        )
     }
 
-    fn evaluate_if(&mut self) -> Result<()> {
-        self.expect_args(3, "?")?;
+    fn construct(&mut self, name: String, fields: &[String]) -> Result<()> {
+        self.expect_args(fields.len(), &name)?;
 
-        let (esle, then, cond) = (self.pop()?, self.pop()?, self.pop()?);
-        let t = if cond == Value::Bool(true) { then } else { esle };
+        let values = self.stack.split_off(self.stack.len() - fields.len());
+        let fields = fields.iter().cloned().zip(values).collect();
 
-        match t {
-            Value::Procedure(s) => {
-                self.prepend_statements(&s.0);
-                Ok(())
-            },
-            _ => Err(format!("Can't evaluate {t}"))
-        }
+        self.push(Value::Struct { name, fields })
     }
 
-    fn nth(&mut self) -> Result<()> {
-        self.expect_args(2, "nth")?;
-
-        let (b, a) = (self.pop()?, self.pop()?);
+    fn field(&mut self, name: &str, field: &str) -> Result<()> {
+        self.expect_args(1, &format!("{name}-{field}"))?;
 
-        let (n, list) = match (a, b) {
-            (Value::Number(n), Value::List(s)) if n.fract() == 0.0 => (n, s),
-            (a, b) => return Err(format!("Can't index {b} by {a}"))
-        };
+        match self.pop()? {
+            Value::Struct { name: n, fields } if n == name => {
+                let (_, x) = fields.into_vec().into_iter()
+                    .find(|(f, _)| f == field)
+                    .ok_or_else(|| format!("{name} has no field {field}"))?;
 
-        if list.len() < n.abs() as usize {
-            return Err(format!("Index {n} out of bounds"))
+                self.push(x)
+            }
+            v => Err(InterpreterError::TypeError(
+                format!("Expected a {name}, got {v}")
+            ))
         }
+    }
 
-        let n = if n.is_sign_negative() {
-            list.len() - (n.abs() as usize)
-        } else {
-            n as usize
+    /// Declares a variable starting out as 0, and a word pushing its name.
+    fn var(&mut self, identifier: String) -> Result<()> {
+        let name = match &self.vocab {
+            Some(v) => format!("{v}.{identifier}"),
+            None => identifier.clone(),
         };
 
-        match list.get(n) {
-            Some(v) => self.push(v.clone()),
-            None => Err(format!("Index {n} out of bounds"))
+        self.variables.insert(name.clone(), Value::Int(0));
+
+        self.def(
+            Symbol::new(&identifier),
+            None,
+            Procedure([Statement::Value(Value::String(name.into()))].into())
+        )
+    }
+
+    fn word(&mut self, word: Symbol) -> Result<()> {
+        if !self.qualified.contains_key(&word) {
+            let names = self.names(word);
+
+            self.qualified.insert(word, names);
         }
+
+        let (word, p) = self.resolve(word)?;
+        let (word, p) = (*word, p.clone());
+
+        let depth = match self.effects.get(&word) {
+            Some(effect) => {
+                let inputs = effect.inputs.len();
+
+                self.expect_args(inputs, word.as_str())?;
+
+                Some(self.stack.len() - inputs + effect.outputs.len())
+            }
+            None => None,
+        };
+
+        let code = self.code(p.0, Some(word));
+
+        self.enter(Frame {
+            code,
+            pc: 0,
+            word: Some(word),
+            depth,
+        });
+
+        Ok(())
     }
 
-    fn dup2(&mut self) -> Result<()> {
-        self.expect_args(2, "2dup")?;
+    fn import(&mut self, path: &str) -> Result<()> {
+        let file = self.find_import(path)?;
+        let file = file.canonicalize()
+            .map_err(|e| format!("Couldn't import {path:?}: {e}"))?;
 
-        self.stack.extend_from_within(self.stack.len() - 2..);
+        if self.imported.contains(&file) {
+            return Ok(());
+        }
+
+        if self.importing.contains(&file) {
+            return Err(format!("Import cycle through {file:?}").into());
+        }
+
+        let input = fs::read_to_string(&file)
+            .map_err(|e| format!("Couldn't import {path:?}: {e}"))?;
+        let statements = match program(&input) {
+            Ok((_, program)) if self.optimize => program.optimize().statements,
+            Ok((_, program)) => program.statements,
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) =>
+                return Err(format!("Couldn't parse {path:?}: {}",
+                                   convert_error(input.as_str(), e)).into()),
+            Err(nom::Err::Incomplete(_)) =>
+                return Err(format!("Couldn't parse {path:?}").into()),
+        };
+
+        self.importing.push(file);
+
+        let vocab = self.set_vocab(None);
+        let code = self.code(statements.into(), None);
+        let res = self.call_code(code);
+        let file = self.importing.pop().unwrap();
+
+        self.set_vocab(vocab);
+
+        if res.is_ok() {
+            self.imported.insert(file);
+        }
+
+        res
+    }
+
+    /// Looks for `path` relative to the importing file, the search path and
+    /// the working directory, in that order.
+    fn find_import(&self, path: &str) -> Result<PathBuf> {
+        let dirs = self.importing.last()
+            .and_then(|f| f.parent())
+            .into_iter()
+            .chain(self.search_path.iter().map(PathBuf::as_path))
+            .chain([Path::new("")]);
+
+        dirs.map(|d| d.join(path))
+            .find(|f| f.is_file())
+            .ok_or_else(|| format!("Couldn't find {path:?} to import").into())
+    }
+
+    fn locals(&mut self, names: &[String], body: &Procedure) -> Result<()> {
+        self.expect_args(names.len(), "locals")?;
+
+        let values = self.stack.split_off(self.stack.len() - names.len());
+        let locals: HashMap<_, _> =
+            names.iter().map(String::as_str).zip(values).collect();
+
+        self.push_frame(map_words(&body.0, &|w, _| {
+            locals.get(w).cloned().map(Statement::Value)
+        }));
 
         Ok(())
     }
 
-    fn push(&mut self, value: Value) -> Result<()> {
-        self.stack.push(value);
+    fn check_depth(&self, word: &str, depth: usize) -> Result<()> {
+        match self.stack.len() {
+            n if n == depth => Ok(()),
+            n => Err(format!("Word `{word}` left the stack at depth {n}, but \
+                              its stack effect requires {depth}").into()),
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<()> {
+        match statement {
+            Statement::Expression(e) =>
+                self.push(evaluate_expression(e)),
+            Statement::Builtin(b, _) => self.evaluate_builtin(*b),
+            Statement::Value(v) => self.push(v.clone()),
+            Statement::Definition { identifier, effect, procedure } =>
+                self.def(*identifier, effect.clone(), procedure.clone()),
+            Statement::Word(w, _) => self.word(*w),
+            Statement::Import(path) => self.import(path),
+            Statement::Struct { name, fields } =>
+                self.structure(name.clone(), fields.clone()),
+            Statement::Construct { name, fields } =>
+                self.construct(name.clone(), fields),
+            Statement::Field { name, field } => self.field(name, field),
+            Statement::Var(v) => self.var(v.clone()),
+            Statement::Vocab(v) => {
+                self.set_vocab(Some(v.clone()));
+
+                Ok(())
+            },
+            Statement::Locals { names, body } => self.locals(names, body),
+            Statement::Recurse(r) => {
+                // The bound literal is only gone if a procedure nested in it
+                // outlived it.
+                let statements = r.bound.get()
+                    .and_then(Weak::upgrade)
+                    .unwrap_or_else(|| bind_literal(&r.literal));
+
+                self.push_frame(statements);
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Looks up a word in the current vocabulary if it is unqualified, then
+    /// as written, and finally without its vocabulary.
+    fn resolve(&self, word: Symbol) -> Result<(&Symbol, &Procedure)> {
+        let Names { qualified, unqualified } = match self.qualified.get(&word) {
+            Some(names) => *names,
+            None => self.names(word),
+        };
+
+        let found = qualified.into_iter()
+            .chain([word])
+            .chain(unqualified)
+            .find_map(|w| self.definitions.get_key_value(&w));
+
+        found.ok_or_else(|| {
+            InterpreterError::UnresolvedWord(word.as_str().into())
+        })
+    }
+
+    /// The names `word` is looked up under besides its own.
+    fn names(&self, word: Symbol) -> Names {
+        let name = word.as_str();
+
+        Names {
+            qualified: self.vocab.as_ref()
+                .filter(|_| !name.contains('.'))
+                .map(|v| Symbol::new(&format!("{v}.{name}"))),
+            unqualified: name.split_once('.').map(|(_, w)| Symbol::new(w)),
+        }
+    }
+
+    fn evaluate_builtin(&mut self, builtin: Builtin) -> Result<()> {
+        match builtin {
+            Builtin::Add => self.add(),
+            Builtin::Sub => self.sub(),
+            Builtin::Mul => self.mul(),
+            Builtin::Div => self.div(),
+            Builtin::Eq => self.eq(),
+            Builtin::Neg => self.neg(),
+            Builtin::Lt => self.lt(),
+            Builtin::Le => self.le(),
+            Builtin::Gt => self.gt(),
+            Builtin::Ge => self.ge(),
+            Builtin::Dup => self.dup(),
+            Builtin::Dup2 => self.dup2(),
+            Builtin::Swap => self.swap(),
+            Builtin::Drop => self.drop(),
+            Builtin::Drop2 => self.drop2(),
+            Builtin::Drop3 => self.drop3(),
+            Builtin::Over => self.over(),
+            Builtin::Dupd => self.dupd(),
+            Builtin::Nip => self.nip(),
+            Builtin::Tuck => self.tuck(),
+            Builtin::Rotl => self.rotl(),
+            Builtin::Pick => self.pick(),
+            Builtin::Roll => self.roll(),
+            Builtin::Depth => self.push(Value::Int(self.stack.len() as i64)),
+            Builtin::Clear => {
+                self.stack.clear();
+
+                Ok(())
+            },
+            Builtin::Rotr => self.rotr(),
+            Builtin::Keep => self.keep(),
+            Builtin::Bi => self.bi(),
+            Builtin::Tri => self.tri(),
+            Builtin::Cleave => self.cleave(),
+            Builtin::Eval => self.eval(),
+            Builtin::Println => self.println(),
+            Builtin::Print => self.print(),
+            Builtin::Eprintln => self.eprintln(),
+            Builtin::Eprint => self.eprint(),
+            Builtin::ReadLine => self.read_line(),
+            Builtin::ReadFile => self.read_file(),
+            Builtin::WriteFile => self.write_file(),
+            Builtin::AppendFile => self.append_file(),
+            Builtin::FileExists => self.file_exists(),
+            Builtin::DeleteFile => self.path_operation(
+                "delete-file",
+                |p| fs::remove_file(p)
+            ),
+            Builtin::ListDir => self.list_dir(),
+            Builtin::Env => self.env(),
+            Builtin::SetEnv => self.set_env(),
+            Builtin::Exit => self.exit(),
+            Builtin::Now => self.now(),
+            Builtin::Monotonic => self.push(Value::Number(
+                self.started.elapsed().as_secs_f64() * 1000.0
+            )),
+            Builtin::Sleep => self.sleep(),
+            Builtin::Args => self.push(Value::List(
+                self.args.iter()
+                    .map(|s| Value::String(s.as_str().into()))
+                    .collect()
+            )),
+            Builtin::Mkdir => self.path_operation(
+                "mkdir",
+                |p| fs::create_dir_all(p)
+            ),
+            Builtin::If => self.evaluate_if(),
+            Builtin::When => self.when("when", true),
+            Builtin::Unless => self.when("unless", false),
+            Builtin::Nth => self.nth("nth", false),
+            Builtin::Min => self.min(),
+            Builtin::Max => self.max(),
+            Builtin::Abs => self.abs(),
+            Builtin::Map => self.map(),
+            Builtin::Filter => self.filter(),
+            Builtin::Fold => self.fold(),
+            Builtin::Each => self.each(),
+            Builtin::Curry => self.curry(),
+            Builtin::Compose => self.compose(),
+            Builtin::Times => self.times(),
+            Builtin::While => self.evaluate_while(),
+            Builtin::Range => self.range(),
+            Builtin::RangeStep => self.range_step(),
+            Builtin::Len => self.len(),
+            Builtin::Append => self.append(),
+            Builtin::Head => self.head(),
+            Builtin::Tail => self.tail(),
+            Builtin::Uncons => self.uncons(),
+            Builtin::Cons => self.cons(),
+            Builtin::Push => self.list_push(),
+            Builtin::Sort => self.sort(),
+            Builtin::SortBy => self.sort_by(),
+            Builtin::Zip => self.zip(),
+            Builtin::Flatten => self.flatten(),
+            Builtin::Contains => self.contains(),
+            Builtin::IndexOf => self.index_of(),
+            Builtin::Slice => self.slice(),
+            Builtin::SetNth => self.set_nth(),
+            Builtin::Split => self.split(),
+            Builtin::Join => self.join(),
+            Builtin::Upper => self.upper(),
+            Builtin::Lower => self.lower(),
+            Builtin::Substring => self.substring(),
+            Builtin::Trim => self.trim(),
+            Builtin::TrimStart => self.trim_start(),
+            Builtin::TrimEnd => self.trim_end(),
+            Builtin::StringToNumber => self.string_to_number(),
+            Builtin::ToString => self.stringify(),
+            Builtin::Format => self.format(),
+            Builtin::Ord => self.ord(),
+            Builtin::Chr => self.chr(),
+            Builtin::Mod => self.rem(),
+            Builtin::Pow => self.pow(),
+            Builtin::Sqrt => self.math("sqrt", f64::sqrt),
+            Builtin::Floor => self.math("floor", f64::floor),
+            Builtin::Ceil => self.math("ceil", f64::ceil),
+            Builtin::Round => self.math("round", f64::round),
+            Builtin::Trunc => self.math("trunc", f64::trunc),
+            Builtin::Sin => self.math("sin", f64::sin),
+            Builtin::Cos => self.math("cos", f64::cos),
+            Builtin::Tan => self.math("tan", f64::tan),
+            Builtin::Atan2 => self.atan2(),
+            Builtin::Ln => self.math("ln", f64::ln),
+            Builtin::Log10 => self.math("log10", f64::log10),
+            Builtin::Exp => self.math("exp", f64::exp),
+            Builtin::Pi => self.push(Value::Number(std::f64::consts::PI)),
+            Builtin::E => self.push(Value::Number(std::f64::consts::E)),
+            Builtin::And => self.logic("and", |a, b| a && b),
+            Builtin::Or => self.logic("or", |a, b| a || b),
+            Builtin::Xor => self.logic("xor", |a, b| a ^ b),
+            Builtin::AndThen => self.short_circuit("and-then", false),
+            Builtin::OrElse => self.short_circuit("or-else", true),
+            Builtin::BitAnd => self.bitwise("band", Value::bitand),
+            Builtin::BitOr => self.bitwise("bor", Value::bitor),
+            Builtin::BitXor => self.bitwise("bxor", Value::bitxor),
+            Builtin::Shl => self.bitwise("shl", Value::shl),
+            Builtin::Shr => self.bitwise("shr", Value::shr),
+            Builtin::Set => self.set(),
+            Builtin::Get => self.get(),
+            Builtin::ListToSet => self.list_to_set(),
+            Builtin::SetToList => self.set_to_list(),
+            Builtin::Spread => self.spread(),
+            Builtin::Apply => self.apply_list(),
+            Builtin::QuotToList => self.quot_to_list(),
+            Builtin::ListToQuot => self.list_to_quot(),
+            Builtin::SetAdd => self.set_add(),
+            Builtin::SetHas => self.set_has(),
+            Builtin::Union => self.set_operation("union", |a, b| {
+                a.into_iter().chain(b).collect()
+            }),
+            Builtin::Intersect => self.set_operation("intersect", |a, b| {
+                a.into_iter().filter(|x| in_set(&b, x)).collect()
+            }),
+            Builtin::Difference => self.set_operation("difference", |a, b| {
+                a.into_iter().filter(|x| !in_set(&b, x)).collect()
+            }),
+            Builtin::IsNil => self.is_nil(),
+            Builtin::Default => self.default(),
+            Builtin::NthOrNil => self.nth("?nth", true),
+            Builtin::Throw => self.throw(),
+            Builtin::Try => self.evaluate_try(),
+            Builtin::Assert => self.assert(),
+            Builtin::Cond => self.cond(),
+            #[cfg(feature = "regex")]
+            Builtin::ReMatch => self.re_match(),
+            #[cfg(feature = "regex")]
+            Builtin::ReFind => self.re_find(),
+            #[cfg(feature = "regex")]
+            Builtin::ReReplace => self.re_replace(),
+            #[cfg(feature = "sh")]
+            Builtin::Sh => self.sh(),
+            #[cfg(feature = "chrono")]
+            Builtin::FormatTime => self.format_time(),
+            #[cfg(feature = "chrono")]
+            Builtin::ParseTime => self.parse_time(),
+            #[cfg(feature = "serde_json")]
+            Builtin::JsonParse => self.json_parse(),
+            #[cfg(feature = "serde_json")]
+            Builtin::JsonEncode => self.json_encode(),
+            #[cfg(feature = "image")]
+            Builtin::SaveImage => {
+                self.expect_args(1, "save-image")?;
+
+                let path = self.pop_string()?;
+
+                self.save_image(Path::new(&*path))
+            }
+            #[cfg(feature = "image")]
+            Builtin::LoadImage => {
+                self.expect_args(1, "load-image")?;
+
+                let path = self.pop_string()?;
+
+                self.load_image(Path::new(&*path))
+            }
+            #[cfg(feature = "csv")]
+            Builtin::CsvParse => self.csv_parse(false),
+            #[cfg(feature = "csv")]
+            Builtin::CsvParseWith => self.csv_parse(true),
+            #[cfg(feature = "csv")]
+            Builtin::CsvEncode => self.csv_encode(false),
+            #[cfg(feature = "csv")]
+            Builtin::CsvEncodeWith => self.csv_encode(true),
+            #[cfg(feature = "ureq")]
+            Builtin::HttpGet => self.http(false),
+            #[cfg(feature = "ureq")]
+            Builtin::HttpPost => self.http(true),
+            #[cfg(feature = "tcp")]
+            Builtin::TcpConnect => self.tcp_open(false),
+            #[cfg(feature = "tcp")]
+            Builtin::TcpListen => self.tcp_open(true),
+            #[cfg(feature = "tcp")]
+            Builtin::TcpAccept => self.tcp_accept(),
+            #[cfg(feature = "tcp")]
+            Builtin::TcpSend => self.tcp_send(),
+            #[cfg(feature = "tcp")]
+            Builtin::TcpRecv => self.tcp_recv(),
+        }
+    }
+
+    /// Runs a builtin on constant arguments and returns the stack it leaves,
+    /// or `None` if it fails.
+    pub(crate) fn evaluate_constant(builtin: Builtin, args: Vec<Value>)
+    -> Option<Vec<Value>> {
+        let mut interpreter = Self::new(false);
+
+        interpreter.stack = args;
+        interpreter.evaluate_builtin(builtin).ok()?;
+
+        Some(interpreter.stack)
+    }
+
+    /// Runs a procedure to completion before returning, leaving the pending
+    /// statements untouched.
+    fn call(&mut self, procedure: &Procedure) -> Result<()> {
+        self.call_code(Code::Statements(procedure.0.clone()))
+    }
+
+    fn call_code(&mut self, code: Code) -> Result<()> {
+        if self.nesting >= NESTING_LIMIT {
+            return Err(InterpreterError::ResourceExhausted {
+                resource: "nested calls",
+                limit: NESTING_LIMIT,
+            });
+        }
+
+        let outer = std::mem::replace(&mut self.base, self.frames.len());
+
+        self.nesting += 1;
+        self.enter(Frame { code, pc: 0, word: None, depth: None });
+
+        let res = self.run_frames();
+
+        self.frames.truncate(self.base);
+        self.base = outer;
+        self.nesting -= 1;
+
+        res
+    }
+
+    /// Calls a procedure with `args` pushed onto the stack and pops the single
+    /// value it has to leave.
+    fn apply(
+        &mut self,
+        args: impl IntoIterator<Item = Value>,
+        procedure: &Procedure,
+        name: &str,
+    ) -> Result<Value> {
+        let depth = self.stack.len();
+
+        self.stack.extend(args);
+        self.call(procedure)?;
+
+        if self.stack.len() != depth + 1 {
+            return Err(InterpreterError::TypeError(
+                format!("Procedure passed to `{name}` must leave exactly one \
+                         value")
+            ));
+        }
+
+        self.pop()
+    }
+
+    fn add(&mut self) -> Result<()> {
+        self.expect_args(2, "+")?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+        let s = (a + b)?;
+
+        self.push(s)
+    }
+
+    fn sub(&mut self) -> Result<()> {
+        self.expect_args(2, "-")?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+        let s = (a - b)?;
+
+        self.push(s)
+    }
+
+    fn mul(&mut self) -> Result<()> {
+        self.expect_args(2, "*")?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+        let s = (a * b)?;
+
+        self.push(s)
+    }
+
+    fn div(&mut self) -> Result<()> {
+        self.expect_args(2, "/")?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+
+        self.push((a / b)?)
+    }
+
+    fn rem(&mut self) -> Result<()> {
+        self.expect_args(2, "mod")?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+
+        self.push((a % b)?)
+    }
+
+    fn pow(&mut self) -> Result<()> {
+        self.expect_args(2, "pow")?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+
+        self.push(a.pow(b)?)
+    }
+
+    /// Applies a function to a single numerical argument.
+    fn math(&mut self, name: &str, f: fn(f64) -> f64) -> Result<()> {
+        self.expect_args(1, name)?;
+
+        let v = self.pop()?;
+
+        match v.as_f64() {
+            Some(a) => self.push(Value::Number(f(a))),
+            None => Err(InterpreterError::TypeError(
+                format!("Can't apply `{name}` to {v}")
+            ))
+        }
+    }
+
+    fn atan2(&mut self) -> Result<()> {
+        self.expect_args(2, "atan2")?;
+
+        let (x, y) = (self.pop()?, self.pop()?);
+
+        match (x.as_f64(), y.as_f64()) {
+            (Some(x), Some(y)) => self.push(Value::Number(y.atan2(x))),
+            _ => Err(InterpreterError::TypeError(
+                format!("Can't apply `atan2` to {y} and {x}")
+            ))
+        }
+    }
+
+    /// Applies a function to two boolean arguments.
+    fn logic(&mut self, name: &str, f: fn(bool, bool) -> bool) -> Result<()> {
+        self.expect_args(2, name)?;
+
+        match (self.pop()?, self.pop()?) {
+            (Value::Bool(b), Value::Bool(a)) => self.push(Value::Bool(f(a, b))),
+            (b, a) => Err(InterpreterError::TypeError(
+                format!("Can't apply `{name}` to {a} and {b}")
+            ))
+        }
+    }
+
+    fn list_to_set(&mut self) -> Result<()> {
+        self.expect_args(1, "list->set")?;
+
+        let list = self.pop_list()?;
+
+        self.push(Value::Set(to_set(list.to_vec())?))
+    }
+
+    fn set_to_list(&mut self) -> Result<()> {
+        self.expect_args(1, "set->list")?;
+
+        let set = self.pop_set()?;
+
+        self.push(Value::List(set))
+    }
+
+    fn spread(&mut self) -> Result<()> {
+        self.expect_args(1, "spread")?;
+
+        let list = self.pop_list()?;
+
+        self.stack.extend(list.iter().cloned());
 
         Ok(())
     }
 
-    fn pop(&mut self) -> Result<Value> {
-        self.expect_args(1, "pop")?;
-        Ok(self.stack.pop().unwrap())
+    fn apply_list(&mut self) -> Result<()> {
+        self.expect_args(2, "apply")?;
+
+        let (procedure, list) = (self.pop_procedure()?, self.pop_list()?);
+
+        self.stack.extend(list.iter().cloned());
+        self.push_frame(procedure.0.clone());
+
+        Ok(())
     }
 
-    fn expect_args(&self, args: usize, name: &str) -> Result<()> {
-        match self.stack.len() {
-            n if n < args => Err(format!("Operation `{name}` expected {args} \
-                                          argument(s), got {n}")),
-            _ => Ok(())
+    fn quot_to_list(&mut self) -> Result<()> {
+        self.expect_args(1, "quot->list")?;
+
+        let procedure = self.pop_procedure()?;
+        let list = procedure.0.iter().map(|s| match s {
+            Statement::Value(v) => Ok(quote(v.clone())),
+            Statement::Expression(e) => Ok(quote(e.clone().into())),
+            Statement::Builtin(b, _) =>
+                Ok(Value::Symbol(Symbol::new(b.to_str()))),
+            Statement::Word(w, _) => Ok(Value::Symbol(*w)),
+            Statement::Recurse(_) => Ok(Value::Symbol(Symbol::new("recurse"))),
+            s => Err(InterpreterError::TypeError(
+                format!("Can't convert `{s}` to a value")
+            )),
+        }).collect::<Result<_>>()?;
+
+        self.push(Value::List(list))
+    }
+
+    fn list_to_quot(&mut self) -> Result<()> {
+        self.expect_args(1, "list->quot")?;
+
+        let list = self.pop_list()?;
+        let statements = list.iter().cloned().map(|v| match v {
+            Value::Symbol(s) => match builtin(s.as_str()) {
+                Ok(("", b)) => Statement::Builtin(b, Span::default()),
+                _ => Statement::Word(s, Span::default()),
+            },
+            Value::List(l) if unquote(&l).is_some() =>
+                Statement::Value(unquote(&l).unwrap().clone()),
+            v => Statement::Value(v),
+        }).collect();
+
+        self.push(Value::Procedure(Procedure(statements)))
+    }
+
+    fn set_add(&mut self) -> Result<()> {
+        self.expect_args(2, "set-add")?;
+
+        let (x, set) = (self.pop()?, self.pop_set()?);
+        let set = match search_set(&set, &x)? {
+            Ok(_) => set,
+            Err(i) => {
+                let mut set = set.to_vec();
+
+                set.insert(i, x);
+                set.into()
+            }
+        };
+
+        self.push(Value::Set(set))
+    }
+
+    fn set_has(&mut self) -> Result<()> {
+        self.expect_args(2, "set-has?")?;
+
+        let (x, set) = (self.pop()?, self.pop_set()?);
+
+        self.push(Value::Bool(search_set(&set, &x)?.is_ok()))
+    }
+
+    /// Pops two sets and pushes the set of the elements computed from them
+    /// by `f`.
+    fn set_operation(
+        &mut self,
+        name: &str,
+        f: fn(Vec<Value>, Vec<Value>) -> Vec<Value>,
+    ) -> Result<()> {
+        self.expect_args(2, name)?;
+
+        let (b, a) = (self.pop_set()?, self.pop_set()?);
+
+        self.push(Value::Set(to_set(f(a.to_vec(), b.to_vec()))?))
+    }
+
+    fn set(&mut self) -> Result<()> {
+        self.expect_args(2, "set")?;
+
+        let (var, x) = (self.pop()?, self.pop()?);
+
+        *self.variable(&var)? = x;
+
+        Ok(())
+    }
+
+    fn get(&mut self) -> Result<()> {
+        self.expect_args(1, "get")?;
+
+        let var = self.pop()?;
+        let x = self.variable(&var)?.clone();
+
+        self.push(x)
+    }
+
+    fn variable(&mut self, var: &Value) -> Result<&mut Value> {
+        match var {
+            Value::String(s) => self.variables.get_mut(&**s)
+                .ok_or_else(|| InterpreterError::TypeError(
+                    format!("Unknown variable {s:?}")
+                )),
+            v => Err(InterpreterError::TypeError(
+                format!("{v} isn't a variable")
+            ))
+        }
+    }
+
+    /// Applies a bitwise operation to two integer arguments.
+    fn bitwise(&mut self, name: &str, f: fn(Value, Value) -> Result<Value>)
+    -> Result<()> {
+        self.expect_args(2, name)?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+
+        self.push(f(a, b)?)
+    }
+
+    /// Pops a procedure and a bool, and only evaluates the procedure if the
+    /// bool isn't `short`.
+    fn short_circuit(&mut self, name: &str, short: bool) -> Result<()> {
+        self.expect_args(2, name)?;
+
+        let procedure = self.pop_procedure()?;
+
+        match self.pop()? {
+            Value::Bool(a) if a == short => self.push(Value::Bool(a)),
+            Value::Bool(_) => match self.apply([], &procedure, name)? {
+                Value::Bool(b) => self.push(Value::Bool(b)),
+                v => Err(InterpreterError::TypeError(
+                    format!("Procedure passed to `{name}` must leave a bool, \
+                             left {v}")
+                ))
+            },
+            v => Err(InterpreterError::TypeError(
+                format!("Can't apply `{name}` to {v}")
+            ))
         }
     }
+
+    fn eq(&mut self) -> Result<()> {
+        self.expect_args(2, "=")?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+
+        self.push(Value::Bool(equal(&a, &b)))
+    }
+
+    fn neg(&mut self) -> Result<()> {
+        self.expect_args(1, "!")?;
+
+        let a = self.pop()?;
+
+        self.push((!a)?)
+    }
+
+    fn lt(&mut self) -> Result<()> {
+        self.expect_args(2, "<")?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+
+        self.push(Value::Bool(a < b))
+    }
+
+    fn le(&mut self) -> Result<()> {
+        self.expect_args(2, "<=")?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+
+        self.push(Value::Bool(a <= b))
+    }
+
+    fn gt(&mut self) -> Result<()> {
+        self.expect_args(2, ">")?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+
+        self.push(Value::Bool(a > b))
+    }
+
+    fn ge(&mut self) -> Result<()> {
+        self.expect_args(2, ">=")?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+
+        self.push(Value::Bool(a >= b))
+    }
+
+    fn dup(&mut self) -> Result<()> {
+        self.expect_args(1, "dup")?;
+
+        let s = self.stack.last().unwrap();
+
+        self.push(s.clone())
+    }
+
+    fn swap(&mut self) -> Result<()> {
+        self.expect_args(2, "swap")?;
+
+        let n = self.stack.len();
+
+        self.stack.swap(n - 1, n - 2);
+
+        Ok(())
+    }
+
+    fn drop(&mut self) -> Result<()> {
+        self.expect_args(1, "drop")?;
+
+        self.pop()?;
+
+        Ok(())
+    }
+
+    fn drop2(&mut self) -> Result<()> {
+        self.expect_args(2, "2drop")?;
+
+        self.stack = self.stack.split_at(self.stack.len() - 2).0.to_vec();
+
+        Ok(())
+    }
+
+    fn drop3(&mut self) -> Result<()> {
+        self.expect_args(3, "3drop")?;
+
+        self.stack = self.stack.split_at(self.stack.len() - 3).0.to_vec();
+
+        Ok(())
+    }
+
+    fn over(&mut self) -> Result<()> {
+        self.expect_args(2, "over")?;
+
+        self.push(self.stack.iter().rev().nth(1).unwrap().clone())
+    }
+
+    fn dupd(&mut self) -> Result<()> {
+        self.expect_args(2, "dupd")?;
+
+        self.stack.insert(
+            self.stack.len() - 1,
+            self.stack.iter().rev().nth(1).unwrap().clone()
+        );
+
+        Ok(())
+    }
+
+    fn nip(&mut self) -> Result<()> {
+        self.expect_args(2, "nip")?;
+
+        self.stack.remove(self.stack.len() - 2);
+
+        Ok(())
+    }
+
+    fn tuck(&mut self) -> Result<()> {
+        self.expect_args(2, "tuck")?;
+
+        self.stack.insert(
+            self.stack.len() - 2,
+            self.stack.last().unwrap().clone()
+        );
+
+        Ok(())
+    }
+
+    fn rotl(&mut self) -> Result<()> {
+        self.expect_args(3, "rotl")?;
+
+        let x = self.stack.remove(self.stack.len() - 3);
+
+        self.push(x)
+    }
+
+    fn rotr(&mut self) -> Result<()> {
+        self.expect_args(3, "rotr")?;
+
+        let z = self.pop()?;
+
+        self.stack.insert(self.stack.len() - 2, z);
+
+        Ok(())
+    }
+
+    fn pick(&mut self) -> Result<()> {
+        let i = self.pop_depth("pick")?;
+
+        self.push(self.stack[i].clone())
+    }
+
+    fn roll(&mut self) -> Result<()> {
+        let i = self.pop_depth("roll")?;
+        let x = self.stack.remove(i);
+
+        self.push(x)
+    }
+
+    /// Pops a depth below the top of the stack and returns the index of the
+    /// element at that depth.
+    fn pop_depth(&mut self, name: &str) -> Result<usize> {
+        self.expect_args(1, name)?;
+
+        let v = self.pop()?;
+        let n = match v.as_integer().and_then(|n| usize::try_from(n).ok()) {
+            Some(n) => n,
+            None => return Err(InterpreterError::TypeError(
+                format!("Can't reach depth {v} of the stack")
+            ))
+        };
+
+        self.expect_args(n + 1, name)?;
+
+        Ok(self.stack.len() - 1 - n)
+    }
+
+    fn keep(&mut self) -> Result<()> {
+        self.expect_args(2, "keep")?;
+
+        let b = self.pop_procedure()?;
+        let a = self.stack.last().unwrap().clone();
+
+        self.push_frame([Statement::Value(a)]);
+        self.push_frame(b.0);
+
+        Ok(())
+    }
+
+    fn bi(&mut self) -> Result<()> {
+        self.expect_args(3, "bi")?;
+
+        let (q, p) = (self.pop_procedure()?, self.pop_procedure()?);
+        let x = self.pop()?;
+
+        self.prepend_applications(x, &[p, q]);
+
+        Ok(())
+    }
+
+    fn tri(&mut self) -> Result<()> {
+        self.expect_args(4, "tri")?;
+
+        let (r, q) = (self.pop_procedure()?, self.pop_procedure()?);
+        let (p, x) = (self.pop_procedure()?, self.pop()?);
+
+        self.prepend_applications(x, &[p, q, r]);
+
+        Ok(())
+    }
+
+    fn cleave(&mut self) -> Result<()> {
+        self.expect_args(2, "cleave")?;
+
+        let procedures = self.pop_list()?.iter().cloned()
+            .map(|v| match v {
+                Value::Procedure(p) => Ok(p),
+                v => Err(InterpreterError::TypeError(
+                    format!("Can't evaluate {v}")
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let x = self.pop()?;
+
+        self.prepend_applications(x, &procedures);
+
+        Ok(())
+    }
+
+    /// Prepends statements applying each procedure to its own copy of `x`.
+    fn prepend_applications(&mut self, x: Value, procedures: &[Procedure]) {
+        let statements = procedures.iter()
+            .flat_map(|p| {
+                std::iter::once(Statement::Value(x.clone()))
+                    .chain(p.0.iter().cloned())
+            })
+            .collect::<Vec<_>>();
+
+        self.push_frame(statements);
+    }
+
+    /// Pops a value, dereferences it, takes it as a procedure and prepends the
+    /// contained statements to the statement buffer.
+    fn eval(&mut self) -> Result<()> {
+        self.expect_args(1, "eval")?;
+
+        let procedure = self.pop_procedure()?;
+
+        self.push_frame(procedure.0);
+
+        Ok(())
+    }
+
+    fn println(&mut self) -> Result<()> {
+        self.expect_args(1, "println")?;
+
+        println!("{}", self.pop()?.to_unquoted_string());
+
+        Ok(())
+    }
+
+    /// Prints without a newline and flushes stdout, so prompts show up before
+    /// reading input.
+    fn print(&mut self) -> Result<()> {
+        self.expect_args(1, "print")?;
+
+        print!("{}", self.pop()?.to_unquoted_string());
+
+        stdout().flush()
+            .map_err(|e| format!("Couldn't flush stdout: {e}").into())
+    }
+
+    fn eprintln(&mut self) -> Result<()> {
+        self.expect_args(1, "eprintln")?;
+
+        eprintln!("{}", self.pop()?.to_unquoted_string());
+
+        Ok(())
+    }
+
+    fn eprint(&mut self) -> Result<()> {
+        self.expect_args(1, "eprint")?;
+
+        eprint!("{}", self.pop()?.to_unquoted_string());
+
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> Result<()> {
+        let mut line = String::new();
+        let read = match &mut self.input {
+            Some(input) => input.read_line(&mut line),
+            None => stdin().lock().read_line(&mut line),
+        }.map_err(|e| format!("Couldn't read a line: {e}"))?;
+
+        if read == 0 {
+            return self.push(Value::Nil);
+        }
+
+        if line.ends_with('\n') {
+            line.pop();
+
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+
+        self.push(Value::String(line.into()))
+    }
+
+    fn read_file(&mut self) -> Result<()> {
+        self.expect_args(1, "read-file")?;
+
+        let path = self.pop_string()?;
+        let s = fs::read_to_string(&*path)
+            .map_err(|e| format!("Couldn't read {path:?}: {e}"))?;
+
+        self.push(Value::String(s.into()))
+    }
+
+    fn write_file(&mut self) -> Result<()> {
+        self.expect_args(2, "write-file")?;
+
+        let (path, s) = (self.pop_string()?, self.pop_string()?);
+
+        fs::write(&*path, &*s)
+            .map_err(|e| format!("Couldn't write {path:?}: {e}").into())
+    }
+
+    fn append_file(&mut self) -> Result<()> {
+        self.expect_args(2, "append-file")?;
+
+        let (path, s) = (self.pop_string()?, self.pop_string()?);
+
+        fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&*path)
+            .and_then(|mut f| f.write_all(s.as_bytes()))
+            .map_err(|e| format!("Couldn't append to {path:?}: {e}").into())
+    }
+
+    fn file_exists(&mut self) -> Result<()> {
+        self.expect_args(1, "file-exists?")?;
+
+        let path = self.pop_string()?;
+
+        self.push(Value::Bool(Path::new(&*path).exists()))
+    }
+
+    fn list_dir(&mut self) -> Result<()> {
+        self.expect_args(1, "list-dir")?;
+
+        let path = self.pop_string()?;
+        let mut names = fs::read_dir(&*path)
+            .and_then(|entries| entries
+                .map(|e| Ok(e?.file_name().to_string_lossy().into_owned()))
+                .collect::<std::io::Result<Vec<_>>>())
+            .map_err(|e| format!("Couldn't list {path:?}: {e}"))?;
+
+        names.sort();
+
+        self.push(Value::List(
+            names.into_iter().map(|s| Value::String(s.into())).collect()
+        ))
+    }
+
+    fn exit(&mut self) -> Result<()> {
+        self.expect_args(1, "exit")?;
+
+        let n = self.pop()?;
+        let code = n.as_integer()
+            .and_then(|n| i32::try_from(n).ok())
+            .ok_or_else(|| format!("Can't exit with status {n}"))?;
+
+        Err(InterpreterError::Exit(code))
+    }
+
+    fn now(&mut self) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map_err(|e| format!("The clock is before the Unix epoch: {e}"))?;
+
+        self.push(Value::Number(now.as_secs_f64()))
+    }
+
+    fn sleep(&mut self) -> Result<()> {
+        self.expect_args(1, "sleep")?;
+
+        let ms = self.pop()?;
+        let duration = ms.as_f64()
+            .and_then(|ms| Duration::try_from_secs_f64(ms / 1000.0).ok())
+            .ok_or_else(|| format!("Can't sleep for {ms} ms"))?;
+
+        std::thread::sleep(duration);
+
+        Ok(())
+    }
+
+    fn env(&mut self) -> Result<()> {
+        self.expect_args(1, "env")?;
+
+        let name = self.pop_string()?;
+
+        match std::env::var(&*name) {
+            Ok(s) => self.push(Value::String(s.into())),
+            Err(std::env::VarError::NotPresent) => self.push(Value::Nil),
+            Err(e) => Err(format!("Couldn't read {name:?}: {e}").into()),
+        }
+    }
+
+    fn set_env(&mut self) -> Result<()> {
+        self.expect_args(2, "set-env")?;
+
+        let (name, s) = (self.pop_string()?, self.pop_string()?);
+
+        if name.is_empty() || name.contains(['=', '\0']) || s.contains('\0') {
+            return Err(
+                format!("Can't set environment variable {name:?}").into()
+            );
+        }
+
+        std::env::set_var(&*name, &*s);
+
+        Ok(())
+    }
+
+    /// Pops a path and applies a filesystem operation to it.
+    fn path_operation(
+        &mut self,
+        name: &str,
+        f: fn(&str) -> std::io::Result<()>,
+    ) -> Result<()> {
+        self.expect_args(1, name)?;
+
+        let path = self.pop_string()?;
+
+        f(&path).map_err(|e| format!("Couldn't {name} {path:?}: {e}").into())
+    }
+
+    fn evaluate_if(&mut self) -> Result<()> {
+        self.expect_args(3, "?")?;
+
+        let (esle, then, cond) = (self.pop()?, self.pop()?, self.pop()?);
+        let t = if flag_of(cond, "if")? { then } else { esle };
+
+        match t {
+            Value::Procedure(s) => {
+                self.push_frame(s.0.clone());
+                Ok(())
+            },
+            _ => Err(InterpreterError::TypeError(
+                format!("Can't evaluate {t}")
+            ))
+        }
+    }
+
+    /// Evaluates a procedure only if the flag below it equals `on`.
+    fn when(&mut self, name: &str, on: bool) -> Result<()> {
+        self.expect_args(2, name)?;
+
+        let (quot, flag) = (self.pop_procedure()?, self.pop()?);
+
+        if flag_of(flag, name)? == on {
+            self.push_frame(quot.0.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Pops an index and a sequence and pushes the element at the index. If
+    /// it is out of bounds, this pushes nil if `or_nil` is set and fails
+    /// otherwise.
+    fn nth(&mut self, name: &str, or_nil: bool) -> Result<()> {
+        self.expect_args(2, name)?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+
+        let x = match (a.as_integer(), &b) {
+            (Some(n), Value::List(l)) => {
+                let i = if n < 0 { l.len() as i64 + n } else { n };
+
+                usize::try_from(i).ok().and_then(|i| l.get(i)).cloned()
+                    .ok_or(InterpreterError::IndexOutOfBounds {
+                        index: n,
+                        len: l.len(),
+                    })
+            }
+            // Walk the characters from the end for negative indices, so only
+            // a failed lookup counts them.
+            (Some(n), Value::String(s)) => match usize::try_from(n) {
+                Ok(i) => s.chars().nth(i),
+                Err(_) => usize::try_from(-1 - n).ok()
+                    .and_then(|i| s.chars().rev().nth(i)),
+            }
+                .map(|c| Value::String(c.to_string().into()))
+                .ok_or_else(|| InterpreterError::IndexOutOfBounds {
+                    index: n,
+                    len: s.chars().count(),
+                }),
+            _ => return Err(InterpreterError::TypeError(
+                format!("Can't index {b} by {a}")
+            ))
+        };
+
+        match x {
+            Ok(v) => self.push(v),
+            Err(_) if or_nil => self.push(Value::Nil),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn throw(&mut self) -> Result<()> {
+        self.expect_args(1, "throw")?;
+
+        let x = self.pop()?;
+        let message = x.to_unquoted_string();
+
+        self.thrown = Some(x);
+
+        Err(InterpreterError::Thrown(message))
+    }
+
+    fn evaluate_try(&mut self) -> Result<()> {
+        self.expect_args(2, "try")?;
+
+        let (handler, procedure) =
+            (self.pop_procedure()?, self.pop_procedure()?);
+        let stack = self.stack.clone();
+
+        self.thrown = None;
+
+        if let Err(e) = self.call(&procedure) {
+            if !e.is_catchable() {
+                return Err(e);
+            }
+
+            let error = self.thrown.take()
+                .unwrap_or_else(|| Value::String(e.kind().to_string().into()));
+
+            self.stack = stack;
+            self.push(error)?;
+            self.push_frame(handler.0.clone());
+        }
+
+        Ok(())
+    }
+
+    fn cond(&mut self) -> Result<()> {
+        self.expect_args(2, "cond")?;
+
+        let (clauses, x) = (self.pop_list()?, self.pop()?);
+
+        for clause in clauses.iter() {
+            let body = match clause {
+                Value::Procedure(body) => body,
+                Value::List(l) => match &l[..] {
+                    [Value::Procedure(pred), Value::Procedure(body)] => {
+                        let flag = self.apply([x.clone()], pred, "cond")?;
+
+                        if !flag_of(flag, "cond")? {
+                            continue;
+                        }
+
+                        body
+                    }
+                    _ => return Err(InterpreterError::TypeError(
+                        format!("Invalid `cond` clause {clause}")
+                    ))
+                },
+                _ => return Err(InterpreterError::TypeError(
+                    format!("Invalid `cond` clause {clause}")
+                ))
+            };
+
+            self.push(x)?;
+            self.push_frame(body.0.clone());
+
+            return Ok(());
+        }
+
+        Err(format!("No `cond` clause matched {x}").into())
+    }
+
+    fn assert(&mut self) -> Result<()> {
+        self.expect_args(2, "assert")?;
+
+        let (message, flag) = (self.pop()?, self.pop()?);
+
+        match flag {
+            Value::Bool(true) => Ok(()),
+            Value::Bool(false) => Err(format!(
+                "Assertion failed: {}",
+                message.to_unquoted_string()
+            ).into()),
+            v => Err(InterpreterError::TypeError(
+                format!("Can't assert {v}, expected a bool")
+            ))
+        }
+    }
+
+    fn is_nil(&mut self) -> Result<()> {
+        self.expect_args(1, "nil?")?;
+
+        let x = self.pop()?;
+
+        self.push(Value::Bool(matches!(x, Value::Nil)))
+    }
+
+    fn default(&mut self) -> Result<()> {
+        self.expect_args(2, "default")?;
+
+        let (fallback, v) = (self.pop()?, self.pop()?);
+
+        self.push(match v {
+            Value::Nil => fallback,
+            v => v,
+        })
+    }
+
+    fn min(&mut self) -> Result<()> {
+        self.expect_args(2, "min")?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+
+        self.push(a.min(b)?)
+    }
+
+    fn max(&mut self) -> Result<()> {
+        self.expect_args(2, "max")?;
+
+        let (b, a) = (self.pop()?, self.pop()?);
+
+        self.push(a.max(b)?)
+    }
+
+    fn abs(&mut self) -> Result<()> {
+        self.expect_args(1, "abs")?;
+
+        let a = self.pop()?;
+
+        self.push(a.abs()?)
+    }
+
+    fn map(&mut self) -> Result<()> {
+        self.expect_args(2, "map")?;
+
+        let (procedure, list) = (self.pop_procedure()?, self.pop_list()?);
+        let results = list.iter().cloned()
+            .map(|x| self.apply([x], &procedure, "map"))
+            .collect::<Result<_>>()?;
+
+        self.push(Value::List(results))
+    }
+
+    fn filter(&mut self) -> Result<()> {
+        self.expect_args(2, "filter")?;
+
+        let (procedure, list) = (self.pop_procedure()?, self.pop_list()?);
+        let mut results = vec![];
+
+        for x in list.iter().cloned() {
+            match self.apply([x.clone()], &procedure, "filter")? {
+                Value::Bool(true) => results.push(x),
+                Value::Bool(false) => {}
+                v => return Err(InterpreterError::TypeError(
+                    format!("Procedure passed to `filter` must leave a bool, \
+                             left {v}")
+                ))
+            }
+        }
+
+        self.push(Value::List(results.into()))
+    }
+
+    fn fold(&mut self) -> Result<()> {
+        self.expect_args(3, "fold")?;
+
+        let (procedure, init) = (self.pop_procedure()?, self.pop()?);
+        let list = self.pop_list()?;
+        let acc = list.iter().cloned()
+            .try_fold(init, |acc, x| self.apply([acc, x], &procedure, "fold"))?;
+
+        self.push(acc)
+    }
+
+    fn each(&mut self) -> Result<()> {
+        self.expect_args(2, "each")?;
+
+        let (procedure, list) = (self.pop_procedure()?, self.pop_list()?);
+
+        for x in list.iter().cloned() {
+            self.push(x)?;
+            self.call(&procedure)?;
+        }
+
+        Ok(())
+    }
+
+    fn curry(&mut self) -> Result<()> {
+        self.expect_args(2, "curry")?;
+
+        let (procedure, x) = (self.pop_procedure()?, self.pop()?);
+        let statements = std::iter::once(Statement::Value(x))
+            .chain(procedure.0.iter().cloned())
+            .collect();
+
+        self.push(Value::Procedure(Procedure(statements)))
+    }
+
+    fn compose(&mut self) -> Result<()> {
+        self.expect_args(2, "compose")?;
+
+        let (q, p) = (self.pop_procedure()?, self.pop_procedure()?);
+        let statements = p.0.iter().cloned()
+            .chain(q.0.iter().cloned())
+            .collect();
+
+        self.push(Value::Procedure(Procedure(statements)))
+    }
+
+    fn times(&mut self) -> Result<()> {
+        self.expect_args(2, "times")?;
+
+        let procedure = self.pop_procedure()?;
+        let v = self.pop()?;
+        let n = match v.as_integer() {
+            Some(n) if n >= 0 => n,
+            _ => return Err(InterpreterError::TypeError(
+                format!("Can't repeat a procedure {v} times")
+            ))
+        };
+
+        for _ in 0..n {
+            self.call(&procedure)?;
+        }
+
+        Ok(())
+    }
+
+    fn evaluate_while(&mut self) -> Result<()> {
+        self.expect_args(2, "while")?;
+
+        let (body, cond) = (self.pop_procedure()?, self.pop_procedure()?);
+
+        loop {
+            self.call(&cond)?;
+
+            match self.pop()? {
+                Value::Bool(true) => self.call(&body)?,
+                Value::Bool(false) => return Ok(()),
+                v => return Err(InterpreterError::TypeError(
+                    format!("Condition of `while` must leave a bool, left {v}")
+                ))
+            }
+        }
+    }
+
+    fn range(&mut self) -> Result<()> {
+        self.expect_args(2, "range")?;
+
+        let (end, start) = (self.pop()?, self.pop()?);
+
+        self.push_range(start, end, Value::Int(1))
+    }
+
+    fn range_step(&mut self) -> Result<()> {
+        self.expect_args(3, "range-step")?;
+
+        let (step, end, start) = (self.pop()?, self.pop()?, self.pop()?);
+
+        self.push_range(start, end, step)
+    }
+
+    /// Pushes a range of integers if all arguments are integers, or a range
+    /// of floats otherwise.
+    fn push_range(&mut self, start: Value, end: Value, step: Value)
+    -> Result<()> {
+        if let (Value::Int(a), Value::Int(b), Value::Int(s)) =
+            (&start, &end, &step)
+        {
+            let (a, b, s) = (*a, *b, *s);
+
+            if s != 0 {
+                let n = (b as i128 - a as i128 + s as i128 - s.signum() as i128)
+                    / s as i128;
+
+                if n > RANGE_LIMIT as i128 {
+                    return Err(range_too_long(&start, &end));
+                }
+
+                return self.push(Value::List(
+                    std::iter::successors(Some(a), |x| x.checked_add(s))
+                        .take_while(|x| if s > 0 { *x < b } else { *x > b })
+                        .map(Value::Int)
+                        .collect()
+                ));
+            }
+        }
+
+        let (start, end, step) = match (
+            start.as_f64(), end.as_f64(), step.as_f64()
+        ) {
+            (Some(a), Some(b), Some(s))
+                if a.is_finite() && b.is_finite() && s.is_finite()
+                    && s != 0.0 => (a, b, s),
+            _ => return Err(InterpreterError::TypeError(
+                format!("Can't make a range from {start} to {end} in steps \
+                         of {step}")
+            ))
+        };
+        let n = ((end - start) / step).ceil().max(0.0);
+
+        if n > RANGE_LIMIT as f64 {
+            return Err(range_too_long(
+                &Value::Number(start),
+                &Value::Number(end),
+            ));
+        }
+
+        self.push(Value::List(
+            (0..n as usize)
+                .map(|i| Value::Number(start + i as f64 * step))
+                .collect()
+        ))
+    }
+
+    fn len(&mut self) -> Result<()> {
+        self.expect_args(1, "len")?;
+
+        let n = match self.pop()? {
+            Value::List(l) => l.len(),
+            Value::String(s) => s.chars().count(),
+            Value::Set(s) => s.len(),
+            v => return Err(InterpreterError::TypeError(
+                format!("Can't take the length of {v}")
+            ))
+        };
+
+        self.push(Value::Int(n as i64))
+    }
+
+    fn append(&mut self) -> Result<()> {
+        self.expect_args(2, "append")?;
+
+        let (b, a) = (self.pop_list()?, self.pop_list()?);
+        let list = a.iter().cloned().chain(b.iter().cloned()).collect();
+
+        self.push(Value::List(list))
+    }
+
+    fn head(&mut self) -> Result<()> {
+        self.expect_args(1, "head")?;
+
+        let (_, x) = self.pop_uncons("head")?;
+
+        self.push(x)
+    }
+
+    fn tail(&mut self) -> Result<()> {
+        self.expect_args(1, "tail")?;
+
+        let (rest, _) = self.pop_uncons("tail")?;
+
+        self.push(rest)
+    }
+
+    fn uncons(&mut self) -> Result<()> {
+        self.expect_args(1, "uncons")?;
+
+        let (rest, x) = self.pop_uncons("uncons")?;
+
+        self.push(rest)?;
+        self.push(x)
+    }
+
+    fn cons(&mut self) -> Result<()> {
+        self.expect_args(2, "cons")?;
+
+        let (list, x) = (self.pop_list()?, self.pop()?);
+        let list = std::iter::once(x).chain(list.iter().cloned()).collect();
+
+        self.push(Value::List(list))
+    }
+
+    fn list_push(&mut self) -> Result<()> {
+        self.expect_args(2, "push")?;
+
+        let (x, list) = (self.pop()?, self.pop_list()?);
+        let mut list = list.to_vec();
+
+        list.push(x);
+
+        self.push(Value::List(list.into()))
+    }
+
+    fn sort(&mut self) -> Result<()> {
+        self.expect_args(1, "sort")?;
+
+        let list = self.pop_list()?.to_vec();
+        let keyed = list.into_iter().map(|x| (x.clone(), x)).collect();
+
+        self.push(Value::List(sort_by_key(keyed)?.into()))
+    }
+
+    fn sort_by(&mut self) -> Result<()> {
+        self.expect_args(2, "sort-by")?;
+
+        let (procedure, list) = (self.pop_procedure()?, self.pop_list()?);
+        let keyed = list.iter().cloned()
+            .map(|x| Ok((self.apply([x.clone()], &procedure, "sort-by")?, x)))
+            .collect::<Result<_>>()?;
+
+        self.push(Value::List(sort_by_key(keyed)?.into()))
+    }
+
+    fn zip(&mut self) -> Result<()> {
+        self.expect_args(2, "zip")?;
+
+        let (b, a) = (self.pop_list()?, self.pop_list()?);
+        let pairs = a.iter().cloned()
+            .zip(b.iter().cloned())
+            .map(|(x, y)| Value::List([x, y].into()))
+            .collect();
+
+        self.push(Value::List(pairs))
+    }
+
+    fn flatten(&mut self) -> Result<()> {
+        self.expect_args(1, "flatten")?;
+
+        let list = self.pop_list()?.iter().cloned()
+            .flat_map(|x| match x {
+                Value::List(l) => l.to_vec(),
+                x => vec![x],
+            })
+            .collect();
+
+        self.push(Value::List(list))
+    }
+
+    fn contains(&mut self) -> Result<()> {
+        self.expect_args(2, "contains?")?;
+
+        let contains = match (self.pop()?, self.pop()?) {
+            (Value::List(l), x) => l.iter().any(|y| equal(y, &x)),
+            (Value::String(s), Value::String(x)) => s.contains(&*x),
+            (b, a) => return Err(InterpreterError::TypeError(
+                format!("Can't check if {b} contains {a}")
+            ))
+        };
+
+        self.push(Value::Bool(contains))
+    }
+
+    fn index_of(&mut self) -> Result<()> {
+        self.expect_args(2, "index-of")?;
+
+        let (list, x) = (self.pop_list()?, self.pop()?);
+
+        match list.iter().position(|y| equal(y, &x)) {
+            Some(n) => self.push(Value::Int(n as i64)),
+            None => Err(format!("Couldn't find {x} in the list").into())
+        }
+    }
+
+    fn slice(&mut self) -> Result<()> {
+        self.expect_args(3, "slice")?;
+
+        let (end, start, seq) = (self.pop()?, self.pop()?, self.pop()?);
+        let len = match &seq {
+            Value::List(l) => l.len(),
+            Value::String(s) => s.chars().count(),
+            v => return Err(InterpreterError::TypeError(
+                format!("Can't slice {v}")
+            ))
+        };
+        let (start, end) = (slice_index(start, len)?, slice_index(end, len)?);
+
+        if start > end {
+            return Err(
+                format!("Slice start {start} is after its end {end}").into()
+            );
+        }
+
+        match seq {
+            Value::List(l) => self.push(Value::List(l[start..end].into())),
+            Value::String(s) => self.push(Value::String(
+                s.chars().skip(start).take(end - start).collect::<String>()
+                    .into()
+            )),
+            _ => unreachable!(),
+        }
+    }
+
+    fn set_nth(&mut self) -> Result<()> {
+        self.expect_args(3, "set-nth")?;
+
+        let (list, n, x) = (self.pop_list()?, self.pop()?, self.pop()?);
+        let mut list = list.to_vec();
+        let i = match n.as_integer() {
+            Some(n) => {
+                let i = if n < 0 { list.len() as i64 + n } else { n };
+
+                match usize::try_from(i) {
+                    Ok(i) if i < list.len() => i,
+                    _ => return Err(InterpreterError::IndexOutOfBounds {
+                        index: n,
+                        len: list.len(),
+                    }),
+                }
+            }
+            None => return Err(InterpreterError::TypeError(
+                format!("Can't index a list by {n}")
+            ))
+        };
+
+        list[i] = x;
+
+        self.push(Value::List(list.into()))
+    }
+
+    fn split(&mut self) -> Result<()> {
+        self.expect_args(2, "split")?;
+
+        let (sep, s) = (self.pop_string()?, self.pop_string()?);
+        let list = if sep.is_empty() {
+            s.chars().map(|c| Value::String(c.to_string().into())).collect()
+        } else {
+            s.split(&*sep).map(|s| Value::String(s.into())).collect()
+        };
+
+        self.push(Value::List(list))
+    }
+
+    fn join(&mut self) -> Result<()> {
+        self.expect_args(2, "join")?;
+
+        let (sep, list) = (self.pop_string()?, self.pop_list()?);
+        let s = list.iter()
+            .map(Value::to_unquoted_string)
+            .collect::<Vec<_>>()
+            .join(&*sep);
+
+        self.push(Value::String(s.into()))
+    }
+
+    fn upper(&mut self) -> Result<()> {
+        self.expect_args(1, "upper")?;
+
+        let s = self.pop_string()?;
+
+        self.push(Value::String(s.to_uppercase().into()))
+    }
+
+    fn lower(&mut self) -> Result<()> {
+        self.expect_args(1, "lower")?;
+
+        let s = self.pop_string()?;
+
+        self.push(Value::String(s.to_lowercase().into()))
+    }
+
+    fn substring(&mut self) -> Result<()> {
+        self.expect_args(3, "substring")?;
+
+        let (len, start, s) = (self.pop()?, self.pop()?, self.pop_string()?);
+        let (start, len) = match (
+            start.as_integer().and_then(|a| usize::try_from(a).ok()),
+            len.as_integer().and_then(|b| usize::try_from(b).ok()),
+        ) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Err(InterpreterError::TypeError(
+                format!("Can't take {len} characters from index {start}")
+            ))
+        };
+        let n = s.chars().count();
+
+        if start.saturating_add(len) > n {
+            return Err(format!(
+                "Substring from {start} of length {len} is out of bounds for \
+                 a string of length {n}"
+            ).into());
+        }
+
+        self.push(Value::String(
+            s.chars().skip(start).take(len).collect::<String>().into()
+        ))
+    }
+
+    fn trim(&mut self) -> Result<()> {
+        self.expect_args(1, "trim")?;
+
+        let s = self.pop_string()?;
+
+        self.push(Value::String(s.trim().into()))
+    }
+
+    fn trim_start(&mut self) -> Result<()> {
+        self.expect_args(1, "trim-start")?;
+
+        let s = self.pop_string()?;
+
+        self.push(Value::String(s.trim_start().into()))
+    }
+
+    fn trim_end(&mut self) -> Result<()> {
+        self.expect_args(1, "trim-end")?;
+
+        let s = self.pop_string()?;
+
+        self.push(Value::String(s.trim_end().into()))
+    }
+
+    fn string_to_number(&mut self) -> Result<()> {
+        self.expect_args(1, "string->number")?;
+
+        let s = self.pop_string()?;
+
+        if let Ok(n) = s.trim().parse() {
+            return self.push(Value::Int(n));
+        }
+
+        #[cfg(feature = "num-bigint")]
+        if let Ok(n) = s.trim().parse::<num_bigint::BigInt>() {
+            return self.push(Value::BigInt(n));
+        }
+
+        if let Ok(n) = s.trim().parse::<num_rational::Rational64>() {
+            return self.push(n.into());
+        }
+
+        match s.trim().parse() {
+            Ok(n) => self.push(Value::Number(n)),
+            Err(e) => Err(
+                format!("Couldn't parse {s:?} as a number: {e}").into()
+            )
+        }
+    }
+
+    fn stringify(&mut self) -> Result<()> {
+        self.expect_args(1, "to-string")?;
+
+        let s = self.pop()?.to_unquoted_string();
+
+        self.push(Value::String(s.into()))
+    }
+
+    fn format(&mut self) -> Result<()> {
+        self.expect_args(2, "format")?;
+
+        let (template, args) = (self.pop_string()?, self.pop_list()?);
+        let mut args = args.iter();
+        let mut chars = template.chars().peekable();
+        let mut s = String::new();
+
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('{', Some('{')) | ('}', Some('}')) => {
+                    chars.next();
+                    s.push(c);
+                }
+                ('{', Some('}')) => {
+                    chars.next();
+
+                    match args.next() {
+                        Some(x) => s.push_str(&x.to_unquoted_string()),
+                        None => return Err(format!(
+                            "Not enough arguments for template {template:?}"
+                        ).into())
+                    }
+                }
+                ('{' | '}', _) => return Err(format!(
+                    "Unmatched `{c}` in template {template:?}"
+                ).into()),
+                _ => s.push(c),
+            }
+        }
+
+        if args.next().is_some() {
+            return Err(format!("Too many arguments for template \
+                                {template:?}").into());
+        }
+
+        self.push(Value::String(s.into()))
+    }
+
+    fn ord(&mut self) -> Result<()> {
+        self.expect_args(1, "ord")?;
+
+        let s = self.pop_string()?;
+        let mut chars = s.chars();
+
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => self.push(Value::Int(c as i64)),
+            _ => Err(InterpreterError::TypeError(
+                format!("Expected a single character, got {s:?}")
+            ))
+        }
+    }
+
+    fn chr(&mut self) -> Result<()> {
+        self.expect_args(1, "chr")?;
+
+        let v = self.pop()?;
+        let c = v.as_integer()
+            .and_then(|n| u32::try_from(n).ok())
+            .and_then(char::from_u32);
+
+        match c {
+            Some(c) => self.push(Value::String(c.to_string().into())),
+            None => Err(InterpreterError::TypeError(
+                format!("Expected a code point, got {v}")
+            ))
+        }
+    }
+
+    #[cfg(feature = "regex")]
+    fn re_match(&mut self) -> Result<()> {
+        self.expect_args(2, "re-match?")?;
+
+        let (re, s) = (self.pop_regex()?, self.pop_string()?);
+
+        self.push(Value::Bool(re.is_match(&s)))
+    }
+
+    #[cfg(feature = "regex")]
+    fn re_find(&mut self) -> Result<()> {
+        self.expect_args(2, "re-find")?;
+
+        let (re, s) = (self.pop_regex()?, self.pop_string()?);
+        let matches = re.find_iter(&s)
+            .map(|m| Value::String(m.as_str().into()))
+            .collect();
+
+        self.push(Value::List(matches))
+    }
+
+    #[cfg(feature = "regex")]
+    fn re_replace(&mut self) -> Result<()> {
+        self.expect_args(3, "re-replace")?;
+
+        let replacement = self.pop_string()?;
+        let (re, s) = (self.pop_regex()?, self.pop_string()?);
+        let s = re.replace_all(&s, &*replacement).into_owned();
+
+        self.push(Value::String(s.into()))
+    }
+
+    /// Runs a command with `sh -c`, or `cmd /C` on Windows. Its stderr isn't
+    /// captured.
+    #[cfg(feature = "sh")]
+    fn sh(&mut self) -> Result<()> {
+        use std::process::{Command, Stdio};
+
+        self.expect_args(1, "sh")?;
+
+        let cmd = self.pop_string()?;
+        let (shell, flag) =
+            if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+        let output = Command::new(shell)
+            .args([flag, &cmd])
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| format!("Couldn't run {cmd:?}: {e}"))?;
+
+        self.push(Value::String(
+            String::from_utf8_lossy(&output.stdout).into()
+        ))?;
+        self.push(match output.status.code() {
+            Some(code) => Value::Int(code.into()),
+            None => Value::Nil,
+        })
+    }
+
+    #[cfg(feature = "chrono")]
+    fn format_time(&mut self) -> Result<()> {
+        use std::fmt::Write;
+
+        self.expect_args(2, "format-time")?;
+
+        let (fmt, epoch) = (self.pop_string()?, self.pop()?);
+        let time = epoch.as_f64()
+            .filter(|s| s.is_finite())
+            .and_then(|s| chrono::DateTime::from_timestamp(
+                s.floor() as i64,
+                (s.fract().abs() * 1e9) as u32,
+            ))
+            .ok_or_else(|| format!("Can't format {epoch} as a time"))?;
+        let mut s = String::new();
+
+        write!(s, "{}", time.format(&fmt))
+            .map_err(|_| format!("Invalid time format {fmt:?}"))?;
+
+        self.push(Value::String(s.into()))
+    }
+
+    /// Parses a time with an offset, or else a date and time or just a date
+    /// in UTC.
+    #[cfg(feature = "chrono")]
+    fn parse_time(&mut self) -> Result<()> {
+        use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+        self.expect_args(2, "parse-time")?;
+
+        let (fmt, s) = (self.pop_string()?, self.pop_string()?);
+        let time = DateTime::parse_from_str(&s, &fmt)
+            .map(|t| t.to_utc())
+            .or_else(|_| NaiveDateTime::parse_from_str(&s, &fmt)
+                .map(|t| t.and_utc()))
+            .or_else(|_| NaiveDate::parse_from_str(&s, &fmt)
+                .map(|d| d.and_time(Default::default()).and_utc()))
+            .map_err(|e| format!("Couldn't parse {s:?} as {fmt:?}: {e}"))?;
+
+        self.push(match time.timestamp_subsec_nanos() {
+            0 => Value::Int(time.timestamp()),
+            ns => Value::Number(time.timestamp() as f64 + ns as f64 / 1e9),
+        })
+    }
+
+    #[cfg(feature = "serde_json")]
+    fn json_parse(&mut self) -> Result<()> {
+        self.expect_args(1, "json-parse")?;
+
+        let s = self.pop_string()?;
+        let json = serde_json::from_str(&s)
+            .map_err(|e| format!("Couldn't parse JSON: {e}"))?;
+
+        self.push(from_json(json))
+    }
+
+    #[cfg(feature = "serde_json")]
+    fn json_encode(&mut self) -> Result<()> {
+        self.expect_args(1, "json-encode")?;
+
+        let json = to_json(&self.pop()?)?;
+
+        self.push(Value::String(json.to_string().into()))
+    }
+
+    /// Parses CSV, taking a separator and whether there's a header line from
+    /// the stack if `options` is set.
+    #[cfg(feature = "csv")]
+    fn csv_parse(&mut self, options: bool) -> Result<()> {
+        let name = if options { "csv-parse-with" } else { "csv-parse" };
+
+        self.expect_args(if options { 3 } else { 1 }, name)?;
+
+        let (sep, headers) = match options {
+            true => {
+                let headers = match self.pop()? {
+                    Value::Bool(b) => b,
+                    v => return Err(InterpreterError::TypeError(
+                        format!("Expected a bool, got {v}")
+                    )),
+                };
+
+                (self.pop_separator()?, headers)
+            }
+            false => (b',', false),
+        };
+        let s = self.pop_string()?;
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(sep)
+            .has_headers(headers)
+            .flexible(true)
+            .from_reader(s.as_bytes());
+        let names = match headers {
+            true => reader.headers().map_err(|e| e.to_string())?.clone(),
+            false => Default::default(),
+        };
+        let rows = reader.records()
+            .map(|row| {
+                let row = row.map_err(|e| format!("Couldn't parse CSV: {e}"))?;
+                let field = |s: &str| Value::String(s.into());
+
+                Ok(Value::List(match headers {
+                    true => names.iter().zip(&row)
+                        .map(|(k, v)| Value::List([field(k), field(v)].into()))
+                        .collect(),
+                    false => row.iter().map(field).collect(),
+                }))
+            })
+            .collect::<Result<_>>()?;
+
+        self.push(Value::List(rows))
+    }
+
+    /// Encodes a list of rows as CSV, taking a separator from the stack if
+    /// `options` is set.
+    #[cfg(feature = "csv")]
+    fn csv_encode(&mut self, options: bool) -> Result<()> {
+        let name = if options { "csv-encode-with" } else { "csv-encode" };
+
+        self.expect_args(if options { 2 } else { 1 }, name)?;
+
+        let sep = if options { self.pop_separator()? } else { b',' };
+        let rows = self.pop_list()?;
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(sep)
+            .flexible(true)
+            .from_writer(vec![]);
+        let cell = |x: &Value| match x {
+            Value::String(s) => Ok(s.to_string()),
+            Value::Symbol(s) => Ok(s.as_str().into()),
+            Value::Nil => Ok(String::new()),
+            Value::List(_) | Value::Set(_) | Value::Procedure(_)
+                | Value::Struct { .. } =>
+                Err(InterpreterError::TypeError(
+                    format!("Can't encode {x} as a CSV field")
+                )),
+            x => Ok(x.to_string()),
+        };
+
+        for (i, row) in rows.iter().enumerate() {
+            let Value::List(row) = row else {
+                return Err(InterpreterError::TypeError(
+                    format!("Expected a list of rows, got {row}")
+                ));
+            };
+            let pairs = row.iter()
+                .map(|x| match x {
+                    Value::List(pair) => match &**pair {
+                        [k, v] => Some((k, v)),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .filter(|pairs| !pairs.is_empty());
+            let fields = match pairs {
+                Some(pairs) => {
+                    if i == 0 {
+                        let names = pairs.iter()
+                            .map(|(k, _)| cell(k))
+                            .collect::<Result<Vec<_>>>()?;
+
+                        writer.write_record(names)
+                            .map_err(|e| e.to_string())?;
+                    }
+
+                    pairs.iter().map(|(_, v)| cell(v)).collect()
+                }
+                None => row.iter().map(cell).collect::<Result<Vec<_>>>(),
+            }?;
+
+            writer.write_record(fields).map_err(|e| e.to_string())?;
+        }
+
+        let bytes = writer.into_inner().map_err(|e| e.to_string())?;
+
+        self.push(Value::String(String::from_utf8(bytes)
+            .map_err(|e| e.to_string())?.into()))
+    }
+
+    /// Makes a GET request, or a POST request taking the body from the stack
+    /// if `post` is set, and pushes the response body and status.
+    #[cfg(feature = "ureq")]
+    fn http(&mut self, post: bool) -> Result<()> {
+        let name = if post { "http-post" } else { "http-get" };
+
+        self.expect_args(if post { 2 } else { 1 }, name)?;
+
+        let body = if post { Some(self.pop_string()?) } else { None };
+        let url = self.pop_string()?;
+        let agent: ureq::Agent = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .build()
+            .into();
+        let mut response = match body {
+            Some(body) => agent.post(&*url).send(&*body),
+            None => agent.get(&*url).call(),
+        }.map_err(|e| format!("Couldn't request {url}: {e}"))?;
+        let body = response.body_mut().read_to_string()
+            .map_err(|e| format!("Couldn't read response from {url}: {e}"))?;
+
+        self.push(Value::String(body.into()))?;
+        self.push(Value::Int(response.status().as_u16().into()))
+    }
+
+    /// Connects to a host and port, or listens on them if `listen` is set.
+    #[cfg(feature = "tcp")]
+    fn tcp_open(&mut self, listen: bool) -> Result<()> {
+        self.expect_args(2, if listen { "tcp-listen" } else { "tcp-connect" })?;
+
+        let port = match self.pop()? {
+            Value::Int(n) => u16::try_from(n)
+                .map_err(|_| format!("Invalid port {n}"))?,
+            v => return Err(InterpreterError::TypeError(
+                format!("Expected a port, got {v}")
+            )),
+        };
+        let host = self.pop_string()?;
+
+        self.push(Value::Socket(match listen {
+            true => Socket::listen(&host, port)?,
+            false => Socket::connect(&host, port)?,
+        }))
+    }
+
+    #[cfg(feature = "tcp")]
+    fn tcp_accept(&mut self) -> Result<()> {
+        self.expect_args(1, "tcp-accept")?;
+
+        let socket = self.pop_socket()?.accept()?;
+
+        self.push(Value::Socket(socket))
+    }
+
+    #[cfg(feature = "tcp")]
+    fn tcp_send(&mut self) -> Result<()> {
+        self.expect_args(2, "tcp-send")?;
+
+        let socket = self.pop_socket()?;
+
+        Ok(socket.send(&self.pop_string()?)?)
+    }
+
+    #[cfg(feature = "tcp")]
+    fn tcp_recv(&mut self) -> Result<()> {
+        self.expect_args(1, "tcp-recv")?;
+
+        match self.pop_socket()?.recv()? {
+            Some(line) => self.push(Value::String(line.into())),
+            None => self.push(Value::Nil),
+        }
+    }
+
+    #[cfg(feature = "tcp")]
+    fn pop_socket(&mut self) -> Result<Socket> {
+        match self.pop()? {
+            Value::Socket(s) => Ok(s),
+            v => Err(InterpreterError::TypeError(
+                format!("Expected a socket, got {v}")
+            )),
+        }
+    }
+
+    #[cfg(feature = "csv")]
+    fn pop_separator(&mut self) -> Result<u8> {
+        match self.pop_string()?.as_bytes() {
+            &[sep] => Ok(sep),
+            _ => Err("Expected a single-byte separator".into()),
+        }
+    }
+
+    #[cfg(feature = "regex")]
+    fn pop_regex(&mut self) -> Result<regex::Regex> {
+        let pattern = self.pop_string()?;
+
+        regex::Regex::new(&pattern)
+            .map_err(|e| format!("Invalid regular expression: {e}").into())
+    }
+
+    /// Pops a non-empty list and splits it into its tail and head.
+    fn pop_uncons(&mut self, name: &str) -> Result<(Value, Value)> {
+        let mut list = self.pop_list()?.to_vec();
+
+        if list.is_empty() {
+            return Err(
+                format!("Can't take the `{name}` of an empty list").into()
+            );
+        }
+
+        let x = list.remove(0);
+
+        Ok((Value::List(list.into()), x))
+    }
+
+    fn dup2(&mut self) -> Result<()> {
+        self.expect_args(2, "2dup")?;
+
+        self.stack.extend_from_within(self.stack.len() - 2..);
+
+        Ok(())
+    }
+
+    fn push(&mut self, value: Value) -> Result<()> {
+        self.stack.push(value);
+
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Value> {
+        self.expect_args(1, "pop")?;
+        Ok(self.stack.pop().unwrap())
+    }
+
+    fn pop_procedure(&mut self) -> Result<Procedure> {
+        match self.pop()? {
+            Value::Procedure(p) => Ok(p),
+            v => Err(InterpreterError::TypeError(
+                format!("Can't evaluate {v}")
+            ))
+        }
+    }
+
+    fn pop_list(&mut self) -> Result<Rc<[Value]>> {
+        match self.pop()? {
+            Value::List(l) => Ok(l),
+            v => Err(InterpreterError::TypeError(
+                format!("Expected a list, got {v}")
+            ))
+        }
+    }
+
+    fn pop_set(&mut self) -> Result<Rc<[Value]>> {
+        match self.pop()? {
+            Value::Set(s) => Ok(s),
+            v => Err(InterpreterError::TypeError(
+                format!("Expected a set, got {v}")
+            ))
+        }
+    }
+
+    fn pop_string(&mut self) -> Result<Rc<str>> {
+        match self.pop()? {
+            Value::String(s) => Ok(s),
+            v => Err(InterpreterError::TypeError(
+                format!("Expected a string, got {v}")
+            ))
+        }
+    }
+
+    fn expect_args(&self, args: usize, name: &str) -> Result<()> {
+        match self.stack.len() {
+            n if n < args => Err(InterpreterError::StackUnderflow {
+                word: name.into(),
+                expected: args,
+                got: n,
+            }),
+            _ => Ok(())
+        }
+    }
+}
+
+/// Replaces the words for which `f` returns a statement, leaving words which
+/// are bound by inner locals alone.
+fn map_words(
+    statements: &[Statement],
+    f: &dyn Fn(&str, Span) -> Option<Statement>,
+) -> Rc<[Statement]> {
+    let procedure = |p: &Procedure| Procedure(map_words(&p.0, f));
+
+    statements.iter().map(|s| match s {
+        Statement::Word(w, span) =>
+            f(w.as_str(), *span).unwrap_or_else(|| s.clone()),
+        Statement::Expression(e) =>
+            Statement::Expression(map_expression_words(e, f)),
+        Statement::Value(Value::Procedure(p)) =>
+            Statement::Value(Value::Procedure(procedure(p))),
+        Statement::Definition { identifier, effect, procedure: p } =>
+            Statement::Definition {
+                identifier: *identifier,
+                effect: effect.clone(),
+                procedure: procedure(p),
+            },
+        Statement::Locals { names, body } => {
+            let free = |w: &str, span| match names.iter().any(|n| n == w) {
+                true => None,
+                false => f(w, span),
+            };
+
+            Statement::Locals {
+                names: names.clone(),
+                body: Procedure(map_words(&body.0, &free)),
+            }
+        }
+        s => s.clone(),
+    }).collect()
+}
+
+/// Evaluates an expression. `recurse` in a procedure literal, including in
+/// the procedures nested in it, evaluates the literal again.
+pub(crate) fn evaluate_expression(expression: &Expression) -> Value {
+    match expression {
+        Expression::Procedure(p) if recurses(&p.0) =>
+            Value::Procedure(Procedure(bind_literal(p))),
+        e => e.clone().into(),
+    }
+}
+
+/// Binds `recurse` in a procedure literal to the literal.
+fn bind_literal(literal: &Procedure) -> Rc<[Statement]> {
+    let recursion = Rc::new(Recursion {
+        literal: literal.clone(),
+        bound: OnceCell::new(),
+    });
+    let again = Statement::Recurse(recursion.clone());
+    let bound = bind_recurse(&literal.0, &again);
+
+    // The cell was just created, so it can't be set yet.
+    let _ = recursion.bound.set(Rc::downgrade(&bound));
+
+    bound
+}
+
+/// Replaces `recurse` by `again`, also in nested procedure literals. These are
+/// turned into values, so evaluating them doesn't bind `recurse` again.
+fn bind_recurse(statements: &[Statement], again: &Statement)
+-> Rc<[Statement]> {
+    fn expression(e: &Expression, again: &Statement) -> Value {
+        match e {
+            Expression::Procedure(p) =>
+                Value::Procedure(Procedure(bind_recurse(&p.0, again))),
+            Expression::List(l) =>
+                Value::List(l.iter().map(|e| expression(e, again)).collect()),
+            e => e.clone().into(),
+        }
+    }
+
+    statements.iter().map(|s| match s {
+        Statement::Word(w, _) if w.as_str() == "recurse" => again.clone(),
+        Statement::Expression(e) => Statement::Value(expression(e, again)),
+        Statement::Locals { names, body } => Statement::Locals {
+            names: names.clone(),
+            body: Procedure(bind_recurse(&body.0, again)),
+        },
+        s => s.clone(),
+    }).collect()
+}
+
+fn map_expression_words(
+    expression: &Expression,
+    f: &dyn Fn(&str, Span) -> Option<Statement>,
+) -> Expression {
+    match expression {
+        Expression::Procedure(p) =>
+            Expression::Procedure(Procedure(map_words(&p.0, f))),
+        Expression::List(l) => Expression::List(
+            l.iter().map(|e| map_expression_words(e, f)).collect()
+        ),
+        e => e.clone(),
+    }
+}
+
+/// Resolves a possibly negative slice index into a sequence of length `len`.
+fn slice_index(n: Value, len: usize) -> Result<usize> {
+    let n = match n.as_integer() {
+        Some(n) => n,
+        None => return Err(InterpreterError::TypeError(
+            format!("Can't slice at {n}")
+        ))
+    };
+    let i = if n < 0 { len as i64 + n } else { n };
+
+    match usize::try_from(i) {
+        Ok(i) if i <= len => Ok(i),
+        _ => Err(InterpreterError::IndexOutOfBounds { index: n, len })
+    }
+}
+
+/// Wraps a pushed value for `quot->list` as `[:quote value]` if it would
+/// otherwise be read back as a word or as another quoted value.
+fn quote(value: Value) -> Value {
+    match &value {
+        Value::Symbol(_) => {}
+        Value::List(l) if unquote(l).is_some() => {}
+        _ => return value,
+    }
+
+    Value::List([Value::Symbol(Symbol::new("quote")), value].into())
+}
+
+/// The value in a list written `[:quote value]`.
+fn unquote(list: &[Value]) -> Option<&Value> {
+    match list {
+        [Value::Symbol(q), value] if q.as_str() == "quote" => Some(value),
+        _ => None,
+    }
+}
+
+/// The flag a conditional named `name` branches on.
+fn flag_of(flag: Value, name: &str) -> Result<bool> {
+    match flag {
+        Value::Bool(b) => Ok(b),
+        v => Err(InterpreterError::TypeError(
+            format!("Flag of `{name}` must be a bool, got {v}")
+        ))
+    }
+}
+
+/// Compares values like `=`: numbers of different types are equal if they
+/// have the same value.
+fn equal(a: &Value, b: &Value) -> bool {
+    a.partial_cmp(b) == Some(Ordering::Equal)
+}
+
+/// Sorts values and removes duplicates to make a set.
+fn to_set(values: Vec<Value>) -> Result<Rc<[Value]>> {
+    let keyed = values.into_iter().map(|x| (x.clone(), x)).collect();
+    let mut set = sort_by_key(keyed)?;
+
+    set.dedup_by(|a, b| equal(a, b));
+
+    Ok(set.into())
+}
+
+/// Binary searches a set for `x`, treating numerically equal values as
+/// equal like `=` does.
+fn search_set(
+    set: &[Value],
+    x: &Value,
+) -> Result<std::result::Result<usize, usize>> {
+    match x.partial_cmp(x) {
+        Some(_) => Ok(set.binary_search_by(|y| {
+            y.partial_cmp(x).unwrap_or(Ordering::Less)
+        })),
+        None => Err(InterpreterError::TypeError(
+            format!("Can't compare {x} with the elements of a set")
+        )),
+    }
+}
+
+/// Checks whether `x` is in a set, comparing like `search_set`.
+fn in_set(set: &[Value], x: &Value) -> bool {
+    matches!(search_set(set, x), Ok(Ok(_)))
+}
+
+#[cfg(feature = "serde_json")]
+fn from_json(json: serde_json::Value) -> Value {
+    use serde_json::Value as Json;
+
+    match json {
+        Json::Null => Value::Nil,
+        Json::Bool(b) => Value::Bool(b),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => Value::Int(i),
+            #[cfg(feature = "num-bigint")]
+            None if n.is_u64() => Value::from(
+                num_bigint::BigInt::from(n.as_u64().unwrap_or_default())
+            ),
+            None => Value::Number(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Json::String(s) => Value::String(s.into()),
+        Json::Array(xs) => Value::List(xs.into_iter().map(from_json).collect()),
+        Json::Object(fields) => Value::List([
+            Value::Symbol(Symbol::new("object")),
+            Value::List(
+                fields.into_iter()
+                    .map(|(k, v)| Value::List(
+                        [Value::String(k.into()), from_json(v)].into()
+                    ))
+                    .collect()
+            ),
+        ].into()),
+    }
+}
+
+#[cfg(feature = "serde_json")]
+fn to_json(value: &Value) -> Result<serde_json::Value> {
+    use serde_json::Value as Json;
+
+    let object = |fields: Vec<(String, &Value)>| fields.into_iter()
+        .map(|(k, v)| Ok((k, to_json(v)?)))
+        .collect::<Result<_>>()
+        .map(Json::Object);
+
+    match value {
+        Value::Nil => Ok(Json::Null),
+        Value::Bool(b) => Ok(Json::Bool(*b)),
+        Value::Int(i) => Ok(Json::from(*i)),
+        Value::String(s) => Ok(Json::String(s.to_string())),
+        Value::Symbol(s) => Ok(Json::String(s.as_str().into())),
+        Value::Struct { fields, .. } =>
+            object(fields.iter().map(|(k, v)| (k.clone(), v)).collect()),
+        Value::List(xs) => match &xs[..] {
+            [Value::Symbol(tag), Value::List(pairs)]
+                if tag.as_str() == "object" => pairs.iter()
+                    .map(|x| match x {
+                        Value::List(pair) => match &**pair {
+                            [Value::String(k), v] => Ok((k.to_string(), v)),
+                            _ => Err(x),
+                        },
+                        _ => Err(x),
+                    })
+                    .collect::<std::result::Result<_, _>>()
+                    .map_err(|x| InterpreterError::TypeError(format!(
+                        "Can't encode {x} as a JSON object member, \
+                         expected a `[key value]` pair with a string key"
+                    )))
+                    .and_then(object),
+            _ => xs.iter().map(to_json).collect::<Result<_>>()
+                .map(Json::Array),
+        },
+        Value::Set(xs) =>
+            xs.iter().map(to_json).collect::<Result<_>>().map(Json::Array),
+        _ => value.as_f64()
+            .and_then(serde_json::Number::from_f64)
+            .map(Json::Number)
+            .ok_or_else(|| InterpreterError::TypeError(
+                format!("Can't encode {value} as JSON")
+            )),
+    }
+}
+
+/// Stably sorts `(key, value)` pairs by their keys and returns the values.
+fn sort_by_key(mut keyed: Vec<(Value, Value)>) -> Result<Vec<Value>> {
+    let mut error = None;
+
+    keyed.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or_else(|| {
+        error.get_or_insert_with(|| format!("Can't compare {a} and {b}"));
+        Ordering::Equal
+    }));
+
+    match error {
+        Some(e) => Err(InterpreterError::TypeError(e)),
+        None => Ok(keyed.into_iter().map(|(_, x)| x).collect()),
+    }
+}
+
+fn range_too_long(start: &Value, end: &Value) -> InterpreterError {
+    format!(
+        "Range from {start} to {end} would have more than {RANGE_LIMIT} \
+         elements"
+    ).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::PathBuf,
+        rc::Rc,
+        sync::{atomic::{AtomicBool, Ordering}, Arc},
+        thread,
+        time::Duration,
+    };
+
+    use crate::{
+        program, Interpreter, InterpreterError, Symbol, Value, FRAME_LIMIT,
+        NESTING_LIMIT, STACK_LIMIT,
+    };
+
+    /// Runs a program, dropping the location from errors.
+    fn run(input: &str) -> Result<Vec<Value>, InterpreterError> {
+        let (_, program) = program(input).expect("Couldn't parse program");
+        let mut interpreter = Interpreter::new(false);
+
+        interpreter.run_program(program).map_err(|e| e.kind().clone())?;
+
+        Ok(interpreter.stack)
+    }
+
+    #[test]
+    fn read_line() {
+        let (_, program) =
+            program("read-line read-line read-line read-line").unwrap();
+        let mut interpreter = Interpreter::new(false)
+            .with_input(std::io::Cursor::new("a b\r\n\nc"));
+
+        interpreter.run_program(program).unwrap();
+
+        assert_eq!(interpreter.stack, vec![
+            Value::String("a b".into()),
+            Value::String("".into()),
+            Value::String("c".into()),
+            Value::Nil,
+        ]);
+    }
+
+    #[test]
+    fn files() {
+        let dir = std::env::temp_dir()
+            .join(format!("stack-files-{}", std::process::id()));
+        let path = dir.join("a.txt");
+
+        fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            run(&format!(
+                "\"hi\" \"{0}\" write-file \"{0}\" read-file",
+                path.display()
+            )),
+            Ok(vec![Value::String("hi".into())])
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hi");
+        assert!(run(&format!("\"{}\" read-file", dir.join("b").display()))
+                .is_err());
+        assert!(run(&format!("1 \"{}\" write-file", path.display())).is_err());
+        assert_eq!(
+            run(&format!(
+                "\" there\" \"{0}/a.txt\" append-file \
+                 \"{0}/sub/x\" mkdir \"{0}/sub\" file-exists? \
+                 \"{0}\" list-dir \"{0}/a.txt\" read-file \
+                 \"{0}/a.txt\" delete-file \"{0}/a.txt\" file-exists?",
+                dir.display()
+            )),
+            Ok(vec![
+                Value::Bool(true),
+                Value::List([
+                    Value::String("a.txt".into()),
+                    Value::String("sub".into()),
+                ].into()),
+                Value::String("hi there".into()),
+                Value::Bool(false),
+            ])
+        );
+        assert!(run(&format!("\"{}/nope\" delete-file", dir.display()))
+                .is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            run("1 +"),
+            Err(InterpreterError::StackUnderflow {
+                word: "+".into(),
+                expected: 2,
+                got: 1,
+            })
+        );
+        assert_eq!(
+            run("missing"),
+            Err(InterpreterError::UnresolvedWord("missing".into()))
+        );
+        assert_eq!(
+            run("2 [1 2] nth"),
+            Err(InterpreterError::IndexOutOfBounds { index: 2, len: 2 })
+        );
+        assert_eq!(
+            run("[1 2] 0 3 slice"),
+            Err(InterpreterError::IndexOutOfBounds { index: 3, len: 2 })
+        );
+
+        for input in [
+            "1 sort", "1 \"a\" +", "nil 1 -", "[] 2 *", "1 :a /", "1 nil mod",
+            "\"a\" 1 pow", "1 [] min", "nil abs", "1 eval", "1 2 keep",
+            "1 [1] cleave", "true 1 2 if", "[1] :a nth", "[1] nil 1 slice",
+            "1 1.5 band", "1 -1 shl", "1 len", "1 0 1 slice", "1 2 contains?",
+            "1 2 set", "\"missing\" get", "1 \"m\" assert", ":a { } times",
+            ":a 2 range", ":a pick", "1 -1 roll", "1 [1] cond", "1 [[1]] cond",
+            "true { 1 } and-then", "[1] { } filter", "\"ab\" :a 1 substring",
+        ] {
+            assert!(
+                matches!(run(input), Err(InterpreterError::TypeError(_))),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn error_spans() {
+        let error = |input| Interpreter::new(false)
+            .run_program(program(input).unwrap().1)
+            .unwrap_err()
+            .to_string();
+
+        assert_eq!(
+            error("1\n  2 +\n+"),
+            "Operation `+` expected 2 argument(s), got 1 at 3:1"
+        );
+        assert_eq!(
+            error("def inc { 1 + }\n\"a\" inc"),
+            "Can't add \"a\" and 1 at 1:13 (in inc)"
+        );
+        assert_eq!(
+            error("def check { 1 + 0 }\ndef fizzbuzz { check drop }\n\
+                   { :x fizzbuzz } { drop } try :y fizzbuzz"),
+            "Can't add :y and 1 at 1:15 (in fizzbuzz → in check)"
+        );
+        assert_eq!(
+            error("def down { dup 0 = { \"a\" + } { 1 - down } if }\n\
+                   def f { 3 down drop }\n2 f 1 +"),
+            "Can't add 0 and \"a\" at 1:26 (in f → in down)"
+        );
+        assert_eq!(
+            error("{ [1] { nope } map } { } try nope"),
+            "Couldn't resolve identifier \"nope\" at 1:30"
+        );
+    }
+
+    #[test]
+    fn bytecode() {
+        let run = |input, bytecode| {
+            let mut interpreter = Interpreter::new(false)
+                .with_bytecode(bytecode);
+
+            match interpreter.run_program(program(input).unwrap().1) {
+                Ok(_) => Ok(interpreter.stack),
+                Err(e) => Err(e.to_string()),
+            }
+        };
+
+        for input in [
+            "def down { dup 0 = { } { 1 - down } if } 100000 down",
+            "def fact { dup 1 <= { drop 1 } { dup 1 - fact * } if } 10 fact",
+            "1 { 2 } { 3 } if true { 4 } when false { 5 } unless",
+            "{ 1 } { 2 } if",
+            "true { 1 } when",
+            "def g { true { \"a\" 1 + } when }\n0 g",
+            "def f ( a -- a ) { { 1 } { 2 } if drop } false f",
+            "true { 1 } { 2 } 3 drop if",
+        ] {
+            assert_eq!(run(input, true), run(input, false), "{input}");
+        }
+
+        assert_eq!(
+            run("def fact { dup 1 <= { drop 1 } { dup 1 - fact * } if }\n\
+                 5 fact", true),
+            Ok(vec![Value::Int(120)])
+        );
+    }
+
+    #[test]
+    fn step_limit() {
+        let run = |input, limit, bytecode| Interpreter::new(false)
+            .with_step_limit(limit)
+            .with_bytecode(bytecode)
+            .run_program(program(input).unwrap().1)
+            .map_err(|e| e.kind().clone());
+
+        for bytecode in [true, false] {
+            assert_eq!(
+                run("def loop { loop } loop", 1000, bytecode),
+                Err(InterpreterError::StepLimit(1000))
+            );
+            assert_eq!(
+                run("{ 0 10 { 1 + } times } { } try", 20, bytecode),
+                Err(InterpreterError::StepLimit(20))
+            );
+            assert_eq!(run("1 2 +", 3, bytecode), Ok(Some(Value::Int(3))));
+            assert_eq!(
+                run("1 2 +", 2, bytecode),
+                Err(InterpreterError::StepLimit(2))
+            );
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let run = |input, mut interpreter: Interpreter| interpreter
+            .run_program(program(input).unwrap().1)
+            .map_err(|e| e.kind().clone());
+        let exhausted = |resource, limit| {
+            Err(InterpreterError::ResourceExhausted { resource, limit })
+        };
+
+        assert_eq!(
+            run("def f { 1 f } f", Interpreter::new(false)
+                .with_stack_limit(50)),
+            exhausted("values on the stack", 50)
+        );
+        assert_eq!(
+            run("1 2 3", Interpreter::new(false).with_stack_limit(3)),
+            Ok(Some(Value::Int(3)))
+        );
+        assert_eq!(
+            run("{ [1 2 3] spread } { } try",
+                Interpreter::new(false).with_stack_limit(2)),
+            exhausted("values on the stack", 2)
+        );
+        assert_eq!(
+            run("def f { f 1 } f", Interpreter::new(false)
+                .with_frame_limit(50)),
+            exhausted("frames", 50)
+        );
+        assert_eq!(
+            run("def f { [1] { f } map } f",
+                Interpreter::new(false).with_frame_limit(50)),
+            exhausted("frames", 50)
+        );
+        assert_eq!(
+            run("def down { dup 0 = { } { 1 - down } if } 1000 down",
+                Interpreter::new(false).with_frame_limit(5)),
+            Ok(Some(Value::Int(0)))
+        );
+
+        // Without a frame limit, the nested calls of `map` are still
+        // bounded before they overflow the native stack.
+        assert_eq!(
+            run("def f { [1] { f } map } f", Interpreter::new(false)),
+            exhausted("nested calls", NESTING_LIMIT)
+        );
+        assert_eq!(
+            run("def f { [1] { f } filter } f", Interpreter::new(false)),
+            exhausted("nested calls", NESTING_LIMIT)
+        );
+        assert_eq!(
+            run("def f { [[1 2]] { f } sort-by } f", Interpreter::new(false)),
+            exhausted("nested calls", NESTING_LIMIT)
+        );
+        assert_eq!(Interpreter::new(false).stack_limit, Some(STACK_LIMIT));
+        assert_eq!(Interpreter::new(false).frame_limit, Some(FRAME_LIMIT));
+    }
+
+    #[test]
+    fn interrupt() {
+        let flag = Arc::new(AtomicBool::new(true));
+        let mut interpreter = Interpreter::new(false)
+            .with_interrupt(flag.clone());
+        let setter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            flag.store(true, Ordering::Relaxed);
+        });
+        let res = interpreter
+            .run_program(program("1 { def loop { loop } loop } { } try")
+                .unwrap().1)
+            .map_err(|e| e.kind().clone());
+
+        setter.join().unwrap();
+        assert_eq!(res, Err(InterpreterError::Interrupted));
+        assert_eq!(
+            interpreter.run_program(program("2 +").unwrap().1),
+            Ok(Some(Value::Int(3)))
+        );
+    }
+
+    #[test]
+    fn snapshots() {
+        let run = |interpreter: &mut Interpreter, input| interpreter
+            .run_program(program(input).unwrap().1)
+            .map_err(|e| e.kind().clone());
+        let mut interpreter = Interpreter::new(false);
+
+        run(&mut interpreter, "var x 5 x set def f { x get 1 + } 2").unwrap();
+
+        let snapshot = interpreter.snapshot();
+
+        run(&mut interpreter, "def f { 0 } 7 x set").unwrap();
+        interpreter.restore(snapshot.clone());
+        assert_eq!(run(&mut interpreter, "f"), Ok(Some(Value::Int(6))));
+        assert_eq!(interpreter.stack, [Value::Int(2), Value::Int(6)]);
+
+        let mut fresh = Interpreter::new(false);
+
+        fresh.restore(snapshot);
+        assert_eq!(run(&mut fresh, "drop f"), Ok(Some(Value::Int(6))));
+    }
+
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    #[test]
+    fn serialized_snapshots() {
+        let mut interpreter = Interpreter::new(false);
+
+        interpreter
+            .run_program(program("def f { [1 :a \"b\"] } f { 1 2 + }")
+                .unwrap().1)
+            .unwrap();
+
+        let snapshot = interpreter.snapshot();
+        let json = serde_json::to_string(&snapshot).unwrap();
+
+        let restored: crate::Snapshot = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored, snapshot);
+    }
+
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    #[test]
+    fn serialized_recursion() {
+        let mut interpreter = Interpreter::new(false);
+
+        interpreter
+            .run_program(program("{ dup 0 > { 1 - recurse } when }")
+                .unwrap().1)
+            .unwrap();
+
+        let json = serde_json::to_string(&interpreter.snapshot()).unwrap();
+        let mut restored = Interpreter::new(false);
+
+        restored.restore(serde_json::from_str(&json).unwrap());
+        restored.run_program(program("3 swap eval").unwrap().1).unwrap();
+
+        assert_eq!(restored.stack, [Value::Int(0)]);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn images() {
+        let path = std::env::temp_dir()
+            .join(format!("stack-image-{}.json", std::process::id()));
+
+        assert_eq!(
+            run(&format!(
+                "def sq ( a -- b ) {{ dup * }} def f {{ 3 sq }} \"{}\" \
+                 save-image",
+                path.display()
+            )),
+            Ok(vec![])
+        );
+        assert_eq!(
+            run(&format!(
+                "def f {{ 0 }} \"{}\" load-image f",
+                path.display()
+            )),
+            Ok(vec![Value::Int(9)])
+        );
+        assert_eq!(
+            run(&format!(
+                "var n 5 n set \"{0}\" save-image 0 n set \"{0}\" load-image \
+                 n get",
+                path.display()
+            )),
+            Ok(vec![Value::Int(5)])
+        );
+        assert_eq!(
+            run(&format!("\"{}\" load-image n get", path.display())),
+            Ok(vec![Value::Int(5)])
+        );
+        assert_eq!(
+            run(&format!(
+                "var x {{ dup 0 > {{ 1 - recurse }} when }} x set \"{}\" \
+                 save-image",
+                path.display()
+            )),
+            Ok(vec![])
+        );
+        assert_eq!(
+            run(&format!("\"{}\" load-image 3 x get eval", path.display())),
+            Ok(vec![Value::Int(0)])
+        );
+        assert!(run("\"/nonexistent/image\" load-image").is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn inlining() {
+        let run = |input, inline| {
+            let mut interpreter = Interpreter::new(false)
+                .with_inlining(inline);
+
+            interpreter.run_program(program(input).unwrap().1)
+                .map(|_| interpreter.stack)
+        };
+
+        for input in [
+            "def sq { dup * } def f { 3 sq sq } f",
+            "def g { 1 } def f { g } f def g { 2 } f",
+            "def neg? { 0 < } def abs { dup neg? { 0 swap - } when } \
+             def f { 0 5 - abs } f",
+            "def down { dup 0 = { } { 1 - down } if } def f { 3 down } f",
+            "def g ( a -- a a ) { dup } def f { 1 g } f",
+            "def g ( a -- a a ) { drop } def f { 1 g } f",
+            "vocab m def g { 1 } def f { g } f",
+            "def inc { 1 + } def f { inc } \"a\" f",
+            "def inc { 1 + } def g { inc } def f { g 2 } \"a\" f",
+            "vocab m def inc { 1 + } def f { inc dup 1 = { } { inc } if } 0 f",
+            "def inc { 1 + } def f { dup { } { inc } if } \"a\" false f",
+            "def inc { 1 + } def f ( a -- a ) { inc } \"a\" f",
+        ] {
+            assert_eq!(run(input, true), run(input, false), "{input}");
+        }
+    }
+
+    #[test]
+    fn exit() {
+        let mut interpreter = Interpreter::new(false);
+        let (_, exits) = program("1 { 3 exit } { drop 2 } try 4").unwrap();
+
+        assert_eq!(
+            interpreter.run_program(exits),
+            Err(InterpreterError::Exit(3))
+        );
+        assert_eq!(interpreter.stack, vec![Value::Int(1)]);
+        assert!(run("1.5 exit").is_err());
+    }
+
+    #[test]
+    fn time() {
+        let stack = run("now monotonic 20 sleep monotonic").unwrap();
+
+        let [Value::Number(now), Value::Number(a), Value::Number(b)] =
+            stack[..]
+        else {
+            panic!("Unexpected stack {stack:?}");
+        };
+
+        assert!(now > 1.7e9);
+        assert!(b - a >= 20.0);
+        assert!(run("-1 sleep").is_err());
+        assert!(run("\"1\" sleep").is_err());
+    }
+
+    #[test]
+    fn args() {
+        let mut interpreter = Interpreter::new(false)
+            .with_args(vec!["a".into(), "b c".into()]);
+
+        interpreter.run_program(program("args").unwrap().1).unwrap();
+
+        assert_eq!(interpreter.stack, vec![Value::List([
+            Value::String("a".into()),
+            Value::String("b c".into()),
+        ].into())]);
+        assert_eq!(run("args"), Ok(vec![Value::List([].into())]));
+    }
+
+    #[test]
+    fn env() {
+        let name = format!("STACK_TEST_{}", std::process::id());
+
+        assert_eq!(
+            run(&format!("\"{name}\" env \"a b\" \"{name}\" set-env \
+                          \"{name}\" env")),
+            Ok(vec![Value::Nil, Value::String("a b".into())])
+        );
+        assert!(run("\"x\" \"a=b\" set-env").is_err());
+        assert!(run("1 env").is_err());
+    }
+
+    #[test]
+    fn pick_roll() {
+        assert_eq!(
+            run("1 2 3 0 pick 4 3 pick"),
+            Ok([1, 2, 3, 3, 4, 2].map(Value::Int).to_vec())
+        );
+        assert_eq!(
+            run("1 2 3 4 3 roll 0 roll"),
+            Ok([2, 3, 4, 1].map(Value::Int).to_vec())
+        );
+        assert!(run("1 2 2 pick").is_err());
+        assert!(run("1 2 0 1 - roll").is_err());
+    }
+
+    #[test]
+    fn import() {
+        fn run_with(input: &str, search_path: Vec<PathBuf>)
+        -> Result<Vec<Value>, InterpreterError> {
+            let (_, program) = program(input).expect("Couldn't parse program");
+            let mut interpreter = Interpreter::new(false)
+                .with_search_path(search_path);
+
+            interpreter.run_program(program)?;
+
+            Ok(interpreter.stack)
+        }
+
+        let dir = std::env::temp_dir()
+            .join(format!("stack-import-{}", std::process::id()));
+
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("lib/square.stk"), "def square { dup * }\n")
+            .unwrap();
+        fs::write(
+            dir.join("lib/cube.stk"),
+            "import \"square.stk\"\ndef cube { dup square * }\n"
+        ).unwrap();
+        fs::write(dir.join("a.stk"), "import \"b.stk\"\n").unwrap();
+        fs::write(dir.join("b.stk"), "import \"a.stk\"\n").unwrap();
+
+        assert_eq!(
+            run_with(
+                "import \"cube.stk\" import \"square.stk\" 2 cube 3 square",
+                vec![dir.join("lib")]
+            ),
+            Ok(vec![Value::Int(8), Value::Int(9)])
+        );
+        assert!(run_with("import \"a.stk\"", vec![dir.clone()])
+            .is_err_and(|e| e.to_string().contains("cycle")));
+        assert!(run_with("import \"missing.stk\"", vec![dir.clone()])
+            .is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn throw_try() {
+        assert_eq!(
+            run("1 { 2 :oops throw 3 } { } try"),
+            Ok(vec![Value::Int(1), Value::Symbol(Symbol::new("oops"))])
+        );
+        assert_eq!(
+            run("{ 1 0 mod } { \"caught: \" swap + } try"),
+            Ok(vec![Value::String("caught: Division by zero".into())])
+        );
+        assert_eq!(
+            run("{ 1 2 + } { drop 0 } try"),
+            Ok(vec![Value::Int(3)])
+        );
+        assert_eq!(
+            run("{ { :inner throw } { drop :outer throw } try } { } try"),
+            Ok(vec![Value::Symbol(Symbol::new("outer"))])
+        );
+        assert_eq!(
+            run("\"boom\" throw"),
+            Err(InterpreterError::Thrown("boom".into()))
+        );
+    }
+
+    #[test]
+    fn when() {
+        assert_eq!(
+            run("0 3 - dup 0 < { 0 swap - } when"),
+            Ok(vec![Value::Int(3)])
+        );
+        assert_eq!(
+            run("3 dup 0 < { 0 swap - } when"),
+            Ok(vec![Value::Int(3)])
+        );
+        assert_eq!(
+            run("1 dup 2 > { 10 * } unless"),
+            Ok(vec![Value::Int(10)])
+        );
+        assert_eq!(run("3 dup 2 > { 10 * } unless"), Ok(vec![Value::Int(3)]));
+
+        for input in [
+            "nil { 1 } unless", "0 { 1 } when", "[] { 1 } { 2 } if",
+            "def f { { 1 } unless } nil f", "def f { { 1 } when } 1 f",
+            "def f { { 1 } { 2 } if } :a f",
+        ] {
+            assert!(
+                matches!(run(input), Err(InterpreterError::TypeError(_))),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn cond() {
+        let sign = "[[{ 0 < } { drop :negative }] \
+                     [{ 0 = } { drop :zero }] \
+                     { drop :positive }] cond";
+
+        assert_eq!(
+            run(&format!("0 5 - {sign} 0 {sign} 5 {sign}")),
+            Ok(vec![
+                Value::Symbol(Symbol::new("negative")),
+                Value::Symbol(Symbol::new("zero")),
+                Value::Symbol(Symbol::new("positive")),
+            ])
+        );
+        assert_eq!(
+            run("3 [[{ 2 > } { 10 * }]] cond"),
+            Ok(vec![Value::Int(30)])
+        );
+        assert!(run("1 [[{ 2 > } { 10 * }]] cond").is_err());
+        assert!(run("1 [[{ 2 > }]] cond").is_err());
+        assert!(matches!(
+            run("5 [[{ drop 1 } { :yes }] { :no }] cond"),
+            Err(InterpreterError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn assert() {
+        assert_eq!(run("1 1 = \"equal\" assert"), Ok(vec![]));
+        assert_eq!(
+            run("1 2 = \"1 = 2\" assert"),
+            Err("Assertion failed: 1 = 2".into())
+        );
+        assert!(run("1 \"not a bool\" assert").is_err());
+    }
+
+    #[test]
+    fn nil() {
+        assert_eq!(
+            run("nil nil? 1 nil? nil 5 default 3 5 default"),
+            Ok(vec![
+                Value::Bool(true), Value::Bool(false),
+                Value::Int(5), Value::Int(3),
+            ])
+        );
+        assert_eq!(
+            run("[nil nil] [true nil] 1 swap nth"),
+            Ok(vec![Value::List([Value::Nil, Value::Nil].into()), Value::Nil])
+        );
+        assert_eq!(
+            run("1 [7 8] ?nth 2 [7 8] ?nth"),
+            Ok(vec![Value::Int(8), Value::Nil])
+        );
+        assert!(run("2 [7 8] nth").is_err());
+        assert!(run("\"a\" [7 8] ?nth").is_err());
+    }
+
+    #[test]
+    fn symbols() {
+        assert_eq!(
+            run(":a :a = :a :b = :a \"a\" ="),
+            Ok(vec![Value::Bool(true), Value::Bool(false), Value::Bool(false)])
+        );
+        assert_eq!(
+            run("[:b :a :b] list->set to-string"),
+            Ok(vec![Value::String("#[ :a :b ]".into())])
+        );
+        assert_eq!(Symbol::lookup("a"), Some(Symbol::new("a")));
+        assert_eq!(Symbol::lookup("never interned"), None);
+    }
+
+    #[test]
+    fn structs() {
+        assert_eq!(
+            run("struct point x y 1 2 point dup point-y swap point-x"),
+            Ok(vec![Value::Int(2), Value::Int(1)])
+        );
+        assert_eq!(
+            run("struct point x y 1 2 point to-string"),
+            Ok(vec![Value::String("point { x: 1 y: 2 }".into())])
+        );
+        assert_eq!(
+            run("struct point x y 1 2 point 1 2 point ="),
+            Ok(vec![Value::Bool(true)])
+        );
+        assert!(run("struct point x y\nstruct size x\n1 size point-x")
+                .is_err());
+        assert_eq!(
+            run("struct p x def f { 1 } 2 f"),
+            Ok(vec![Value::Int(2), Value::Int(1)])
+        );
+        assert_eq!(
+            run("struct p x struct p y 1 p p-y"),
+            Ok(vec![Value::Int(1)])
+        );
+        assert!(run("struct point x y 1 point").is_err());
+    }
+
+    #[test]
+    fn sets() {
+        let set = |x: &[i64]| Value::Set(x.iter().copied().map(Value::Int)
+            .collect());
+
+        assert_eq!(run("[3 1 2 1] list->set"), Ok(vec![set(&[1, 2, 3])]));
+        assert_eq!(run("[1 1.0 2] list->set"), Ok(vec![set(&[1, 2])]));
+        assert_eq!(
+            run("[1] list->set 1.0 set-has? [1] list->set 1.0 set-add"),
+            Ok(vec![Value::Bool(true), set(&[1])])
+        );
+        assert_eq!(
+            run("[1 3] list->set 2 set-add [2.0] list->set intersect"),
+            Ok(vec![set(&[2])])
+        );
+        assert!(run("[1] list->set 0.0 0.0 / set-has?").is_err());
+        assert_eq!(
+            run("[1 2] list->set 3 set-add 2 set-add dup 3 set-has?"),
+            Ok(vec![set(&[1, 2, 3]), Value::Bool(true)])
+        );
+        assert_eq!(
+            run("[1 2 3] list->set [2 3 4] list->set \
+                 2dup union rotr 2dup intersect rotr difference"),
+            Ok(vec![set(&[1, 2, 3, 4]), set(&[2, 3]), set(&[1])])
+        );
+        assert_eq!(
+            run("[2 1] list->set dup set->list swap len"),
+            Ok(vec![
+                Value::List([1, 2].map(Value::Int).into()),
+                Value::Int(2),
+            ])
+        );
+        assert_eq!(
+            run("[1 2] list->set to-string"),
+            Ok(vec![Value::String("#[ 1 2 ]".into())])
+        );
+        assert!(run("[1] 1 set-add").is_err());
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(
+            run("var n n get 10 n set n get"),
+            Ok(vec![Value::Int(0), Value::Int(10)])
+        );
+        assert_eq!(
+            run("var total def add { total get + total set } \
+                 [1 2 3] { add } each total get"),
+            Ok(vec![Value::Int(6)])
+        );
+        assert!(run("1 \"nope\" set").is_err());
+        assert!(run("1 get").is_err());
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(
+            run("def origin [0 0] def greeting \"hi\" origin greeting"),
+            Ok(vec![
+                Value::List([Value::Int(0), Value::Int(0)].into()),
+                Value::String("hi".into()),
+            ])
+        );
+        assert_eq!(
+            run("def half 0.5 half 3 *"),
+            Ok(vec![Value::Number(1.5)])
+        );
+
+        for input in ["def pi 3.14159", "def e 2", "def dup { 1 }"] {
+            assert!(matches!(
+                run(input),
+                Err(e) if e.to_string().contains("builtin")
+            ));
+        }
+
+        for input in [
+            "def def { 1 }", "def var { 1 }", "def struct 1", "def vocab 1",
+            "def import { }", "def recurse { 1 }",
+        ] {
+            assert!(
+                matches!(
+                    run(input),
+                    Err(e) if e.to_string().contains("keyword")
+                ),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn vocabs() {
+        assert_eq!(
+            run("vocab a def sq { dup * } def quad { sq sq } 2 quad 3 a.sq"),
+            Ok(vec![Value::Int(16), Value::Int(9)])
+        );
+
+        let dir = std::env::temp_dir()
+            .join(format!("stack-vocabs-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("a.stk"),
+            "vocab a\ndef f { 1 + }\ndef g { f f }\n"
+        ).unwrap();
+        fs::write(
+            dir.join("b.stk"),
+            "vocab b\ndef f { 10 * }\ndef g { f f }\n"
+        ).unwrap();
+
+        let (_, imports) = program(
+            "import \"a.stk\" import \"b.stk\" 1 a.g 1 b.g 1 a.f"
+        ).unwrap();
+        let mut interpreter = Interpreter::new(false)
+            .with_search_path(vec![dir.clone()]);
+
+        assert_eq!(interpreter.run_program(imports), Ok(Some(Value::Int(2))));
+        assert_eq!(interpreter.stack, [3, 100, 2].map(Value::Int));
+        assert!(interpreter.run_program(program("1 f").unwrap().1).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unicode_words() {
+        assert_eq!(
+            run("def größe { 2 * } def π 3 π größe"),
+            Ok(vec![Value::Int(6)])
+        );
+    }
+
+    #[test]
+    fn punctuated_words() {
+        assert_eq!(
+            run("def ->pair { [] swap push swap push } \
+                 def empty? { len 0 = } \
+                 def set! { drop } \
+                 1 2 ->pair empty? 3 set! true false"),
+            Ok(vec![Value::Bool(false), Value::Bool(true), Value::Bool(false)])
+        );
+    }
+
+    #[test]
+    fn recurse() {
+        let fact = "{ dup 1 > { dup 1 - recurse * } when }";
+
+        assert_eq!(
+            run(&format!("5 {fact} eval")),
+            Ok(vec![Value::Int(120)])
+        );
+        assert_eq!(
+            run(&format!("[1 2 3 4] {fact} map")),
+            Ok(vec![Value::List([1, 2, 6, 24].map(Value::Int).into())])
+        );
+        assert_eq!(
+            run(&format!("def fact {fact} 4 fact")),
+            Ok(vec![Value::Int(24)])
+        );
+        assert_eq!(
+            run(&format!("vocab math def fact {fact} 3 math.fact")),
+            Ok(vec![Value::Int(6)])
+        );
+        assert_eq!(
+            run(&format!("{fact} to-string")),
+            Ok(vec![Value::String(fact.into())])
+        );
+        assert_eq!(
+            run("{ 1 recurse } quot->list"),
+            Ok(vec![Value::List([
+                Value::Int(1),
+                Value::Symbol(Symbol::new("recurse")),
+            ].into())])
+        );
+        // A nested procedure still recurses into the literal after leaving
+        // it.
+        assert_eq!(
+            run("0 { 1 + { recurse } } eval eval drop"),
+            Ok(vec![Value::Int(2)])
+        );
+    }
+
+    #[test]
+    fn spread() {
+        assert_eq!(
+            run("[1 2 3] spread"),
+            Ok(vec![Value::Int(1), Value::Int(2), Value::Int(3)])
+        );
+        assert_eq!(run("[] spread"), Ok(vec![]));
+        assert_eq!(
+            run("[1 2 3] { + * } apply"),
+            Ok(vec![Value::Int(5)])
+        );
+        assert!(run("1 spread").is_err());
+        assert!(run("[1] 2 apply").is_err());
+    }
+
+    #[test]
+    fn reflection() {
+        assert_eq!(
+            run("{ 1 2 + sq } quot->list"),
+            Ok(vec![Value::List([
+                Value::Int(1),
+                Value::Int(2),
+                Value::Symbol(Symbol::new("+")),
+                Value::Symbol(Symbol::new("sq")),
+            ].into())])
+        );
+        assert_eq!(
+            run("def sq { dup * } [3 :sq :dup :swap] list->quot eval"),
+            Ok(vec![Value::Int(9), Value::Int(9)])
+        );
+        assert_eq!(
+            run("{ 2 { 1 + } eval } quot->list list->quot eval"),
+            Ok(vec![Value::Int(3)])
+        );
+        assert_eq!(
+            run("{ :foo dup } quot->list"),
+            Ok(vec![Value::List([
+                Value::List([
+                    Value::Symbol(Symbol::new("quote")),
+                    Value::Symbol(Symbol::new("foo")),
+                ].into()),
+                Value::Symbol(Symbol::new("dup")),
+            ].into())])
+        );
+        assert_eq!(
+            run("{ :foo } quot->list list->quot eval"),
+            Ok(vec![Value::Symbol(Symbol::new("foo"))])
+        );
+        assert_eq!(
+            run("{ [:quote 1] } quot->list list->quot eval"),
+            Ok(vec![Value::List([
+                Value::Symbol(Symbol::new("quote")),
+                Value::Int(1),
+            ].into())])
+        );
+        assert_eq!(
+            run("[[:quote :sq]] list->quot eval"),
+            Ok(vec![Value::Symbol(Symbol::new("sq"))])
+        );
+        assert!(run("{ var x } quot->list").is_err());
+        assert!(run("1 list->quot").is_err());
+    }
+
+    #[test]
+    fn locals() {
+        assert_eq!(
+            run("1 2 {| a b | b a - } eval"),
+            Ok(vec![Value::Int(1)])
+        );
+        assert_eq!(
+            run("def hyp { {| a b | a a * b b * + sqrt } eval } 3 4 hyp"),
+            Ok(vec![Value::Number(5.0)])
+        );
+        assert_eq!(
+            run("10 {| n | [1 2] { n + } map } eval"),
+            Ok(vec![Value::List([11, 12].map(Value::Int).into())])
+        );
+        assert_eq!(
+            run("1 2 {| x | 3 {| x | x } eval x } eval"),
+            Ok([1, 3, 2].map(Value::Int).to_vec())
+        );
+        assert!(run("{| x | x } eval").is_err());
+    }
+
+    #[test]
+    fn declared_stack_effects() {
+        assert_eq!(
+            run("def inc ( n -- n ) { 1 + } 1 inc inc"),
+            Ok(vec![Value::Int(3)])
+        );
+        assert_eq!(
+            run("def countdown ( n -- ) { dup 0 = { drop } { 1 - countdown } \
+                 if } 1000 countdown depth"),
+            Ok(vec![Value::Int(0)])
+        );
+        assert!(run("def inc ( n -- n ) { 1 + } inc").is_err());
+        assert!(run("def bad ( n -- n ) { dup } 1 bad").is_err());
+        assert_eq!(
+            run("def f ( n -- n ) { dup } def f { dup } 1 f"),
+            Ok(vec![Value::Int(1), Value::Int(1)])
+        );
+    }
+
+    #[test]
+    fn shuffle_words() {
+        assert_eq!(
+            run("1 2 2dup"),
+            Ok([1, 2, 1, 2].map(Value::Int).to_vec())
+        );
+        assert_eq!(run("1 2 3 2drop"), Ok(vec![Value::Int(1)]));
+        assert_eq!(run("1 2 3 4 3drop"), Ok(vec![Value::Int(1)]));
+        assert_eq!(
+            run("1 2 3 rotl"),
+            Ok([2, 3, 1].map(Value::Int).to_vec())
+        );
+        assert_eq!(
+            run("1 2 3 rotr"),
+            Ok([3, 1, 2].map(Value::Int).to_vec())
+        );
+        assert!(run("1 2dup").is_err());
+        assert!(run("1 2drop").is_err());
+        assert!(run("1 2 3drop").is_err());
+        assert!(run("1 2 rotl").is_err());
+        assert!(run("1 2 rotr").is_err());
+    }
+
+    #[test]
+    fn nip_tuck() {
+        assert_eq!(run("1 2 nip"), Ok(vec![Value::Int(2)]));
+        assert_eq!(
+            run("1 2 tuck"),
+            Ok([2, 1, 2].map(Value::Int).to_vec())
+        );
+        assert!(run("1 nip").is_err());
+        assert!(run("1 tuck").is_err());
+    }
+
+    #[test]
+    fn sharing() {
+        let stack = run("[1 2 3] dup \"abc\" dup").unwrap();
+
+        match &stack[..] {
+            [Value::List(a), Value::List(b), Value::String(c), Value::String(d)]
+                => assert!(Rc::ptr_eq(a, b) && Rc::ptr_eq(c, d)),
+            s => panic!("Unexpected stack {s:?}"),
+        }
+    }
+
+    #[test]
+    fn depth_clear() {
+        assert_eq!(
+            run("depth 1 2 depth"),
+            Ok([0, 1, 2, 3].map(Value::Int).to_vec())
+        );
+        assert_eq!(run("1 2 clear depth"), Ok(vec![Value::Int(0)]));
+    }
+
+    #[test]
+    fn min_max() {
+        assert_eq!(run("1 2 min"), Ok(vec![Value::Int(1)]));
+        assert_eq!(run("2 1 min"), Ok(vec![Value::Int(1)]));
+        assert_eq!(run("1 2 max"), Ok(vec![Value::Int(2)]));
+        assert_eq!(run("2 1 max"), Ok(vec![Value::Int(2)]));
+        assert_eq!(run("3 3 min"), Ok(vec![Value::Int(3)]));
+        assert_eq!(run("3 3 max"), Ok(vec![Value::Int(3)]));
+        assert_eq!(run("0 2 - 1 min"), Ok(vec![Value::Int(-2)]));
+        assert_eq!(run("0 2 - 1 max"), Ok(vec![Value::Int(1)]));
+    }
+
+    #[test]
+    fn numeric_equality() {
+        assert_ne!(Value::Int(1), Value::Number(1.0));
+        assert_ne!(
+            Value::List([Value::Int(1)].into()),
+            Value::List([Value::Number(1.0)].into())
+        );
+        assert_eq!(
+            run("1 1.0 = [1 2] [1.0 2] = 1 2 / 0.5 = 1 3 / 0.25 >"),
+            Ok(vec![Value::Bool(true); 4])
+        );
+        assert_eq!(
+            run("0.1 0.2 + 1.1 16777217.0"),
+            Ok(vec![
+                Value::Number(0.1 + 0.2),
+                Value::Number(1.1),
+                Value::Number(16777217.0),
+            ])
+        );
+        // 2^53 + 1 has no float of its own, so rounding it would make it
+        // equal to 2^53.
+        assert_eq!(
+            run("9007199254740993 9007199254740992.0 = \
+                 9007199254740993 9007199254740992.0 >"),
+            Ok(vec![Value::Bool(false), Value::Bool(true)])
+        );
+        assert_eq!(
+            run("9223372036854775807 9223372036854775808.0 <"),
+            Ok(vec![Value::Bool(true)])
+        );
+        assert_eq!(run("1 0 0 / ="), Ok(vec![Value::Bool(false)]));
+    }
+
+    #[cfg(feature = "num-bigint")]
+    #[test]
+    fn big_numeric_equality() {
+        assert_eq!(
+            run("2 70 pow 1 + 2.0 70 pow = 2 70 pow 2.0 70 pow = \
+                 2 70 pow 1 2 / >"),
+            Ok(vec![Value::Bool(false), Value::Bool(true), Value::Bool(true)])
+        );
+    }
+
+    #[test]
+    fn min_max_nan() {
+        let stack = run("0 0 / 1 max 1 0 0 / min").unwrap();
+
+        assert!(matches!(stack[..], [Value::Number(a), Value::Number(b)]
+                         if a.is_nan() && b.is_nan()));
+    }
+
+    #[test]
+    fn abs() {
+        assert_eq!(run("0 5 - abs"), Ok(vec![Value::Int(5)]));
+        assert_eq!(run("5 abs"), Ok(vec![Value::Int(5)]));
+    }
+
+    #[test]
+    fn map() {
+        assert_eq!(
+            run("[1 2 3] { dup * } map"),
+            Ok(vec![Value::List([
+                Value::Int(1), Value::Int(4), Value::Int(9),
+            ].into())])
+        );
+        assert_eq!(run("[] { 1 + } map"), Ok(vec![Value::List([].into())]));
+        assert_eq!(
+            run("def inc { 1 + } [1 2] { inc } map 5"),
+            Ok(vec![
+                Value::List([Value::Int(2), Value::Int(3)].into()),
+                Value::Int(5),
+            ])
+        );
+        assert!(run("[1 2] { drop } map").is_err());
+        assert!(run("[1 2] 3 map").is_err());
+        assert!(run("1 { } map").is_err());
+    }
+
+    #[test]
+    fn filter() {
+        assert_eq!(
+            run("[1 2 3 4] { 2 > } filter"),
+            Ok(vec![Value::List([
+                Value::Int(3), Value::Int(4),
+            ].into())])
+        );
+        assert_eq!(
+            run("[[1] [] [2 3]] { [] = ! } filter"),
+            Ok(vec![Value::List([
+                Value::List([Value::Int(1)].into()),
+                Value::List([Value::Int(2), Value::Int(3)].into()),
+            ].into())])
+        );
+        assert!(run("[1 2] { } filter").is_err());
+        assert!(run("[1 2] { 1 + } filter").is_err());
+    }
+
+    #[test]
+    fn fold() {
+        assert_eq!(run("[1 2 3] 0 { + } fold"), Ok(vec![Value::Int(6)]));
+        assert_eq!(run("[1 2 3] 0 { - } fold"), Ok(vec![Value::Int(-6)]));
+        assert_eq!(run("[2 3] 1 { * } fold"), Ok(vec![Value::Int(6)]));
+        assert_eq!(run("[] 7 { + } fold"), Ok(vec![Value::Int(7)]));
+        assert_eq!(
+            run("[3 1 2] 0 { max } fold"),
+            Ok(vec![Value::Int(3)])
+        );
+        assert!(run("[1 2] 0 { drop drop } fold").is_err());
+    }
+
+    #[test]
+    fn each() {
+        assert_eq!(run("0 [1 2 3] { + } each"), Ok(vec![Value::Int(6)]));
+        assert_eq!(
+            run("[1 2] { dup } each"),
+            Ok(vec![
+                Value::Int(1), Value::Int(1),
+                Value::Int(2), Value::Int(2),
+            ])
+        );
+        assert_eq!(run("[] { println } each"), Ok(vec![]));
+        assert!(run("[1] { + } each").is_err());
+    }
+
+    #[test]
+    fn bi_tri_cleave() {
+        assert_eq!(
+            run("3 { 1 + } { 2 * } bi"),
+            Ok(vec![Value::Int(4), Value::Int(6)])
+        );
+        assert_eq!(
+            run("3 { 1 + } { 2 * } { dup * } tri"),
+            Ok(vec![Value::Int(4), Value::Int(6), Value::Int(9)])
+        );
+        assert_eq!(
+            run("3 [{ 1 - } { }] cleave"),
+            Ok(vec![Value::Int(2), Value::Int(3)])
+        );
+        assert!(run("3 { } bi").is_err());
+        assert!(run("3 [1] cleave").is_err());
+    }
+
+    #[test]
+    fn curry() {
+        assert_eq!(
+            run("2 { + } curry 3 swap eval"),
+            Ok(vec![Value::Int(5)])
+        );
+        assert_eq!(
+            run("[1 2] 10 { * } curry map"),
+            Ok(vec![Value::List([
+                Value::Int(10), Value::Int(20),
+            ].into())])
+        );
+        assert_eq!(
+            run("[1 2] { } curry eval"),
+            Ok(vec![Value::List([
+                Value::Int(1), Value::Int(2),
+            ].into())])
+        );
+        assert!(run("1 2 curry").is_err());
+    }
+
+    #[test]
+    fn compose() {
+        assert_eq!(
+            run("3 { 1 + } { 2 * } compose eval"),
+            Ok(vec![Value::Int(8)])
+        );
+        assert_eq!(
+            run("[1 2] 1 { + } curry { dup * } compose map"),
+            Ok(vec![Value::List([
+                Value::Int(4), Value::Int(9),
+            ].into())])
+        );
+        assert_eq!(run("{ } { } compose eval"), Ok(vec![]));
+        assert!(run("{ } 1 compose").is_err());
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(run("1 3 { 2 * } times"), Ok(vec![Value::Int(8)]));
+        assert_eq!(run("1 0 { 2 * } times"), Ok(vec![Value::Int(1)]));
+        assert!(run("1 1.5 { } times").is_err());
+        assert!(run("0 1 - { } times").is_err());
+        assert!(run("true { } times").is_err());
+    }
+
+    #[test]
+    fn evaluate_while() {
+        assert_eq!(
+            run("1 { dup 100 < } { 2 * } while"),
+            Ok(vec![Value::Int(128)])
+        );
+        assert_eq!(
+            run("1 { dup 0 < } { 2 * } while"),
+            Ok(vec![Value::Int(1)])
+        );
+        assert!(run("{ 1 } { } while").is_err());
+        assert!(run("{ } { } while").is_err());
+    }
+
+    #[test]
+    fn range() {
+        assert_eq!(
+            run("0 3 range"),
+            Ok(vec![Value::List([
+                Value::Int(0), Value::Int(1), Value::Int(2),
+            ].into())])
+        );
+        assert_eq!(run("3 0 range"), Ok(vec![Value::List([].into())]));
+        assert_eq!(
+            run("0 5 2 range-step"),
+            Ok(vec![Value::List([
+                Value::Int(0), Value::Int(2), Value::Int(4),
+            ].into())])
+        );
+        assert_eq!(
+            run("3 0 0 1 - range-step"),
+            Ok(vec![Value::List([
+                Value::Int(3), Value::Int(2), Value::Int(1),
+            ].into())])
+        );
+        assert_eq!(
+            run("0 4 range 0 { + } fold"),
+            Ok(vec![Value::Int(6)])
+        );
+        assert!(run("0 5 0 range-step").is_err());
+        assert!(run("0 100000000000 range").is_err());
+        assert!(run("0 1 0.000000001 range-step").is_err());
+        assert_eq!(
+            run("0 9223372036854775807 9223372036854775807 range-step"),
+            Ok(vec![Value::List([Value::Int(0)].into())])
+        );
+        assert!(run("0 \"a\" range").is_err());
+    }
+
+    #[test]
+    fn len() {
+        assert_eq!(run("[1 [2 3] 4] len"), Ok(vec![Value::Int(3)]));
+        assert_eq!(run("[] len"), Ok(vec![Value::Int(0)]));
+        assert_eq!(run("\"héllo\" len"), Ok(vec![Value::Int(5)]));
+        assert!(run("1 len").is_err());
+    }
+
+    #[test]
+    fn append() {
+        assert_eq!(
+            run("[1] [2 3] append"),
+            Ok(vec![Value::List([
+                Value::Int(1), Value::Int(2), Value::Int(3),
+            ].into())])
+        );
+        assert_eq!(run("[] [] append"), Ok(vec![Value::List([].into())]));
+        assert!(run("[1] 2 append").is_err());
+    }
+
+    #[test]
+    fn head_tail_uncons() {
+        assert_eq!(run("[1 2 3] head"), Ok(vec![Value::Int(1)]));
+        assert_eq!(
+            run("[1 2 3] tail"),
+            Ok(vec![Value::List([
+                Value::Int(2), Value::Int(3),
+            ].into())])
+        );
+        assert_eq!(
+            run("[1] uncons"),
+            Ok(vec![Value::List([].into()), Value::Int(1)])
+        );
+        assert!(run("[] head").is_err());
+        assert!(run("[] tail").is_err());
+        assert!(run("[] uncons").is_err());
+        assert!(run("1 head").is_err());
+    }
+
+    #[test]
+    fn cons_push() {
+        assert_eq!(
+            run("1 [2] cons"),
+            Ok(vec![Value::List([
+                Value::Int(1), Value::Int(2),
+            ].into())])
+        );
+        assert_eq!(
+            run("[1] 2 push"),
+            Ok(vec![Value::List([
+                Value::Int(1), Value::Int(2),
+            ].into())])
+        );
+        assert_eq!(
+            run("[] [1] push"),
+            Ok(vec![Value::List([
+                Value::List([Value::Int(1)].into()),
+            ].into())])
+        );
+        assert!(run("1 2 cons").is_err());
+        assert!(run("1 2 push").is_err());
+    }
+
+    #[test]
+    fn sort() {
+        assert_eq!(
+            run("[3 1 2] sort"),
+            Ok(vec![Value::List([
+                Value::Int(1), Value::Int(2), Value::Int(3),
+            ].into())])
+        );
+        assert_eq!(
+            run("[\"b\" \"a\"] sort"),
+            Ok(vec![Value::List([
+                Value::String("a".into()), Value::String("b".into()),
+            ].into())])
+        );
+        assert_eq!(run("[] sort"), Ok(vec![Value::List([].into())]));
+        assert!(run("[1 2] 0 0 / push sort").is_err());
+    }
+
+    #[test]
+    fn sort_by() {
+        assert_eq!(
+            run("[1 3 2] { 0 swap - } sort-by"),
+            Ok(vec![Value::List([
+                Value::Int(3), Value::Int(2), Value::Int(1),
+            ].into())])
+        );
+        assert_eq!(
+            run("[[2 1] [1] [3 2 1]] { len } sort-by"),
+            Ok(vec![Value::List([
+                Value::List([Value::Int(1)].into()),
+                Value::List([Value::Int(2), Value::Int(1)].into()),
+                Value::List([
+                    Value::Int(3), Value::Int(2), Value::Int(1),
+                ].into()),
+            ].into())])
+        );
+        assert!(run("[1 2] { drop } sort-by").is_err());
+    }
+
+    #[test]
+    fn zip() {
+        assert_eq!(
+            run("[1 2 3] [\"a\" \"b\"] zip"),
+            Ok(vec![Value::List([
+                Value::List([
+                    Value::Int(1), Value::String("a".into()),
+                ].into()),
+                Value::List([
+                    Value::Int(2), Value::String("b".into()),
+                ].into()),
+            ].into())])
+        );
+        assert_eq!(run("[] [1] zip"), Ok(vec![Value::List([].into())]));
+        assert!(run("[1] 1 zip").is_err());
+    }
+
+    #[test]
+    fn flatten() {
+        assert_eq!(
+            run("[[1 2] 3 [] [[4]]] flatten"),
+            Ok(vec![Value::List([
+                Value::Int(1), Value::Int(2), Value::Int(3),
+                Value::List([Value::Int(4)].into()),
+            ].into())])
+        );
+        assert_eq!(run("[] flatten"), Ok(vec![Value::List([].into())]));
+        assert!(run("1 flatten").is_err());
+    }
+
+    #[test]
+    fn contains() {
+        assert_eq!(run("2 [1 2 3] contains?"), Ok(vec![Value::Bool(true)]));
+        assert_eq!(run("4 [1 2 3] contains?"), Ok(vec![Value::Bool(false)]));
+        assert_eq!(run("[1] [[1]] contains?"), Ok(vec![Value::Bool(true)]));
+        assert_eq!(run("1.0 [1 2] contains?"), Ok(vec![Value::Bool(true)]));
+        assert_eq!(
+            run("\"ell\" \"hello\" contains?"),
+            Ok(vec![Value::Bool(true)])
+        );
+        assert_eq!(
+            run("\"elk\" \"hello\" contains?"),
+            Ok(vec![Value::Bool(false)])
+        );
+        assert!(run("1 \"hello\" contains?").is_err());
+        assert!(run("1 2 contains?").is_err());
+    }
+
+    #[test]
+    fn index_of() {
+        assert_eq!(run("2 [1 2 3 2] index-of"), Ok(vec![Value::Int(1)]));
+        assert_eq!(run("1.0 [1 2] index-of"), Ok(vec![Value::Int(0)]));
+        assert_eq!(
+            run("3 [1 2 3] index-of [\"a\" \"b\" \"c\"] nth"),
+            Ok(vec![Value::String("c".into())])
+        );
+        assert!(run("4 [1 2 3] index-of").is_err());
+        assert!(run("1 2 index-of").is_err());
+    }
+
+    #[test]
+    fn slice() {
+        assert_eq!(
+            run("[1 2 3 4] 1 3 slice"),
+            Ok(vec![Value::List([
+                Value::Int(2), Value::Int(3),
+            ].into())])
+        );
+        assert_eq!(
+            run("[1 2 3 4] 0 1 - 4 slice"),
+            Ok(vec![Value::List([Value::Int(4)].into())])
+        );
+        assert_eq!(
+            run("\"héllo\" 1 0 2 - slice"),
+            Ok(vec![Value::String("él".into())])
+        );
+        assert_eq!(
+            run("\"abc\" 3 3 slice"),
+            Ok(vec![Value::String("".into())])
+        );
+        assert!(run("[1 2] 0 3 slice").is_err());
+        assert!(run("[1 2] 2 1 slice").is_err());
+        assert!(run("[1 2] 0.5 1 slice").is_err());
+        assert!(run("1 0 1 slice").is_err());
+    }
+
+    #[test]
+    fn set_nth() {
+        assert_eq!(
+            run("9 1 [1 2 3] set-nth"),
+            Ok(vec![Value::List([
+                Value::Int(1), Value::Int(9), Value::Int(3),
+            ].into())])
+        );
+        assert_eq!(
+            run("9 0 1 - [1 2] set-nth"),
+            Ok(vec![Value::List([
+                Value::Int(1), Value::Int(9),
+            ].into())])
+        );
+        assert!(run("9 2 [1 2] set-nth").is_err());
+        assert!(run("9 0 [] set-nth").is_err());
+        assert!(run("9 true [1] set-nth").is_err());
+    }
+
+    #[test]
+    fn add_strings() {
+        assert_eq!(
+            run("\"foo\" \"bar\" +"),
+            Ok(vec![Value::String("foobar".into())])
+        );
+        assert!(run("\"foo\" 1 +").is_err());
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(
+            run("\"a,b,,c\" \",\" split"),
+            Ok(vec![Value::List([
+                Value::String("a".into()), Value::String("b".into()),
+                Value::String("".into()), Value::String("c".into()),
+            ].into())])
+        );
+        assert_eq!(
+            run("\"ab\" \"x\" 0 0 slice split"),
+            Ok(vec![Value::List([
+                Value::String("a".into()), Value::String("b".into()),
+            ].into())])
+        );
+        assert!(run("\"a b\" 1 split").is_err());
+        assert!(run("1 \" \" split").is_err());
+    }
+
+    #[test]
+    fn join() {
+        assert_eq!(
+            run("[\"a\" 1 true [2]] \", \" join"),
+            Ok(vec![Value::String("a, 1, true, [ 2 ]".into())])
+        );
+        assert_eq!(
+            run("\"a b c\" \" \" split \"-\" join"),
+            Ok(vec![Value::String("a-b-c".into())])
+        );
+        assert!(run("[1] 1 join").is_err());
+    }
+
+    #[test]
+    fn upper_lower() {
+        assert_eq!(
+            run("\"Straße\" upper"),
+            Ok(vec![Value::String("STRASSE".into())])
+        );
+        assert_eq!(
+            run("\"HeLLo\" lower"),
+            Ok(vec![Value::String("hello".into())])
+        );
+        assert!(run("1 upper").is_err());
+        assert!(run("[] lower").is_err());
+    }
+
+    #[test]
+    fn substring() {
+        assert_eq!(
+            run("\"héllo\" 1 3 substring"),
+            Ok(vec![Value::String("éll".into())])
+        );
+        assert_eq!(
+            run("\"abc\" 3 0 substring"),
+            Ok(vec![Value::String("".into())])
+        );
+        assert!(run("\"abc\" 2 2 substring").is_err());
+        assert!(run("\"abc\" 0 1 - 1 substring").is_err());
+        assert!(run("[1] 0 1 substring").is_err());
+    }
+
+    #[test]
+    fn trim() {
+        assert_eq!(
+            run("\"  a b  \" trim"),
+            Ok(vec![Value::String("a b".into())])
+        );
+        assert_eq!(
+            run("\"  a b  \" trim-start"),
+            Ok(vec![Value::String("a b  ".into())])
+        );
+        assert_eq!(
+            run("\"  a b  \" trim-end"),
+            Ok(vec![Value::String("  a b".into())])
+        );
+        assert!(run("1 trim").is_err());
+    }
+
+    #[test]
+    fn string_to_number() {
+        assert_eq!(
+            run("\" 42 \" string->number 1 +"),
+            Ok(vec![Value::Int(43)])
+        );
+        assert_eq!(
+            run("\"-1.5e2\" string->number"),
+            Ok(vec![Value::Number(-150.0)])
+        );
+        assert!(run("\"12abc\" string->number").is_err());
+        assert!(run("12 string->number").is_err());
+    }
+
+    #[test]
+    fn stringify() {
+        assert_eq!(
+            run("\"n = \" 1.5 to-string +"),
+            Ok(vec![Value::String("n = 1.5".into())])
+        );
+        assert_eq!(
+            run("[1 \"a\"] to-string"),
+            Ok(vec![Value::String("[ 1 \"a\" ]".into())])
+        );
+        assert_eq!(
+            run("\"a\" to-string"),
+            Ok(vec![Value::String("a".into())])
+        );
+        assert_eq!(
+            run("{ 1 + } to-string"),
+            Ok(vec![Value::String("{ 1 + }".into())])
+        );
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(
+            run("[1 \"b\" [true]] \"a={} b={} c={}\" format"),
+            Ok(vec![Value::String("a=1 b=b c=[ true ]".into())])
+        );
+        assert_eq!(
+            run("[] \"{{}}\" format"),
+            Ok(vec![Value::String("{}".into())])
+        );
+        assert!(run("[] \"{}\" format").is_err());
+        assert!(run("[1 2] \"{}\" format").is_err());
+        assert!(run("[1] \"{ }\" format").is_err());
+    }
+
+    #[test]
+    fn ord_chr() {
+        assert_eq!(run("\"a\" ord"), Ok(vec![Value::Int(97)]));
+        assert_eq!(run("955 chr"), Ok(vec![Value::String("λ".into())]));
+        assert_eq!(
+            run("\"héllo\" 1 swap nth ord 1 + chr"),
+            Ok(vec![Value::String("ê".into())])
+        );
+        assert_eq!(
+            run("0 1 - \"abc\" nth"),
+            Ok(vec![Value::String("c".into())])
+        );
+        assert_eq!(
+            run("0 3 - \"héllo\" nth 9 \"ab\" ?nth"),
+            Ok(vec![Value::String("l".into()), Value::Nil])
+        );
+        assert_eq!(
+            run("0 4 - \"abc\" nth"),
+            Err(InterpreterError::IndexOutOfBounds { index: -4, len: 3 })
+        );
+        assert!(run("\"ab\" ord").is_err());
+        assert!(run("55296 chr").is_err());
+        assert!(run("0.5 chr").is_err());
+    }
+
+    #[cfg(feature = "sh")]
+    #[test]
+    fn sh() {
+        assert_eq!(
+            run("\"echo hi; exit 3\" sh"),
+            Ok(vec![Value::String("hi\n".into()), Value::Int(3)])
+        );
+        assert_eq!(
+            run("\"true\" sh"),
+            Ok(vec![Value::String("".into()), Value::Int(0)])
+        );
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn time_formatting() {
+        assert_eq!(
+            run("86400 \"%Y-%m-%d %H:%M:%S\" format-time"),
+            Ok(vec![Value::String("1970-01-02 00:00:00".into())])
+        );
+        assert_eq!(
+            run("1.5 \"%S%.3f\" format-time"),
+            Ok(vec![Value::String("01.500".into())])
+        );
+        assert_eq!(
+            run("\"2024-03-01\" \"%Y-%m-%d\" parse-time"),
+            Ok(vec![Value::Int(1709251200)])
+        );
+        assert_eq!(
+            run("\"2024-03-01 01:00 +0100\" \"%Y-%m-%d %H:%M %z\" \
+                 parse-time"),
+            Ok(vec![Value::Int(1709251200)])
+        );
+        assert!(run("0 \"%Q\" format-time").is_err());
+        assert!(run("\"soon\" \"%Y\" parse-time").is_err());
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn json() {
+        assert_eq!(
+            run(r##"r#"{"a": [1, 2.5, null], "b": true}"# json-parse"##),
+            Ok(vec![Value::List([
+                Value::Symbol(Symbol::new("object")),
+                Value::List([
+                    Value::List([
+                        Value::String("a".into()),
+                        Value::List([
+                            Value::Int(1), Value::Number(2.5), Value::Nil,
+                        ].into()),
+                    ].into()),
+                    Value::List([
+                        Value::String("b".into()), Value::Bool(true),
+                    ].into()),
+                ].into()),
+            ].into())])
+        );
+        for json in [r#"[["a",1]]"#, "{}", "[]", r#"{"a":{"b":[]}}"#] {
+            assert_eq!(
+                run(&format!("r#\"{json}\"# json-parse json-encode")),
+                Ok(vec![Value::String(json.into())])
+            );
+        }
+        assert_eq!(
+            run(r##"r#"{"a":[1,2.5,null],"b":"x"}"# json-parse json-encode"##),
+            Ok(vec![Value::String(r#"{"a":[1,2.5,null],"b":"x"}"#.into())])
+        );
+        assert_eq!(
+            run("[] [1 2] [[1 2]] json-encode"),
+            Ok(vec![
+                Value::List([].into()),
+                Value::List([Value::Int(1), Value::Int(2)].into()),
+                Value::String("[[1,2]]".into()),
+            ])
+        );
+        assert!(run("\"{\" json-parse").is_err());
+        assert!(run("{ 1 } json-encode").is_err());
+        assert!(matches!(
+            run("[:object [[1 2]]] json-encode"),
+            Err(InterpreterError::TypeError(_))
+        ));
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn csv() {
+        let row = |cells: &[&str]| Value::List(
+            cells.iter().map(|&s| Value::String(s.into())).collect()
+        );
+        let pair = |k: &str, v: &str| row(&[k, v]);
+
+        assert_eq!(
+            run("r#\"a,b\n1,\"x,y\"\n\"# csv-parse"),
+            Ok(vec![Value::List([row(&["a", "b"]), row(&["1", "x,y"])]
+                                .into())])
+        );
+        assert_eq!(
+            run("\"a;b\n1;2\n\" \";\" true csv-parse-with"),
+            Ok(vec![Value::List([
+                Value::List([pair("a", "1"), pair("b", "2")].into()),
+            ].into())])
+        );
+        assert_eq!(
+            run("[[1 \"x,y\"] [:a 2.5]] csv-encode"),
+            Ok(vec![Value::String("1,\"x,y\"\na,2.5\n".into())])
+        );
+        assert_eq!(
+            run("\"a\tb\n1\t2\n\" \"\t\" true csv-parse-with \
+                 \"\t\" csv-encode-with"),
+            Ok(vec![Value::String("a\tb\n1\t2\n".into())])
+        );
+        assert!(run("\"a\" \"::\" false csv-parse-with").is_err());
+        assert!(run("[[[1 2 3]]] csv-encode").is_err());
+    }
+
+    #[cfg(feature = "ureq")]
+    #[test]
+    fn http() {
+        use std::{
+            io::{BufRead, BufReader, Read, Write},
+            net::TcpListener,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut request = String::new();
+                let mut length = 0;
+
+                reader.read_line(&mut request).unwrap();
+
+                loop {
+                    let mut line = String::new();
+
+                    reader.read_line(&mut line).unwrap();
+
+                    match line.to_lowercase().split_once(':') {
+                        Some(("content-length", n)) =>
+                            length = n.trim().parse().unwrap(),
+                        _ if line.trim().is_empty() => break,
+                        _ => {}
+                    }
+                }
+
+                let mut body = vec![0; length];
+
+                reader.read_exact(&mut body).unwrap();
+
+                let (status, body) = match length {
+                    0 => ("404 Not Found", request.trim().as_bytes().to_vec()),
+                    _ => ("201 Created", body),
+                };
+
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n",
+                    body.len()
+                ).unwrap();
+                reader.get_mut().write_all(&body).unwrap();
+            }
+        });
+
+        assert_eq!(
+            run(&format!("\"{url}/a\" http-get")),
+            Ok(vec![
+                Value::String("GET /a HTTP/1.1".into()),
+                Value::Int(404),
+            ])
+        );
+        assert_eq!(
+            run(&format!("\"{url}\" \"hi\" http-post")),
+            Ok(vec![Value::String("hi".into()), Value::Int(201)])
+        );
+
+        server.join().unwrap();
+    }
+
+    #[cfg(feature = "tcp")]
+    #[test]
+    fn tcp() {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = match run("\"127.0.0.1\" 0 tcp-listen").unwrap().pop() {
+            Some(Value::Socket(s)) => s,
+            v => panic!("Expected a listener, got {v:?}"),
+        };
+        let port = listener.port().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream =
+                std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let mut line = String::new();
+
+            stream.write_all(b"ping\r\n").unwrap();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+
+            line
+        });
+        let (_, program) = program(
+            "tcp-accept dup tcp-recv \"\n\" + over tcp-send \
+             dup tcp-recv swap drop"
+        ).unwrap();
+        let mut interpreter = Interpreter::new(false);
+
+        interpreter.stack.push(Value::Socket(listener));
+
+        assert_eq!(interpreter.run_program(program), Ok(Some(Value::Nil)));
+        assert_eq!(client.join().unwrap(), "ping\n");
+        assert!(run("\"127.0.0.1\" 70000 tcp-connect").is_err());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex() {
+        assert_eq!(
+            run("\"abc123\" \"^[a-z]+[0-9]+$\" re-match?"),
+            Ok(vec![Value::Bool(true)])
+        );
+        assert_eq!(
+            run("\"abc\" \"[0-9]\" re-match?"),
+            Ok(vec![Value::Bool(false)])
+        );
+        assert_eq!(
+            run("\"a1b22c333\" \"[0-9]+\" re-find"),
+            Ok(vec![Value::List([
+                Value::String("1".into()), Value::String("22".into()),
+                Value::String("333".into()),
+            ].into())])
+        );
+        assert_eq!(
+            run("\"a1b22\" \"([a-z])([0-9]+)\" \"$2$1\" re-replace"),
+            Ok(vec![Value::String("1a22b".into())])
+        );
+        assert!(run("\"a\" \"(\" re-match?").is_err());
+    }
+
+    #[test]
+    fn rem() {
+        assert_eq!(run("7 3 mod"), Ok(vec![Value::Int(1)]));
+        assert_eq!(run("6 3 mod"), Ok(vec![Value::Int(0)]));
+        assert_eq!(run("0 7 - 3 mod"), Ok(vec![Value::Int(-1)]));
+        assert_eq!(run("5.5 2 mod"), Ok(vec![Value::Number(1.5)]));
+        assert!(run("1 \"a\" mod").is_err());
+    }
+
+    #[test]
+    fn pow() {
+        assert_eq!(run("2 10 pow"), Ok(vec![Value::Int(1024)]));
+        assert_eq!(run("9 0.5 pow"), Ok(vec![Value::Number(3.0)]));
+        assert_eq!(run("2 0 1 - pow"), Ok(vec![Value::Number(0.5)]));
+        assert!(run("2 \"a\" pow").is_err());
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(run("16 sqrt"), Ok(vec![Value::Number(4.0)]));
+        assert_eq!(
+            run("2.5 floor 2.5 ceil 2.5 round 0 2.5 - trunc"),
+            Ok(vec![
+                Value::Number(2.0), Value::Number(3.0),
+                Value::Number(3.0), Value::Number(-2.0),
+            ])
+        );
+        assert_eq!(
+            run("[10 20 30] 0.5 3 * floor swap nth"),
+            Ok(vec![Value::Int(20)])
+        );
+        assert!(run("\"a\" sqrt").is_err());
+        assert!(run("floor").is_err());
+    }
+
+    #[test]
+    fn trigonometry() {
+        use std::f64::consts::{E, FRAC_PI_4, PI};
+
+        assert_eq!(
+            run("pi e"),
+            Ok(vec![Value::Number(PI), Value::Number(E)])
+        );
+        assert_eq!(
+            run("0 sin 0 cos 0 tan"),
+            Ok(vec![
+                Value::Number(0.0), Value::Number(1.0), Value::Number(0.0),
+            ])
+        );
+        assert_eq!(run("1 1 atan2"), Ok(vec![Value::Number(FRAC_PI_4)]));
+        assert_eq!(
+            run("e ln 1000 log10 0 exp"),
+            Ok(vec![
+                Value::Number(1.0), Value::Number(3.0), Value::Number(1.0),
+            ])
+        );
+        assert_eq!(
+            run("def even? { 2 mod 0 = } 4 even?"),
+            Ok(vec![Value::Bool(true)])
+        );
+        assert!(run("1 \"a\" atan2").is_err());
+    }
+
+    #[test]
+    fn logic() {
+        let t = |b| Value::Bool(b);
+
+        assert_eq!(
+            run("1 1 = 1 2 = and 1 1 = 1 2 = or 1 1 = 1 1 = xor"),
+            Ok(vec![t(false), t(true), t(false)])
+        );
+        assert_eq!(
+            run("1 2 = { + } and-then 1 1 = { + } or-else"),
+            Ok(vec![t(false), t(true)])
+        );
+        assert_eq!(
+            run("1 1 = { 2 2 = } and-then 1 2 = { 2 3 = } or-else"),
+            Ok(vec![t(true), t(false)])
+        );
+        assert!(run("1 1 = 1 and").is_err());
+        assert!(run("1 1 = { 1 } and-then").is_err());
+        assert!(run("1 { 1 1 = } or-else").is_err());
+    }
+
+    #[test]
+    fn integers() {
+        assert!(matches!(run("2 3 * 1 -").unwrap()[..], [Value::Int(5)]));
+        assert!(matches!(run("6 3 /").unwrap()[..], [Value::Int(2)]));
+        assert_eq!(run("3 -1 -"), Ok(vec![Value::Int(4)]));
+        assert_eq!(run("1_000 -2_000 +"), Ok(vec![Value::Int(-1000)]));
+        assert!(matches!(run("7 2 /").unwrap()[..], [Value::Number(3.5)]));
+        assert!(matches!(run("1 0.5 +").unwrap()[..], [Value::Number(1.5)]));
+        assert!(matches!(run("2 62 pow").unwrap()[..], [Value::Int(_)]));
+        assert!(matches!(run("[1 2] len").unwrap()[..], [Value::Int(2)]));
+        assert_eq!(
+            run("9007199254740993 1 +"),
+            Ok(vec![Value::Int(9007199254740994)])
+        );
+        assert_eq!(run("1 1.0 = 1 1.5 <"), Ok(vec![
+            Value::Bool(true), Value::Bool(true),
+        ]));
+        assert_eq!(
+            run("[3 1.5 2] sort"),
+            Ok(vec![Value::List([
+                Value::Number(1.5), Value::Int(2), Value::Int(3),
+            ].into())])
+        );
+        assert!(run("1 0 mod").is_err());
+
+        #[cfg(not(feature = "num-bigint"))]
+        {
+            assert!(run("2 63 pow").is_err());
+            assert!(run("9223372036854775807 1 +").is_err());
+        }
+    }
+
+    #[test]
+    fn rationals() {
+        use num_rational::Rational64;
+
+        assert_eq!(
+            run("0.1d 0.2d + 0.3d = 0.1 0.2 + 0.3 ="),
+            Ok(vec![Value::Bool(true), Value::Bool(false)])
+        );
+        assert!(matches!(
+            run("1/3 1/3 + 1/3 +").unwrap()[..],
+            [Value::Int(1)]
+        ));
+        assert_eq!(
+            run("1/2 3 * 7/2 1 mod 2/3 0 2 - pow"),
+            Ok(vec![
+                Value::Rational(Rational64::new(3, 2)),
+                Value::Rational(Rational64::new(1, 2)),
+                Value::Rational(Rational64::new(9, 4)),
+            ])
+        );
+        assert!(matches!(run("1/2 0.5 +").unwrap()[..], [Value::Number(1.0)]));
+        assert_eq!(
+            run("1.50d to-string \"2/6\" string->number 1/3 ="),
+            Ok(vec![Value::String("3/2".into()), Value::Bool(true)])
+        );
+        assert!(run("1/2 0 mod").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "num-bigint")]
+    fn big_integers() {
+        let max = i64::MAX;
+
+        assert_eq!(
+            run(&format!("{max} 1 + to-string")),
+            Ok(vec![Value::String("9223372036854775808".into())])
+        );
+        assert_eq!(
+            run(&format!("{max} 1 + 1 - {max} =")),
+            Ok(vec![Value::Bool(true)])
+        );
+        assert_eq!(
+            run("2 100 pow to-string"),
+            Ok(vec![Value::String("1267650600228229401496703205376".into())])
+        );
+        assert_eq!(
+            run("\"1267650600228229401496703205376\" string->number \
+                2 64 pow /"),
+            Ok(vec![Value::Int(1 << 36)])
+        );
+        assert!(matches!(
+            run(&format!("{max} 2 *")).as_deref(),
+            Ok([Value::BigInt(_)])
+        ));
+    }
+
+    #[test]
+    fn bitwise() {
+        assert_eq!(
+            run("12 10 band 12 10 bor 12 10 bxor"),
+            Ok(vec![Value::Int(8), Value::Int(14), Value::Int(6)])
+        );
+        assert_eq!(
+            run("1 4 shl 0 16 - 2 shr"),
+            Ok(vec![Value::Int(16), Value::Int(-4)])
+        );
+        assert_eq!(
+            run("0 1 - 70 shr 5 64 shr"),
+            Ok(vec![Value::Int(-1), Value::Int(0)])
+        );
+        assert!(run("1 0 1 - shr").is_err());
+        assert!(run("1.5 1 band").is_err());
+        assert!(run("1 2 / 1 bor").is_err());
+    }
+
+    #[cfg(not(feature = "num-bigint"))]
+    #[test]
+    fn bitwise_overflow() {
+        assert!(run("1 64 shl").is_err());
+        assert!(run("3 62 shl").is_err());
+    }
+
+    #[cfg(feature = "num-bigint")]
+    #[test]
+    fn big_bitwise() {
+        assert_eq!(
+            run("1 64 shl 2 64 pow = 3 62 shl 3 2 62 pow * ="),
+            Ok(vec![Value::Bool(true), Value::Bool(true)])
+        );
+        assert_eq!(
+            run("1 70 shl 1 70 shl 1 + band 70 shr \
+                 1 70 shl 1 bor 1 70 shl bxor"),
+            Ok(vec![Value::Int(1), Value::Int(1)])
+        );
+    }
+
+    #[test]
+    fn numeric_type_errors() {
+        assert!(run("1 true min").is_err());
+        assert!(run("\"a\" 1 max").is_err());
+        assert!(run("false abs").is_err());
+        assert!(run("1 max").is_err());
+    }
 }
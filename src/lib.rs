@@ -2,18 +2,31 @@ mod ast;
 mod value;
 mod parser;
 mod interpreter;
+mod effect;
+mod error;
+mod bytecode;
+mod optimize;
+#[cfg(feature = "tcp")]
+mod net;
 
 pub use ast::*;
 pub use value::*;
 pub use parser::*;
 pub use interpreter::*;
+pub use effect::*;
+pub use error::*;
+pub use bytecode::*;
+#[cfg(feature = "tcp")]
+pub use net::*;
 
 use std::{io::{stdin, stdout, Write}, process::exit};
 
 use nom::error::convert_error;
 
-pub fn run_repl(verbose: bool) {
-    let mut interpreter = Interpreter::new(verbose);
+/// Reads and runs lines until the input ends or a program calls `exit`, and
+/// returns the interpreter with the status to exit with.
+pub fn run_repl(mut interpreter: Interpreter) -> (Interpreter, i32) {
+    let verbose = interpreter.verbose;
     let mut input = String::new();
 
     println!(
@@ -28,9 +41,13 @@ the Free Software Foundation, either version 3 of the License, or
     print!(">>> ");
 
     loop {
-        interpreter.statements.clear();
         stdout().flush().unwrap();
-        stdin().read_line(&mut input).expect("Couldn't read stdin");
+
+        if stdin().read_line(&mut input).expect("Couldn't read stdin") == 0 {
+            println!();
+
+            return (interpreter, 0);
+        }
 
         if verbose {
             println!("Input: {input:?}")
@@ -53,6 +70,7 @@ the Free Software Foundation, either version 3 of the License, or
         match interpreter.run_program(program) {
             Ok(None) => {}
             Ok(Some(v)) => println!("{v}"),
+            Err(InterpreterError::Exit(code)) => return (interpreter, code),
             Err(e) => eprintln!("{e}"),
         }
 
@@ -61,12 +79,26 @@ the Free Software Foundation, either version 3 of the License, or
     }
 }
 
-pub fn run_program(input: &str, verbose: bool) {
-    let res = Interpreter::new(verbose).run_program(parse_program(input));
+/// Runs a program and returns the interpreter it ran in with the status to
+/// exit with. Errors are reported before returning, and parser errors exit
+/// right away.
+pub fn run_program(input: &str, mut interpreter: Interpreter)
+-> (Interpreter, i32) {
+    let res = interpreter.run_program(parse_program(input));
+    let status = match res {
+        Ok(_) => 0,
+        Err(InterpreterError::Exit(code)) => code,
+        Err(e) if *e.kind() == InterpreterError::Interrupted => {
+            eprintln!("{e}");
+            130
+        }
+        Err(e) => {
+            eprintln!("Interpreter error: {e}");
+            1
+        }
+    };
 
-    if let Err(e) = res {
-        eprintln!("Interpreter error: {e}");
-    }
+    (interpreter, status)
 }
 
 /// Tries to parse a program and exits on failure.
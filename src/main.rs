@@ -1,7 +1,15 @@
-use std::{fs, io::{stdin, IsTerminal, Read}, path::PathBuf};
+use std::{
+    fs,
+    io::{stdin, IsTerminal, Read},
+    path::PathBuf,
+    process::exit,
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+};
 
 use clap::Parser;
-use stack::{run_program, run_repl};
+use stack::{run_program, run_repl, Interpreter};
+#[cfg(feature = "image")]
+use stack::InterpreterError;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -11,24 +19,107 @@ struct Cli {
     /// Verbose mode. (good for debugging)
     #[arg(short)]
     verbose: bool,
+    /// Fold constants before running programs.
+    #[arg(long)]
+    opt: bool,
+    /// Inline small words into the words calling them.
+    #[arg(long)]
+    inline: bool,
+    /// Abort after running this many statements.
+    #[arg(long, value_name = "N")]
+    max_steps: Option<u64>,
+    /// Abort once the stack holds more than this many values.
+    #[arg(long, value_name = "N")]
+    max_stack: Option<usize>,
+    /// Abort once more than this many words and procedures run at once.
+    #[arg(long, value_name = "N")]
+    max_frames: Option<usize>,
+    /// Load the definitions in an image file before running.
+    #[cfg(feature = "image")]
+    #[arg(long, value_name = "FILE")]
+    load_image: Option<PathBuf>,
+    /// Save all definitions and variables to an image file after running
+    /// the program or leaving the REPL.
+    #[cfg(feature = "image")]
+    #[arg(long, value_name = "FILE")]
+    save_image: Option<PathBuf>,
+    /// Directory to search for imported files. May be given multiple times.
+    #[arg(short = 'I', long = "include", value_name = "DIR")]
+    include: Vec<PathBuf>,
+    /// Arguments passed to the program, after `--`.
+    #[arg(last = true)]
+    args: Vec<String>,
 }
 
 fn main() {
     let cli = Cli::parse();
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let flag = interrupt.clone();
 
-    match cli.file {
-        Some(f) if f != PathBuf::from("-") => {
-            let input = fs::read_to_string(f).expect("Couldn't read file");
+    // Ctrl-C stops the running program. Pressing it again before the program
+    // noticed, like while it waits for input, exits.
+    ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::Relaxed) {
+            exit(130);
+        }
+    }).expect("Couldn't set the Ctrl-C handler");
+
+    let mut interpreter = Interpreter::new(cli.verbose)
+        .with_interrupt(interrupt)
+        .with_args(cli.args)
+        .with_optimize(cli.opt)
+        .with_inlining(cli.inline);
+
+    if let Some(n) = cli.max_steps {
+        interpreter = interpreter.with_step_limit(n);
+    }
+
+    if let Some(n) = cli.max_stack {
+        interpreter = interpreter.with_stack_limit(n);
+    }
+
+    if let Some(n) = cli.max_frames {
+        interpreter = interpreter.with_frame_limit(n);
+    }
 
-            run_program(&input, cli.verbose)
+    #[cfg(feature = "image")]
+    if let Some(path) = &cli.load_image {
+        interpreter.load_image(path).unwrap_or_else(|e| fail(e));
+    }
+
+    #[cfg_attr(not(feature = "image"), allow(unused_variables))]
+    let (interpreter, status) = match cli.file {
+        Some(f) if f.as_os_str() != "-" => {
+            let input = fs::read_to_string(&f).expect("Couldn't read file");
+            let search_path = f.parent().map(Into::into).into_iter()
+                .chain(cli.include)
+                .collect();
+
+            run_program(&input, interpreter.with_search_path(search_path))
         },
-        _ if stdin().is_terminal() => run_repl(cli.verbose),
+        _ if stdin().is_terminal() =>
+            run_repl(interpreter.with_search_path(cli.include)),
         _ => {
             let mut input = String::new();
 
             stdin().read_to_string(&mut input).expect("Couldn't read stdin");
 
-            run_program(&input, cli.verbose)
+            run_program(&input, interpreter.with_search_path(cli.include))
         }
+    };
+
+    // Definitions made before a program failed or called `exit` are saved
+    // too.
+    #[cfg(feature = "image")]
+    if let Some(path) = &cli.save_image {
+        interpreter.save_image(path).unwrap_or_else(|e| fail(e));
     }
+
+    exit(status)
+}
+
+#[cfg(feature = "image")]
+fn fail(e: InterpreterError) -> ! {
+    eprintln!("Interpreter error: {e}");
+    exit(1)
 }
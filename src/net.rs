@@ -0,0 +1,109 @@
+use std::{
+    fmt::Display,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+};
+
+type Result<A> = std::result::Result<A, String>;
+
+/// A handle to a TCP connection or listener. Copies share the same socket,
+/// which is closed once the last one is dropped.
+#[derive(Debug, Clone)]
+pub struct Socket(Arc<Kind>);
+
+#[derive(Debug)]
+enum Kind {
+    /// Buffered so lines can be received without losing what follows them.
+    Stream(Mutex<BufReader<TcpStream>>),
+    Listener(TcpListener),
+}
+
+impl Socket {
+    pub fn connect(host: &str, port: u16) -> Result<Self> {
+        TcpStream::connect((host, port))
+            .map(Self::stream)
+            .map_err(|e| format!("Couldn't connect to {host}:{port}: {e}"))
+    }
+
+    pub fn listen(host: &str, port: u16) -> Result<Self> {
+        TcpListener::bind((host, port))
+            .map(|l| Self(Arc::new(Kind::Listener(l))))
+            .map_err(|e| format!("Couldn't listen on {host}:{port}: {e}"))
+    }
+
+    /// Waits for a connection to a listener.
+    pub fn accept(&self) -> Result<Self> {
+        match &*self.0 {
+            Kind::Listener(l) => l.accept()
+                .map(|(s, _)| Self::stream(s))
+                .map_err(|e| format!("Couldn't accept a connection: {e}")),
+            Kind::Stream(_) => Err(format!("Can't accept on {self}")),
+        }
+    }
+
+    pub fn send(&self, s: &str) -> Result<()> {
+        self.with_stream(|r| r.get_mut().write_all(s.as_bytes()))?
+            .map_err(|e| format!("Couldn't send to {self}: {e}"))
+    }
+
+    /// Receives a line without its line ending, or `None` once the peer has
+    /// closed the connection.
+    pub fn recv(&self) -> Result<Option<String>> {
+        let mut line = String::new();
+        let n = self.with_stream(|r| r.read_line(&mut line))?
+            .map_err(|e| format!("Couldn't receive from {self}: {e}"))?;
+
+        if line.ends_with('\n') {
+            line.pop();
+
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+
+        Ok((n > 0).then_some(line))
+    }
+
+    /// The local port, so listeners bound to port 0 can be connected to.
+    pub fn port(&self) -> Result<u16> {
+        match &*self.0 {
+            Kind::Stream(s) => s.lock().unwrap().get_ref().local_addr(),
+            Kind::Listener(l) => l.local_addr(),
+        }.map(|a| a.port()).map_err(|e| e.to_string())
+    }
+
+    fn stream(stream: TcpStream) -> Self {
+        Self(Arc::new(Kind::Stream(Mutex::new(BufReader::new(stream)))))
+    }
+
+    fn with_stream<A>(
+        &self,
+        f: impl FnOnce(&mut BufReader<TcpStream>) -> std::io::Result<A>,
+    ) -> Result<std::io::Result<A>> {
+        match &*self.0 {
+            Kind::Stream(s) => Ok(f(&mut s.lock().unwrap())),
+            Kind::Listener(_) => Err(format!("Can't transfer data on {self}")),
+        }
+    }
+}
+
+impl PartialEq for Socket {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Display for Socket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match &*self.0 {
+            Kind::Stream(_) => "connection",
+            Kind::Listener(_) => "listener",
+        };
+
+        match self.port() {
+            Ok(port) => write!(f, "<{kind} :{port}>"),
+            Err(_) => write!(f, "<{kind}>"),
+        }
+    }
+}
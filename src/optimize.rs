@@ -0,0 +1,201 @@
+use crate::{
+    Builtin, Expression, Interpreter, Procedure, Program, Statement, Value,
+};
+
+impl Program {
+    /// Evaluates builtins applied to constants ahead of time, like `2 3 +`
+    /// to `5`, and replaces `if`, `when` and `unless` on a constant flag by
+    /// the branch they take. Builtins which would fail are left for the
+    /// interpreter to report.
+    pub fn optimize(self) -> Self {
+        Self { statements: optimize(&self.statements).into() }
+    }
+}
+
+fn optimize(statements: &[Statement]) -> Vec<Statement> {
+    let mut optimized = vec![];
+
+    for statement in statements {
+        fold(&mut optimized, nested(statement));
+    }
+
+    optimized
+}
+
+/// Optimizes the procedures in a statement.
+fn nested(statement: &Statement) -> Statement {
+    let procedure = |p: &Procedure| Procedure(optimize(&p.0).into());
+
+    match statement {
+        Statement::Expression(Expression::Procedure(p)) =>
+            Statement::Expression(Expression::Procedure(procedure(p))),
+        Statement::Definition { identifier, effect, procedure: p } =>
+            Statement::Definition {
+                identifier: *identifier,
+                effect: effect.clone(),
+                procedure: procedure(p),
+            },
+        Statement::Locals { names, body } => Statement::Locals {
+            names: names.clone(),
+            body: procedure(body),
+        },
+        s => s.clone(),
+    }
+}
+
+/// Appends a statement, evaluating it with the statements before it if they
+/// are constants.
+fn fold(optimized: &mut Vec<Statement>, statement: Statement) {
+    let Statement::Builtin(builtin, _) = statement else {
+        return optimized.push(statement);
+    };
+
+    if let Some((n, p)) = branch(optimized, builtin) {
+        optimized.truncate(optimized.len() - n);
+
+        for s in p.0.iter() {
+            fold(optimized, s.clone());
+        }
+
+        return;
+    }
+
+    let results = builtin.stack_effect()
+        .filter(|_| builtin.is_pure())
+        .and_then(|(inputs, _)| {
+            let start = optimized.len().checked_sub(inputs)?;
+            let args = optimized[start..].iter()
+                .map(constant)
+                .collect::<Option<_>>()?;
+
+            Some((start, Interpreter::evaluate_constant(builtin, args)?))
+        });
+
+    match results {
+        Some((start, results)) => {
+            optimized.truncate(start);
+            optimized.extend(results.into_iter().map(Statement::Value));
+        }
+        None => optimized.push(statement),
+    }
+}
+
+/// If `builtin` is `if`, `when` or `unless` and the statements before it
+/// push a constant flag and literal procedures, returns the number of these
+/// statements and the procedure it runs.
+fn branch(optimized: &[Statement], builtin: Builtin)
+-> Option<(usize, Procedure)> {
+    let (n, on) = match builtin {
+        Builtin::If => (3, true),
+        Builtin::When => (2, true),
+        Builtin::Unless => (2, false),
+        _ => return None,
+    };
+    let start = optimized.len().checked_sub(n)?;
+    let flag = constant(&optimized[start])?;
+    let procedures = optimized[start + 1..].iter()
+        .map(procedure)
+        .collect::<Option<Vec<_>>>()?;
+    // Leave a flag that isn't a bool for the conditional to reject.
+    let Value::Bool(flag) = flag else { return None };
+    let taken = match (flag == on, &procedures[..]) {
+        (true, [p, ..]) => p.clone(),
+        (false, [_, esle]) => esle.clone(),
+        _ => Procedure([].into()),
+    };
+
+    (!recurses(&taken.0)).then_some((n, taken))
+}
+
+/// The value a statement pushes if it is a constant.
+fn constant(statement: &Statement) -> Option<Value> {
+    match statement {
+        Statement::Expression(Expression::Literal(l)) => Some(l.clone().into()),
+        Statement::Value(Value::Procedure(_)) => None,
+        Statement::Value(v) => Some(v.clone()),
+        _ => None,
+    }
+}
+
+/// The procedure a statement pushes if it is a literal.
+fn procedure(statement: &Statement) -> Option<Procedure> {
+    match statement {
+        Statement::Expression(Expression::Procedure(p))
+            | Statement::Value(Value::Procedure(p)) => Some(p.clone()),
+        _ => None,
+    }
+}
+
+/// Whether statements refer to the procedure they are in through `recurse`,
+/// so they can't be moved out of it. `recurse` in a nested procedure literal
+/// refers to the outermost one.
+pub(crate) fn recurses(statements: &[Statement]) -> bool {
+    fn expression(e: &Expression) -> bool {
+        match e {
+            Expression::Procedure(p) => recurses(&p.0),
+            Expression::List(l) => l.iter().any(expression),
+            Expression::Literal(_) => false,
+        }
+    }
+
+    statements.iter().any(|s| match s {
+        Statement::Word(w, _) => w.as_str() == "recurse",
+        Statement::Expression(e) => expression(e),
+        Statement::Locals { body, .. } => recurses(&body.0),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{program, Statement, Value};
+
+    fn optimize(input: &str) -> Vec<Statement> {
+        program(input).unwrap().1.optimize().statements.to_vec()
+    }
+
+    fn parse(input: &str) -> Vec<Statement> {
+        program(input).unwrap().1.statements.to_vec()
+    }
+
+    #[test]
+    fn folding() {
+        assert_eq!(optimize("2 3 +"), vec![Statement::Value(Value::Int(5))]);
+        assert_eq!(
+            optimize("1 2 3 * + dup"),
+            [7, 7].map(|a| Statement::Value(Value::Int(a))).to_vec()
+        );
+        assert_eq!(
+            optimize("\"a\" upper 2 x *"),
+            [
+                vec![Statement::Value(Value::String("A".into()))],
+                parse("2 x *"),
+            ].concat()
+        );
+        assert_eq!(optimize("1 0 mod"), parse("1 0 mod"));
+        assert_eq!(optimize("read-line 1 +"), parse("read-line 1 +"));
+
+        let [Statement::Definition { procedure, .. }] =
+            &optimize("def f { 1 1 + }")[..]
+        else {
+            panic!("Expected a definition");
+        };
+
+        assert_eq!(procedure.0[..], [Statement::Value(Value::Int(2))]);
+    }
+
+    #[test]
+    fn branches() {
+        assert_eq!(
+            optimize("1 2 < { 3 } { 4 } if 1 +"),
+            vec![Statement::Value(Value::Int(4))]
+        );
+        assert_eq!(optimize("false { 3 } when"), vec![]);
+        assert_eq!(optimize("false { f } unless"), parse("f"));
+        assert_eq!(optimize("x { 3 } { 4 } if"), parse("x { 3 } { 4 } if"));
+        assert_eq!(
+            optimize("true { recurse } { } if"),
+            parse("true { recurse } { } if")
+        );
+    }
+}
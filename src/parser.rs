@@ -1,29 +1,62 @@
+use std::rc::Rc;
+
 use nom::{
     branch::alt,
-    bytes::complete::{is_not, tag},
+    bytes::complete::{is_not, tag, take_until, take_while, take_while1},
     character::complete::{
-        alpha1, alphanumeric0, char, multispace0, multispace1, space0, space1
+        char, digit1, multispace0, multispace1, one_of, satisfy, space1
+    },
+    combinator::{
+        all_consuming, cut, map_opt, map_res, not, opt, recognize, value,
+        verify
     },
-    combinator::{all_consuming, cut, opt, recognize, value},
     error::{context, VerboseError},
-    multi::{separated_list0, separated_list1},
-    number::complete::float,
-    sequence::{delimited, pair, preceded, terminated, tuple},
+    multi::{many0, many1, separated_list0, separated_list1},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     Parser
 };
+use num_rational::Rational64;
+use unicode_ident::{is_xid_continue, is_xid_start};
 
-use crate::{Builtin, Expression, Literal, Procedure, Program, Statement};
+use crate::{
+    Builtin, Expression, Literal, Procedure, Program, Span, StackEffect,
+    Statement, Symbol,
+};
 
 type IResult<I, O> = nom::IResult<I, O, VerboseError<I>>;
 
 fn eol_comment(input: &str) -> IResult<&str, &str> {
-    preceded(char('#'), is_not("\n\r"))(input)
+    preceded(pair(char('#'), not(char('['))), rest_of_line)(input)
+}
+
+fn rest_of_line(input: &str) -> IResult<&str, &str> {
+    take_while(|c| c != '\n' && c != '\r')(input)
+}
+
+/// A line like `#!/usr/bin/env stack` at the start of a script.
+fn shebang(input: &str) -> IResult<&str, &str> {
+    preceded(tag("#!"), rest_of_line)(input)
+}
+
+/// A comment like `#[ ... ]#`, which may span several lines.
+fn block_comment(input: &str) -> IResult<&str, &str> {
+    context(
+        "Block comment",
+        preceded(tag("#["), cut(terminated(take_until("]#"), tag("]#"))))
+    )(input)
+}
+
+/// Whitespace matched by `space`, mixed with any number of block comments.
+fn gap<'a>(space: fn(&'a str) -> IResult<&'a str, &'a str>)
+-> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    recognize(many1(alt((space, block_comment))))
 }
 
 fn statements(input: &str) -> IResult<&str, Box<[Statement]>> {
-    let line = terminated(
-        separated_list0(space1, statement),
-        pair(space0, opt(eol_comment))
+    let line = delimited(
+        opt(gap(space1)),
+        separated_list0(gap(space1), statement),
+        pair(opt(gap(space1)), opt(eol_comment))
     );
 
     preceded(multispace0, separated_list1(multispace1, line))
@@ -34,16 +67,87 @@ fn statements(input: &str) -> IResult<&str, Box<[Statement]>> {
 pub fn program(input: &str) -> IResult<&str, Program> {
     context(
         "Program",
-        all_consuming(statements).map(|statements| Program { statements })
+        all_consuming(preceded(opt(shebang), statements))
+            .map(|mut statements| {
+                let lines = std::iter::once(0)
+                    .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+                    .collect::<Vec<_>>();
+
+                locate(&mut statements, input, &lines);
+
+                Program { statements }
+            })
     )(input)
 }
 
+/// Turns the spans of statements parsed from `input`, which hold the length
+/// of the input that was left, into lines and columns. `lines` are the
+/// offsets the lines of `input` start at.
+fn locate(statements: &mut [Statement], input: &str, lines: &[usize]) {
+    fn expression(e: &mut Expression, input: &str, lines: &[usize]) {
+        match e {
+            Expression::Procedure(p) =>
+                locate(Rc::make_mut(&mut p.0), input, lines),
+            Expression::List(l) =>
+                l.iter_mut().for_each(|e| expression(e, input, lines)),
+            Expression::Literal(_) => {}
+        }
+    }
+
+    for s in statements {
+        match s {
+            Statement::Builtin(_, span) | Statement::Word(_, span) => {
+                let offset = input.len() - span.column;
+                let line = lines.partition_point(|&l| l <= offset);
+
+                *span = Span {
+                    line,
+                    column: input[lines[line - 1]..offset].chars().count() + 1,
+                };
+            }
+            Statement::Definition { procedure: p, .. }
+                | Statement::Locals { body: p, .. } =>
+                locate(Rc::make_mut(&mut p.0), input, lines),
+            Statement::Expression(e) => expression(e, input, lines),
+            _ => {}
+        }
+    }
+}
+
+/// Punctuation which may appear anywhere in identifiers like `set!`,
+/// `->string` or `<point>`.
+const PUNCTUATION: &str = "-?!<>=*+/";
+
+/// The words starting a declaration. They end the field list of a struct.
+pub(crate) const KEYWORDS: [&str; 5] =
+    ["def", "import", "struct", "var", "vocab"];
+
+fn is_identifier_char(c: char) -> bool {
+    is_xid_continue(c) || PUNCTUATION.contains(c)
+}
+
+/// An identifier following Unicode's XID rules like `größe` or `π`, mixed
+/// with punctuation. After any leading punctuation it has to start with a
+/// letter or `_`, so it can't be confused with a number or builtin like `-`.
+/// `true`, `false` and `nil` are reserved.
 pub fn identifier(input: &str) -> IResult<&str, &str> {
-    context("Identifier", recognize(tuple((
-        alt((alpha1, tag("_"))),
-        alphanumeric0,
-        opt(char('?')),
-    ))))(input)
+    context("Identifier", verify(
+        recognize(tuple((
+            take_while(|c| PUNCTUATION.contains(c)),
+            satisfy(|c| c == '_' || is_xid_start(c)),
+            take_while(is_identifier_char),
+        ))),
+        |w: &str| !["true", "false", "nil"].contains(&w)
+    ))(input)
+}
+
+/// An identifier, optionally qualified by a vocabulary like `math.square`.
+/// It can't be followed by a quote, so raw strings aren't parsed as words.
+pub fn word(input: &str) -> IResult<&str, &str> {
+    context("Word", terminated(
+        recognize(pair(identifier, opt(pair(char('.'), identifier)))),
+        not(pair(many0(char('#')), char('"')))
+    ))(input)
 }
 
 pub fn expression(input: &str) -> IResult<&str, Expression> {
@@ -57,16 +161,37 @@ pub fn expression(input: &str) -> IResult<&str, Expression> {
 pub fn procedure(input: &str) -> IResult<&str, Procedure> {
     context("Procedure", delimited(
         char('{'),
-        cut(Parser::into(statements).map(Procedure)),
+        cut(alt((
+            locals.map(|s| Procedure([s].into())),
+            Parser::into(statements).map(Procedure),
+        ))),
         cut(char('}'))
     ))(input)
 }
 
+/// The body of a procedure with local bindings, i.e. `| x y | body`.
+fn locals(input: &str) -> IResult<&str, Statement> {
+    let names = terminated(
+        many0(preceded(multispace0, Parser::into(identifier))),
+        pair(multispace0, char('|'))
+    );
+
+    context("Locals", preceded(
+        pair(multispace0, char('|')),
+        cut(pair(names, Parser::into(statements).map(Procedure)))
+    ))
+        .map(|(names, body): (Vec<_>, _)| Statement::Locals {
+            names: names.into(),
+            body,
+        })
+        .parse(input)
+}
+
 pub fn list(input: &str) -> IResult<&str, Box<[Expression]>> {
     let items = delimited(
-        multispace0,
-        separated_list0(multispace1, expression),
-        multispace0
+        opt(gap(multispace1)),
+        separated_list0(gap(multispace1), expression),
+        opt(gap(multispace1))
     );
 
     context("List", delimited(char('['), Parser::into(items), char(']')))(input)
@@ -75,86 +200,535 @@ pub fn list(input: &str) -> IResult<&str, Box<[Expression]>> {
 pub fn definition(input: &str) -> IResult<&str, Statement> {
     context("Definition", preceded(
         pair(tag("def"), multispace1),
-        cut(pair(
-            Parser::into(identifier),
-            preceded(multispace0, procedure)
-        )),
+        cut(tuple((
+            identifier.map(Symbol::new),
+            opt(preceded(multispace1, stack_effect)),
+            preceded(multispace0, alt((
+                procedure,
+                // A constant is a procedure which only pushes its value.
+                expression
+                    .map(|e| Procedure([Statement::Expression(e)].into())),
+            )))
+        ))),
     ))
-        .map(|(identifier, procedure)| Statement::Definition {
+        .map(|(identifier, effect, procedure)| Statement::Definition {
             identifier,
+            effect,
             procedure,
         })
         .parse(input)
 }
 
+pub fn import(input: &str) -> IResult<&str, Statement> {
+    context("Import", preceded(
+        pair(tag("import"), multispace1),
+        cut(Parser::into(string).map(Statement::Import))
+    ))(input)
+}
+
+pub fn structure(input: &str) -> IResult<&str, Statement> {
+    context("Struct", preceded(
+        pair(tag("struct"), space1),
+        cut(pair(
+            Parser::into(identifier),
+            context("Distinct fields", verify(
+                many0(preceded(space1, Parser::into(verify(
+                    identifier,
+                    |field: &str| !KEYWORDS.contains(&field)
+                )))),
+                |fields: &Vec<String>| fields.iter().enumerate()
+                    .all(|(i, f)| !fields[..i].contains(f))
+            ))
+        ))
+    ))
+        .map(|(name, fields): (_, Vec<_>)| Statement::Struct {
+            name,
+            fields: fields.into(),
+        })
+        .parse(input)
+}
+
+pub fn var(input: &str) -> IResult<&str, Statement> {
+    context("Var", preceded(
+        pair(tag("var"), multispace1),
+        cut(Parser::into(identifier).map(Statement::Var))
+    ))(input)
+}
+
+pub fn vocab(input: &str) -> IResult<&str, Statement> {
+    context("Vocab", preceded(
+        pair(tag("vocab"), multispace1),
+        cut(Parser::into(identifier).map(Statement::Vocab))
+    ))(input)
+}
+
+/// A stack effect declaration like `( a b -- c )`.
+pub fn stack_effect(input: &str) -> IResult<&str, StackEffect> {
+    let names = || many0(
+        terminated(effect_name, multispace0).map(String::from)
+    );
+
+    context("StackEffect", delimited(
+        pair(char('('), multispace0),
+        cut(separated_pair(names(), pair(tag("--"), multispace0), names())),
+        cut(char(')'))
+    ))
+        .map(|(inputs, outputs): (Vec<_>, Vec<_>)| StackEffect {
+            inputs: inputs.into(),
+            outputs: outputs.into(),
+        })
+        .parse(input)
+}
+
+fn effect_name(input: &str) -> IResult<&str, &str> {
+    preceded(not(tag("--")), is_not(" \t\r\n()"))(input)
+}
+
+/// A statement. The spans of words and builtins are only resolved by
+/// [`program`], until then they hold the length of the remaining input.
 pub fn statement(input: &str) -> IResult<&str, Statement> {
+    let span = Span { line: 0, column: input.len() };
+
     context("Statement", alt((
         definition,
-        builtin.map(Statement::Builtin),
-        Parser::into(identifier).map(Statement::Word),
+        import,
+        structure,
+        var,
+        vocab,
+        builtin.map(move |b| Statement::Builtin(b, span)),
+        word.map(move |w| Statement::Word(Symbol::new(w), span)),
         expression.map(Statement::Expression),
     )))(input)
 }
 
+/// A builtin, which can't be directly followed by a character that could be
+/// part of an identifier, so `dupe` and `set!` are words.
 pub fn builtin(input: &str) -> IResult<&str, Builtin> {
     context("Builtin", alt((
-        value(Builtin::Add, tag("+")),
-        value(Builtin::Sub, tag("-")),
-        value(Builtin::Mul, tag("*")),
-        value(Builtin::Div, tag("/")),
-        value(Builtin::Eq, tag("=")),
-        value(Builtin::Neg, tag("!")),
-        value(Builtin::Le, tag("<=")),
-        value(Builtin::Lt, tag("<")),
-        value(Builtin::Ge, tag(">=")),
-        value(Builtin::Gt, tag(">")),
-        value(Builtin::Swap, tag("swap")),
-        value(Builtin::Drop, tag("drop")),
-        value(Builtin::Drop2, tag("2drop")),
-        value(Builtin::Drop3, tag("3drop")),
-        value(Builtin::Over, tag("over")),
-        value(Builtin::Eval, tag("eval")),
-        value(Builtin::Dupd, tag("dupd")),
-        value(Builtin::Dup, tag("dup")),
-        value(Builtin::Dup2, tag("2dup")),
-        value(Builtin::Rotl, tag("rotl")),
-        value(Builtin::Rotr, tag("rotr")),
+        value(Builtin::Add, keyword("+")),
+        value(Builtin::Sub, keyword("-")),
+        value(Builtin::Mul, keyword("*")),
+        value(Builtin::Div, keyword("/")),
+        value(Builtin::Eq, keyword("=")),
+        value(Builtin::Neg, keyword("!")),
+        value(Builtin::Le, keyword("<=")),
+        value(Builtin::Lt, keyword("<")),
+        value(Builtin::Ge, keyword(">=")),
+        value(Builtin::Gt, keyword(">")),
+        value(Builtin::Swap, keyword("swap")),
+        value(Builtin::Drop, keyword("drop")),
+        value(Builtin::Drop2, keyword("2drop")),
+        value(Builtin::Drop3, keyword("3drop")),
+        value(Builtin::Over, keyword("over")),
+        value(Builtin::Eval, keyword("eval")),
+        value(Builtin::Dupd, keyword("dupd")),
+        value(Builtin::Dup, keyword("dup")),
+        value(Builtin::Dup2, keyword("2dup")),
+        value(Builtin::Rotl, keyword("rotl")),
+        value(Builtin::Rotr, keyword("rotr")),
     )).or(alt((
-        value(Builtin::Keep, tag("keep")),
-        value(Builtin::Println, tag("println")),
-        value(Builtin::If, tag("if")),
-        value(Builtin::Nth, tag("nth")),
-    ))))(input)
+        value(Builtin::Keep, keyword("keep")),
+        value(Builtin::Bi, keyword("bi")),
+        value(Builtin::Cleave, keyword("cleave")),
+        value(Builtin::Println, keyword("println")),
+        value(Builtin::If, keyword("if")),
+        value(Builtin::Nth, keyword("nth")),
+        value(Builtin::Min, keyword("min")),
+        value(Builtin::Max, keyword("max")),
+        value(Builtin::Map, keyword("map")),
+        value(Builtin::Filter, keyword("filter")),
+        value(Builtin::Fold, keyword("fold")),
+        value(Builtin::Each, keyword("each")),
+        value(Builtin::Curry, keyword("curry")),
+        value(Builtin::Compose, keyword("compose")),
+        value(Builtin::Times, keyword("times")),
+        value(Builtin::While, keyword("while")),
+        value(Builtin::RangeStep, keyword("range-step")),
+        value(Builtin::Range, keyword("range")),
+        value(Builtin::Abs, keyword("abs")),
+        value(Builtin::Len, keyword("len")),
+    ))).or(alt((
+        value(Builtin::Append, keyword("append")),
+        value(Builtin::Head, keyword("head")),
+        value(Builtin::Tail, keyword("tail")),
+        value(Builtin::Uncons, keyword("uncons")),
+        value(Builtin::Cons, keyword("cons")),
+        value(Builtin::Push, keyword("push")),
+        value(Builtin::SortBy, keyword("sort-by")),
+        value(Builtin::Sort, keyword("sort")),
+        value(Builtin::Zip, keyword("zip")),
+        value(Builtin::Flatten, keyword("flatten")),
+        value(Builtin::Contains, keyword("contains?")),
+        value(Builtin::IndexOf, keyword("index-of")),
+        value(Builtin::Slice, keyword("slice")),
+        value(Builtin::SetNth, keyword("set-nth")),
+        value(Builtin::Split, keyword("split")),
+        value(Builtin::Join, keyword("join")),
+        value(Builtin::Upper, keyword("upper")),
+        value(Builtin::Lower, keyword("lower")),
+        value(Builtin::Substring, keyword("substring")),
+        value(Builtin::Spread, keyword("spread")),
+        value(Builtin::Apply, keyword("apply")),
+    ))).or(alt((
+        value(Builtin::TrimStart, keyword("trim-start")),
+        value(Builtin::TrimEnd, keyword("trim-end")),
+        value(Builtin::Trim, keyword("trim")),
+        value(Builtin::Tri, keyword("tri")),
+        value(Builtin::StringToNumber, keyword("string->number")),
+        value(Builtin::ToString, keyword("to-string")),
+        value(Builtin::Format, keyword("format")),
+        value(Builtin::Ord, keyword("ord")),
+        value(Builtin::Chr, keyword("chr")),
+        value(Builtin::Mod, keyword("mod")),
+        value(Builtin::Pow, keyword("pow")),
+        value(Builtin::Sqrt, keyword("sqrt")),
+        value(Builtin::Floor, keyword("floor")),
+        value(Builtin::Ceil, keyword("ceil")),
+        value(Builtin::Round, keyword("round")),
+        value(Builtin::Trunc, keyword("trunc")),
+    ))).or(alt((
+        value(Builtin::ListToSet, keyword("list->set")),
+        value(Builtin::SetToList, keyword("set->list")),
+        value(Builtin::QuotToList, keyword("quot->list")),
+        value(Builtin::ListToQuot, keyword("list->quot")),
+        value(Builtin::SetAdd, keyword("set-add")),
+        value(Builtin::SetHas, keyword("set-has?")),
+        value(Builtin::Union, keyword("union")),
+        value(Builtin::Intersect, keyword("intersect")),
+        value(Builtin::Difference, keyword("difference")),
+    ))).or(alt((
+        value(Builtin::IsNil, keyword("nil?")),
+        value(Builtin::Default, keyword("default")),
+        value(Builtin::NthOrNil, keyword("?nth")),
+        value(Builtin::Throw, keyword("throw")),
+        value(Builtin::Try, keyword("try")),
+        value(Builtin::Assert, keyword("assert")),
+        value(Builtin::Cond, keyword("cond")),
+        value(Builtin::When, keyword("when")),
+        value(Builtin::Unless, keyword("unless")),
+    ))).or(alt((
+        value(Builtin::Pick, keyword("pick")),
+        value(Builtin::Roll, keyword("roll")),
+        value(Builtin::Depth, keyword("depth")),
+        value(Builtin::Clear, keyword("clear")),
+        value(Builtin::Nip, keyword("nip")),
+        value(Builtin::Tuck, keyword("tuck")),
+        value(Builtin::Set, keyword("set")),
+        value(Builtin::Get, keyword("get")),
+    ))).or(alt((
+        value(Builtin::Sin, keyword("sin")),
+        value(Builtin::Cos, keyword("cos")),
+        value(Builtin::Tan, keyword("tan")),
+        value(Builtin::Atan2, keyword("atan2")),
+        value(Builtin::Ln, keyword("ln")),
+        value(Builtin::Log10, keyword("log10")),
+        value(Builtin::Exp, keyword("exp")),
+        value(Builtin::Pi, keyword("pi")),
+        value(Builtin::E, keyword("e")),
+        value(Builtin::AndThen, keyword("and-then")),
+        value(Builtin::OrElse, keyword("or-else")),
+        value(Builtin::And, keyword("and")),
+        value(Builtin::Or, keyword("or")),
+        value(Builtin::Xor, keyword("xor")),
+        value(Builtin::BitAnd, keyword("band")),
+        value(Builtin::BitOr, keyword("bor")),
+        value(Builtin::BitXor, keyword("bxor")),
+        value(Builtin::Shl, keyword("shl")),
+        value(Builtin::Shr, keyword("shr")),
+    ))).or(alt((
+        value(Builtin::ReadLine, keyword("read-line")),
+        value(Builtin::ReadFile, keyword("read-file")),
+        value(Builtin::WriteFile, keyword("write-file")),
+        value(Builtin::AppendFile, keyword("append-file")),
+        value(Builtin::FileExists, keyword("file-exists?")),
+        value(Builtin::DeleteFile, keyword("delete-file")),
+        value(Builtin::ListDir, keyword("list-dir")),
+        value(Builtin::Mkdir, keyword("mkdir")),
+        value(Builtin::Env, keyword("env")),
+        value(Builtin::SetEnv, keyword("set-env")),
+        value(Builtin::Args, keyword("args")),
+        value(Builtin::Exit, keyword("exit")),
+        value(Builtin::Now, keyword("now")),
+        value(Builtin::Monotonic, keyword("monotonic")),
+        value(Builtin::Sleep, keyword("sleep")),
+        value(Builtin::Print, keyword("print")),
+        value(Builtin::Eprintln, keyword("eprintln")),
+        value(Builtin::Eprint, keyword("eprint")),
+    ))).or(regex_builtin).or(sh_builtin).or(time_builtin)
+        .or(json_builtin).or(image_builtin).or(csv_builtin)
+        .or(http_builtin).or(tcp_builtin))(input)
+}
+
+/// Matches `name` only if it isn't the start of a longer identifier.
+fn keyword<'a>(name: &'static str)
+-> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    terminated(tag(name), not(satisfy(is_identifier_char)))
+}
+
+#[cfg(feature = "regex")]
+fn regex_builtin(input: &str) -> IResult<&str, Builtin> {
+    alt((
+        value(Builtin::ReMatch, keyword("re-match?")),
+        value(Builtin::ReFind, keyword("re-find")),
+        value(Builtin::ReReplace, keyword("re-replace")),
+    ))(input)
+}
+
+#[cfg(not(feature = "regex"))]
+fn regex_builtin(input: &str) -> IResult<&str, Builtin> {
+    nom::combinator::fail(input)
+}
+
+#[cfg(feature = "sh")]
+fn sh_builtin(input: &str) -> IResult<&str, Builtin> {
+    value(Builtin::Sh, keyword("sh"))(input)
+}
+
+#[cfg(not(feature = "sh"))]
+fn sh_builtin(input: &str) -> IResult<&str, Builtin> {
+    nom::combinator::fail(input)
+}
+
+#[cfg(feature = "chrono")]
+fn time_builtin(input: &str) -> IResult<&str, Builtin> {
+    alt((
+        value(Builtin::FormatTime, keyword("format-time")),
+        value(Builtin::ParseTime, keyword("parse-time")),
+    ))(input)
+}
+
+#[cfg(not(feature = "chrono"))]
+fn time_builtin(input: &str) -> IResult<&str, Builtin> {
+    nom::combinator::fail(input)
+}
+
+#[cfg(feature = "serde_json")]
+fn json_builtin(input: &str) -> IResult<&str, Builtin> {
+    alt((
+        value(Builtin::JsonParse, keyword("json-parse")),
+        value(Builtin::JsonEncode, keyword("json-encode")),
+    ))(input)
+}
+
+#[cfg(not(feature = "serde_json"))]
+fn json_builtin(input: &str) -> IResult<&str, Builtin> {
+    nom::combinator::fail(input)
+}
+
+#[cfg(feature = "image")]
+fn image_builtin(input: &str) -> IResult<&str, Builtin> {
+    alt((
+        value(Builtin::SaveImage, keyword("save-image")),
+        value(Builtin::LoadImage, keyword("load-image")),
+    ))(input)
+}
+
+#[cfg(not(feature = "image"))]
+fn image_builtin(input: &str) -> IResult<&str, Builtin> {
+    nom::combinator::fail(input)
+}
+
+#[cfg(feature = "csv")]
+fn csv_builtin(input: &str) -> IResult<&str, Builtin> {
+    alt((
+        value(Builtin::CsvParse, keyword("csv-parse")),
+        value(Builtin::CsvParseWith, keyword("csv-parse-with")),
+        value(Builtin::CsvEncode, keyword("csv-encode")),
+        value(Builtin::CsvEncodeWith, keyword("csv-encode-with")),
+    ))(input)
+}
+
+#[cfg(not(feature = "csv"))]
+fn csv_builtin(input: &str) -> IResult<&str, Builtin> {
+    nom::combinator::fail(input)
+}
+
+#[cfg(feature = "ureq")]
+fn http_builtin(input: &str) -> IResult<&str, Builtin> {
+    alt((
+        value(Builtin::HttpGet, keyword("http-get")),
+        value(Builtin::HttpPost, keyword("http-post")),
+    ))(input)
+}
+
+#[cfg(not(feature = "ureq"))]
+fn http_builtin(input: &str) -> IResult<&str, Builtin> {
+    nom::combinator::fail(input)
+}
+
+#[cfg(feature = "tcp")]
+fn tcp_builtin(input: &str) -> IResult<&str, Builtin> {
+    alt((
+        value(Builtin::TcpConnect, keyword("tcp-connect")),
+        value(Builtin::TcpListen, keyword("tcp-listen")),
+        value(Builtin::TcpAccept, keyword("tcp-accept")),
+        value(Builtin::TcpSend, keyword("tcp-send")),
+        value(Builtin::TcpRecv, keyword("tcp-recv")),
+    ))(input)
+}
+
+#[cfg(not(feature = "tcp"))]
+fn tcp_builtin(input: &str) -> IResult<&str, Builtin> {
+    nom::combinator::fail(input)
 }
 
 pub fn literal(input: &str) -> IResult<&str, Literal> {
     context("Literal", alt((
+        radix_integer.map(Literal::Int),
+        rational.map(Literal::Rational),
+        integer.map(Literal::Int),
+        big_integer,
         float.map(Literal::Number),
         Parser::into(string).map(Literal::String),
+        symbol.map(Literal::Symbol),
         bool.map(Literal::Bool),
+        nil,
     )))(input)
 }
 
+/// An integer literal, i.e. digits not followed by a fraction or exponent.
+pub fn integer(input: &str) -> IResult<&str, i64> {
+    context("Integer", map_res(
+        terminated(signed_digits, not(one_of(".eE"))),
+        |s| s.replace('_', "").parse()
+    ))(input)
+}
+
+/// An integer literal too large for an `i64`.
+#[cfg(feature = "num-bigint")]
+pub fn big_integer(input: &str) -> IResult<&str, Literal> {
+    context("Integer", map_res(
+        terminated(signed_digits, not(one_of(".eE"))),
+        |s| s.replace('_', "").parse().map(Literal::BigInt)
+    ))(input)
+}
+
+/// Fails on an integer literal too large for an `i64`, rather than letting
+/// it be read as a float and silently lose precision.
+#[cfg(not(feature = "num-bigint"))]
+pub fn big_integer(input: &str) -> IResult<&str, Literal> {
+    preceded(
+        terminated(signed_digits, not(one_of(".eE"))),
+        cut(context("Integer literal too large", nom::combinator::fail))
+    )(input)
+}
+
+/// A float literal like `1.5`, `1.` or `-2.5e-3`. Unlike Rust, this doesn't
+/// accept `inf`, `nan` or a missing integer part like in `.5`.
+pub fn float(input: &str) -> IResult<&str, f64> {
+    let number = recognize(tuple((
+        signed_digits,
+        opt(preceded(char('.'), opt(digits))),
+        opt(pair(one_of("eE"), cut(pair(opt(one_of("+-")), digits)))),
+    )));
+
+    context("Float", map_res(number, |s| s.replace('_', "").parse()))(input)
+}
+
+/// Decimal digits, which may be grouped by single underscores like in
+/// `1_000_000`.
+fn digits(input: &str) -> IResult<&str, &str> {
+    recognize(separated_list1(char('_'), digit1))(input)
+}
+
+/// Like [`digits`], but optionally preceded by a minus sign.
+fn signed_digits(input: &str) -> IResult<&str, &str> {
+    recognize(pair(opt(char('-')), digits))(input)
+}
+
+/// A hexadecimal, octal or binary integer literal like `0xff`, `0o17` or
+/// `0b1010`.
+pub fn radix_integer(input: &str) -> IResult<&str, i64> {
+    let digits = |prefix, radix| preceded(tag(prefix), cut(terminated(
+        map_res(
+            take_while1(move |c: char| c.is_digit(radix)),
+            move |d| i64::from_str_radix(d, radix)
+        ),
+        not(satisfy(|c| c.is_alphanumeric()))
+    )));
+
+    context(
+        "Integer",
+        alt((digits("0x", 16), digits("0o", 8), digits("0b", 2)))
+    )(input)
+}
+
+/// A rational literal, either a fraction like `1/3` or a decimal with a `d`
+/// suffix like `1.50d`.
+pub fn rational(input: &str) -> IResult<&str, Rational64> {
+    let fraction = map_opt(
+        separated_pair(signed_digits, char('/'), digits),
+        |(n, d): (&str, &str)| {
+            let d = d.replace('_', "").parse().ok().filter(|&d| d != 0)?;
+
+            Some(Rational64::new(n.replace('_', "").parse().ok()?, d))
+        }
+    );
+    let decimal = map_opt(
+        terminated(
+            pair(signed_digits, opt(preceded(char('.'), digits))),
+            pair(char('d'), not(satisfy(char::is_alphanumeric)))
+        ),
+        |(int, frac): (&str, Option<&str>)| {
+            let frac = frac.unwrap_or_default().replace('_', "");
+            let d = 10i64.checked_pow(frac.len().try_into().ok()?)?;
+            let n = format!("{int}{frac}").replace('_', "");
+
+            Some(Rational64::new(n.parse().ok()?, d))
+        }
+    );
+
+    context("Rational", alt((fraction, decimal)))(input)
+}
+
+/// A symbol literal like `:foo`.
+pub fn symbol(input: &str) -> IResult<&str, Symbol> {
+    context("Symbol", preceded(char(':'), identifier).map(Symbol::new))(input)
+}
+
 pub fn bool(input: &str) -> IResult<&str, bool> {
-    context("bool", alt((
-        value(false, tag("false")),
-        value(true, tag("true")),
-    )))(input)
+    context("bool", terminated(
+        alt((value(false, tag("false")), value(true, tag("true")))),
+        not(satisfy(is_identifier_char))
+    ))(input)
+}
+
+pub fn nil(input: &str) -> IResult<&str, Literal> {
+    context("nil", value(Literal::Nil, keyword("nil")))(input)
 }
 
 pub fn string(input: &str) -> IResult<&str, &str> {
-    context("String", delimited(
-        char('"'),
-        is_not("\\\""),
-        cut(char('"'))
-    ))(input)
+    context("String", alt((
+        raw_string,
+        delimited(tag("\"\"\""), take_until("\"\"\""), tag("\"\"\"")),
+        delimited(char('"'), is_not("\\\""), cut(char('"'))),
+    )))(input)
+}
+
+/// A raw string like `r"C:\dir"` or `r#"say "hi""#`, which ends at the first
+/// quote followed by as many `#` as it starts with.
+fn raw_string(input: &str) -> IResult<&str, &str> {
+    let (input, hashes) =
+        delimited(char('r'), recognize(many0(char('#'))), char('"'))(input)?;
+    let end = format!("\"{hashes}");
+
+    let (input, s) =
+        cut(terminated(take_until(end.as_str()), tag(end.as_str())))(input)?;
+
+    Ok((input, s))
 }
 
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "num-bigint")]
+    use num_bigint::BigInt;
+    use num_rational::Rational64;
+
     use crate::{
-        builtin, definition, expression, parser::statements, Builtin,
-        Expression, Literal, Procedure, Statement,
+        builtin, definition, expression, identifier, literal,
+        parser::statements, procedure, program, rational, stack_effect,
+        statement, Builtin, Expression, Literal, Procedure, Span, StackEffect,
+        Statement, Symbol,
     };
 
     #[test]
@@ -168,20 +742,231 @@ mod tests {
         assert_eq!(
             statements("1"),
             Ok(("", [Statement::Expression(Expression::Literal(
-                Literal::Number(1.0)
+                Literal::Int(1)
             ))].into()))
         );
     }
 
+    #[cfg(feature = "num-bigint")]
+    #[test]
+    fn big_integers() {
+        assert_eq!(
+            literal("99_999_999_999_999_999_999"),
+            Ok(("", Literal::BigInt("99999999999999999999".parse().unwrap())))
+        );
+        assert_eq!(
+            literal("-9223372036854775809"),
+            Ok(("", Literal::BigInt(BigInt::from(i64::MIN) - 1)))
+        );
+    }
+
+    #[cfg(not(feature = "num-bigint"))]
+    #[test]
+    fn big_integers() {
+        assert!(matches!(
+            literal("99999999999999999999"),
+            Err(nom::Err::Failure(_))
+        ));
+        assert!(program("99999999999999999999 println").is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(literal("42"), Ok(("", Literal::Int(42))));
+        assert_eq!(literal("4.5"), Ok(("", Literal::Number(4.5))));
+        assert_eq!(literal("4."), Ok(("", Literal::Number(4.0))));
+        assert_eq!(literal("1e3"), Ok(("", Literal::Number(1000.0))));
+        assert_eq!(
+            literal("1/3"),
+            Ok(("", Literal::Rational(Rational64::new(1, 3))))
+        );
+        assert_eq!(
+            literal("1.50d"),
+            Ok(("", Literal::Rational(Rational64::new(3, 2))))
+        );
+        assert_eq!(
+            literal("2d"),
+            Ok(("", Literal::Rational(Rational64::from_integer(2))))
+        );
+        assert!(rational("1/0").is_err());
+        assert_eq!(literal("1_000_000"), Ok(("", Literal::Int(1_000_000))));
+        assert_eq!(literal("-5"), Ok(("", Literal::Int(-5))));
+        assert_eq!(literal("1_0.2_5"), Ok(("", Literal::Number(10.25))));
+        assert_eq!(literal("-2.5e-1"), Ok(("", Literal::Number(-0.25))));
+        assert_eq!(literal("1.e2"), Ok(("", Literal::Number(100.0))));
+        assert_eq!(literal("0.1"), Ok(("", Literal::Number(0.1))));
+        assert_eq!(
+            literal("-1_000/3"),
+            Ok(("", Literal::Rational(Rational64::new(-1000, 3))))
+        );
+        assert_eq!(literal("1_"), Ok(("_", Literal::Int(1))));
+        assert!(literal("1e").is_err());
+        assert!(literal("_1").is_err());
+        assert!(literal(".5").is_err());
+        assert!(literal("inf").is_err());
+        assert!(literal("nan").is_err());
+        assert!(literal("-").is_err());
+        assert_eq!(literal("0xFF"), Ok(("", Literal::Int(255))));
+        assert_eq!(literal("0o17"), Ok(("", Literal::Int(15))));
+        assert_eq!(literal("0b1010"), Ok(("", Literal::Int(10))));
+        assert_eq!(
+            literal("0x7fffffffffffffff"),
+            Ok(("", Literal::Int(i64::MAX)))
+        );
+        assert!(literal("0x").is_err());
+        assert!(literal("0b102").is_err());
+        assert!(literal("0x10000000000000000").is_err());
+        assert_eq!(
+            literal("9223372036854775808.0"),
+            Ok(("", Literal::Number(9223372036854775808.0)))
+        );
+        assert_eq!(
+            literal(":foo-bar"),
+            Ok(("", Literal::Symbol(Symbol::new("foo-bar"))))
+        );
+        assert!(literal(":").is_err());
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(literal("\"hi\""), Ok(("", Literal::String("hi".into()))));
+        assert_eq!(
+            literal("\"\"\"say \"hi\"\nbye\"\"\""),
+            Ok(("", Literal::String("say \"hi\"\nbye".into())))
+        );
+        assert_eq!(
+            literal("r\"C:\\dir\""),
+            Ok(("", Literal::String("C:\\dir".into())))
+        );
+        assert_eq!(
+            literal("r#\"say \"hi\"\"#"),
+            Ok(("", Literal::String("say \"hi\"".into())))
+        );
+        assert_eq!(literal("r\"\""), Ok(("", Literal::String("".into()))));
+        assert!(literal("r#\"unterminated\"").is_err());
+        assert_eq!(
+            statements("r\"a\" r"),
+            Ok(("", [
+                Statement::Expression(
+                    Expression::Literal(Literal::String("a".into()))
+                ),
+                Statement::Word(Symbol::new("r"), Span::default()),
+            ].into()))
+        );
+    }
+
     #[test]
     fn comment() {
         assert_eq!(statements("# hello\n"), Ok(("", [].into())))
     }
 
+    #[test]
+    fn shebang() {
+        let one = Statement::Expression(Expression::Literal(Literal::Int(1)));
+
+        assert_eq!(
+            program("#!/usr/bin/env stack\n1").map(|(_, p)| p.statements),
+            Ok([one.clone()].into())
+        );
+        assert_eq!(
+            program("#!\r\n1 #\n#\n").map(|(_, p)| p.statements),
+            Ok([one].into())
+        );
+        assert!(program("1\n#!/usr/bin/env stack").is_ok());
+        assert!(program("1 #!").is_ok());
+    }
+
+    #[test]
+    fn spans() {
+        let (_, program) = program("1\n  dup\tgrüße +\ndef f { { swap } }")
+            .unwrap();
+        let spans = |statements: &[Statement]| statements.iter()
+            .map(|s| s.span().map(|s| (s.line, s.column)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            spans(&program.statements),
+            [None, Some((2, 3)), Some((2, 7)), Some((2, 13)), None]
+        );
+
+        let Statement::Definition { procedure, .. } = &program.statements[4]
+        else {
+            panic!("Expected a definition");
+        };
+        let Statement::Expression(Expression::Procedure(inner)) =
+            &procedure.0[0]
+        else {
+            panic!("Expected a procedure");
+        };
+
+        assert_eq!(spans(&inner.0), [Some((3, 11))]);
+        assert_eq!(statement("dup").unwrap().1.span(), None);
+    }
+
+    #[test]
+    fn block_comment() {
+        let one = Statement::Expression(Expression::Literal(Literal::Int(1)));
+        let two = Statement::Expression(Expression::Literal(Literal::Int(2)));
+
+        assert_eq!(statements("#[ hello ]#"), Ok(("", [].into())));
+        assert_eq!(
+            statements("#[ a\nb ]#\n1 #[ c\n d ]# 2 # e\n"),
+            Ok(("", [one.clone(), two.clone()].into()))
+        );
+        assert_eq!(
+            statements("1#[]#2"),
+            Ok(("", [one.clone(), two.clone()].into()))
+        );
+        assert_eq!(
+            expression("[1 #[ 3 ]# 2]"),
+            Ok(("", Expression::List([
+                Expression::Literal(Literal::Int(1)),
+                Expression::Literal(Literal::Int(2)),
+            ].into())))
+        );
+        assert!(statements("1 #[ 2").is_err());
+        assert_eq!(
+            statements("#[ a ]# 1"),
+            Ok(("", [one.clone()].into()))
+        );
+        assert_eq!(
+            statements("1\n  #[ a ]# #[ b\nc ]#\t2 #[ d ]#"),
+            Ok(("", [one.clone(), two.clone()].into()))
+        );
+        assert_eq!(
+            program("  #[ a ]# 1").map(|(_, p)| p.statements),
+            Ok([one].into())
+        );
+    }
+
     #[test]
     fn builtins() {
         assert_eq!(builtin("+"), Ok(("", Builtin::Add)));
         assert_eq!(builtin("-"), Ok(("", Builtin::Sub)));
+        assert!(builtin("-1").is_err());
+        assert_eq!(builtin("read-line"), Ok(("", Builtin::ReadLine)));
+        assert_eq!(builtin("read-file"), Ok(("", Builtin::ReadFile)));
+        assert_eq!(builtin("write-file"), Ok(("", Builtin::WriteFile)));
+        assert_eq!(builtin("append-file"), Ok(("", Builtin::AppendFile)));
+        assert_eq!(builtin("file-exists?"), Ok(("", Builtin::FileExists)));
+        assert_eq!(builtin("delete-file"), Ok(("", Builtin::DeleteFile)));
+        assert_eq!(builtin("list-dir"), Ok(("", Builtin::ListDir)));
+        assert_eq!(builtin("mkdir"), Ok(("", Builtin::Mkdir)));
+        assert_eq!(builtin("env"), Ok(("", Builtin::Env)));
+        assert_eq!(builtin("set-env"), Ok(("", Builtin::SetEnv)));
+        assert_eq!(builtin("args"), Ok(("", Builtin::Args)));
+        assert_eq!(builtin("exit"), Ok(("", Builtin::Exit)));
+        assert_eq!(builtin("now"), Ok(("", Builtin::Now)));
+        assert_eq!(builtin("monotonic"), Ok(("", Builtin::Monotonic)));
+        assert_eq!(builtin("sleep"), Ok(("", Builtin::Sleep)));
+        assert_eq!(builtin("print"), Ok(("", Builtin::Print)));
+        assert_eq!(builtin("println"), Ok(("", Builtin::Println)));
+        assert_eq!(builtin("eprintln"), Ok(("", Builtin::Eprintln)));
+        assert_eq!(builtin("eprint"), Ok(("", Builtin::Eprint)));
+        assert!(builtin("dupe").is_err());
+        assert!(builtin("set!").is_err());
+        assert!(builtin("->string").is_err());
+        assert!(builtin("e2").is_err());
         assert_eq!(builtin("*"), Ok(("", Builtin::Mul)));
         assert_eq!(builtin("/"), Ok(("", Builtin::Div)));
         assert_eq!(builtin("="), Ok(("", Builtin::Eq)));
@@ -199,35 +984,212 @@ mod tests {
         assert_eq!(builtin("over"), Ok(("", Builtin::Over)));
         assert_eq!(builtin("dupd"), Ok(("", Builtin::Dupd)));
         assert_eq!(builtin("rotl"), Ok(("", Builtin::Rotl)));
+        assert_eq!(builtin("pick"), Ok(("", Builtin::Pick)));
+        assert_eq!(builtin("roll"), Ok(("", Builtin::Roll)));
+        assert_eq!(builtin("depth"), Ok(("", Builtin::Depth)));
+        assert_eq!(builtin("clear"), Ok(("", Builtin::Clear)));
+        assert_eq!(builtin("nip"), Ok(("", Builtin::Nip)));
+        assert_eq!(builtin("tuck"), Ok(("", Builtin::Tuck)));
+        assert_eq!(builtin("set"), Ok(("", Builtin::Set)));
+        assert_eq!(builtin("get"), Ok(("", Builtin::Get)));
+        assert!(builtin("setup").is_err());
+        assert_eq!(builtin("list->set"), Ok(("", Builtin::ListToSet)));
+        assert_eq!(builtin("set->list"), Ok(("", Builtin::SetToList)));
+        assert_eq!(builtin("spread"), Ok(("", Builtin::Spread)));
+        assert_eq!(builtin("apply"), Ok(("", Builtin::Apply)));
+        assert_eq!(builtin("quot->list"), Ok(("", Builtin::QuotToList)));
+        assert_eq!(builtin("list->quot"), Ok(("", Builtin::ListToQuot)));
+        assert_eq!(builtin("set-add"), Ok(("", Builtin::SetAdd)));
+        assert_eq!(builtin("set-has?"), Ok(("", Builtin::SetHas)));
+        assert_eq!(builtin("union"), Ok(("", Builtin::Union)));
+        assert_eq!(builtin("intersect"), Ok(("", Builtin::Intersect)));
+        assert_eq!(builtin("difference"), Ok(("", Builtin::Difference)));
+        assert_eq!(builtin("nil?"), Ok(("", Builtin::IsNil)));
+        assert_eq!(builtin("default"), Ok(("", Builtin::Default)));
+        assert_eq!(builtin("?nth"), Ok(("", Builtin::NthOrNil)));
+        assert_eq!(builtin("throw"), Ok(("", Builtin::Throw)));
+        assert_eq!(builtin("try"), Ok(("", Builtin::Try)));
+        assert_eq!(builtin("assert"), Ok(("", Builtin::Assert)));
+        assert_eq!(builtin("cond"), Ok(("", Builtin::Cond)));
+        assert_eq!(builtin("when"), Ok(("", Builtin::When)));
+        assert_eq!(builtin("unless"), Ok(("", Builtin::Unless)));
         assert_eq!(builtin("rotr"), Ok(("", Builtin::Rotr)));
         assert_eq!(builtin("keep"), Ok(("", Builtin::Keep)));
+        assert_eq!(builtin("bi"), Ok(("", Builtin::Bi)));
+        assert_eq!(builtin("tri"), Ok(("", Builtin::Tri)));
+        assert_eq!(builtin("cleave"), Ok(("", Builtin::Cleave)));
         assert_eq!(builtin("eval"), Ok(("", Builtin::Eval)));
         assert_eq!(builtin("println"), Ok(("", Builtin::Println)));
         assert_eq!(builtin("if"), Ok(("", Builtin::If)));
         assert_eq!(builtin("nth"), Ok(("", Builtin::Nth)));
+        assert_eq!(builtin("min"), Ok(("", Builtin::Min)));
+        assert_eq!(builtin("max"), Ok(("", Builtin::Max)));
+        assert_eq!(builtin("abs"), Ok(("", Builtin::Abs)));
+        assert_eq!(builtin("map"), Ok(("", Builtin::Map)));
+        assert_eq!(builtin("filter"), Ok(("", Builtin::Filter)));
+        assert_eq!(builtin("fold"), Ok(("", Builtin::Fold)));
+        assert_eq!(builtin("each"), Ok(("", Builtin::Each)));
+        assert_eq!(builtin("curry"), Ok(("", Builtin::Curry)));
+        assert_eq!(builtin("compose"), Ok(("", Builtin::Compose)));
+        assert_eq!(builtin("times"), Ok(("", Builtin::Times)));
+        assert_eq!(builtin("while"), Ok(("", Builtin::While)));
+        assert_eq!(builtin("range"), Ok(("", Builtin::Range)));
+        assert_eq!(builtin("range-step"), Ok(("", Builtin::RangeStep)));
+        assert_eq!(builtin("len"), Ok(("", Builtin::Len)));
+        assert_eq!(builtin("append"), Ok(("", Builtin::Append)));
+        assert_eq!(builtin("head"), Ok(("", Builtin::Head)));
+        assert_eq!(builtin("tail"), Ok(("", Builtin::Tail)));
+        assert_eq!(builtin("uncons"), Ok(("", Builtin::Uncons)));
+        assert_eq!(builtin("cons"), Ok(("", Builtin::Cons)));
+        assert_eq!(builtin("push"), Ok(("", Builtin::Push)));
+        assert_eq!(builtin("sort"), Ok(("", Builtin::Sort)));
+        assert_eq!(builtin("sort-by"), Ok(("", Builtin::SortBy)));
+        assert_eq!(builtin("zip"), Ok(("", Builtin::Zip)));
+        assert_eq!(builtin("flatten"), Ok(("", Builtin::Flatten)));
+        assert_eq!(builtin("contains?"), Ok(("", Builtin::Contains)));
+        assert_eq!(builtin("index-of"), Ok(("", Builtin::IndexOf)));
+        assert_eq!(builtin("slice"), Ok(("", Builtin::Slice)));
+        assert_eq!(builtin("set-nth"), Ok(("", Builtin::SetNth)));
+        assert_eq!(builtin("split"), Ok(("", Builtin::Split)));
+        assert_eq!(builtin("join"), Ok(("", Builtin::Join)));
+        assert_eq!(builtin("upper"), Ok(("", Builtin::Upper)));
+        assert_eq!(builtin("lower"), Ok(("", Builtin::Lower)));
+        assert_eq!(builtin("substring"), Ok(("", Builtin::Substring)));
+        assert_eq!(builtin("trim"), Ok(("", Builtin::Trim)));
+        assert_eq!(builtin("trim-start"), Ok(("", Builtin::TrimStart)));
+        assert_eq!(builtin("trim-end"), Ok(("", Builtin::TrimEnd)));
+        assert_eq!(
+            builtin("string->number"),
+            Ok(("", Builtin::StringToNumber))
+        );
+        assert_eq!(builtin("to-string"), Ok(("", Builtin::ToString)));
+        assert_eq!(builtin("format"), Ok(("", Builtin::Format)));
+        assert_eq!(builtin("ord"), Ok(("", Builtin::Ord)));
+        assert_eq!(builtin("chr"), Ok(("", Builtin::Chr)));
+        assert_eq!(builtin("mod"), Ok(("", Builtin::Mod)));
+        assert_eq!(builtin("pow"), Ok(("", Builtin::Pow)));
+        assert_eq!(builtin("sqrt"), Ok(("", Builtin::Sqrt)));
+        assert_eq!(builtin("floor"), Ok(("", Builtin::Floor)));
+        assert_eq!(builtin("ceil"), Ok(("", Builtin::Ceil)));
+        assert_eq!(builtin("round"), Ok(("", Builtin::Round)));
+        assert_eq!(builtin("trunc"), Ok(("", Builtin::Trunc)));
+        assert_eq!(builtin("sin"), Ok(("", Builtin::Sin)));
+        assert_eq!(builtin("cos"), Ok(("", Builtin::Cos)));
+        assert_eq!(builtin("tan"), Ok(("", Builtin::Tan)));
+        assert_eq!(builtin("atan2"), Ok(("", Builtin::Atan2)));
+        assert_eq!(builtin("ln"), Ok(("", Builtin::Ln)));
+        assert_eq!(builtin("log10"), Ok(("", Builtin::Log10)));
+        assert_eq!(builtin("exp"), Ok(("", Builtin::Exp)));
+        assert_eq!(builtin("pi"), Ok(("", Builtin::Pi)));
+        assert_eq!(builtin("e"), Ok(("", Builtin::E)));
+        assert_eq!(builtin("and"), Ok(("", Builtin::And)));
+        assert_eq!(builtin("or"), Ok(("", Builtin::Or)));
+        assert_eq!(builtin("xor"), Ok(("", Builtin::Xor)));
+        assert_eq!(builtin("and-then"), Ok(("", Builtin::AndThen)));
+        assert_eq!(builtin("or-else"), Ok(("", Builtin::OrElse)));
+        assert_eq!(builtin("band"), Ok(("", Builtin::BitAnd)));
+        assert_eq!(builtin("bor"), Ok(("", Builtin::BitOr)));
+        assert_eq!(builtin("bxor"), Ok(("", Builtin::BitXor)));
+        assert_eq!(builtin("shl"), Ok(("", Builtin::Shl)));
+        assert_eq!(builtin("shr"), Ok(("", Builtin::Shr)));
+        assert!(builtin("even?").is_err());
+        assert!(builtin("orange").is_err());
+        assert!(builtin("pixel").is_err());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_builtins() {
+        assert_eq!(builtin("re-match?"), Ok(("", Builtin::ReMatch)));
+        assert_eq!(builtin("re-find"), Ok(("", Builtin::ReFind)));
+        assert_eq!(builtin("re-replace"), Ok(("", Builtin::ReReplace)));
+    }
+
+    #[cfg(feature = "sh")]
+    #[test]
+    fn sh_builtin() {
+        assert_eq!(builtin("sh"), Ok(("", Builtin::Sh)));
+        assert!(builtin("shell").is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn time_builtins() {
+        assert_eq!(builtin("format-time"), Ok(("", Builtin::FormatTime)));
+        assert_eq!(builtin("parse-time"), Ok(("", Builtin::ParseTime)));
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn json_builtins() {
+        assert_eq!(builtin("json-parse"), Ok(("", Builtin::JsonParse)));
+        assert_eq!(builtin("json-encode"), Ok(("", Builtin::JsonEncode)));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn image_builtins() {
+        assert_eq!(builtin("save-image"), Ok(("", Builtin::SaveImage)));
+        assert_eq!(builtin("load-image"), Ok(("", Builtin::LoadImage)));
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn csv_builtins() {
+        assert_eq!(builtin("csv-parse"), Ok(("", Builtin::CsvParse)));
+        assert_eq!(
+            builtin("csv-parse-with"),
+            Ok(("", Builtin::CsvParseWith))
+        );
+        assert_eq!(builtin("csv-encode"), Ok(("", Builtin::CsvEncode)));
+        assert_eq!(
+            builtin("csv-encode-with"),
+            Ok(("", Builtin::CsvEncodeWith))
+        );
+    }
+
+    #[cfg(feature = "ureq")]
+    #[test]
+    fn http_builtins() {
+        assert_eq!(builtin("http-get"), Ok(("", Builtin::HttpGet)));
+        assert_eq!(builtin("http-post"), Ok(("", Builtin::HttpPost)));
+    }
+
+    #[cfg(feature = "tcp")]
+    #[test]
+    fn tcp_builtins() {
+        assert_eq!(builtin("tcp-connect"), Ok(("", Builtin::TcpConnect)));
+        assert_eq!(builtin("tcp-listen"), Ok(("", Builtin::TcpListen)));
+        assert_eq!(builtin("tcp-accept"), Ok(("", Builtin::TcpAccept)));
+        assert_eq!(builtin("tcp-send"), Ok(("", Builtin::TcpSend)));
+        assert_eq!(builtin("tcp-recv"), Ok(("", Builtin::TcpRecv)));
     }
 
     #[test]
     fn list() {
         assert_eq!(expression("[1 2 3]"), Ok(("", Expression::List([
-            Expression::Literal(Literal::Number(1.0)),
-            Expression::Literal(Literal::Number(2.0)),
-            Expression::Literal(Literal::Number(3.0)),
+            Expression::Literal(Literal::Int(1)),
+            Expression::Literal(Literal::Int(2)),
+            Expression::Literal(Literal::Int(3)),
         ].into()))));
         assert_eq!(expression("[1 2 3 ]"), Ok(("", Expression::List([
-            Expression::Literal(Literal::Number(1.0)),
-            Expression::Literal(Literal::Number(2.0)),
-            Expression::Literal(Literal::Number(3.0)),
+            Expression::Literal(Literal::Int(1)),
+            Expression::Literal(Literal::Int(2)),
+            Expression::Literal(Literal::Int(3)),
         ].into()))));
         assert_eq!(expression("[ 1 2 3]"), Ok(("", Expression::List([
-            Expression::Literal(Literal::Number(1.0)),
-            Expression::Literal(Literal::Number(2.0)),
-            Expression::Literal(Literal::Number(3.0)),
+            Expression::Literal(Literal::Int(1)),
+            Expression::Literal(Literal::Int(2)),
+            Expression::Literal(Literal::Int(3)),
         ].into()))));
         assert_eq!(expression("[ 1 2 3 ]"), Ok(("", Expression::List([
-            Expression::Literal(Literal::Number(1.0)),
-            Expression::Literal(Literal::Number(2.0)),
-            Expression::Literal(Literal::Number(3.0)),
+            Expression::Literal(Literal::Int(1)),
+            Expression::Literal(Literal::Int(2)),
+            Expression::Literal(Literal::Int(3)),
+        ].into()))));
+        assert_eq!(expression("[true nil]"), Ok(("", Expression::List([
+            Expression::Literal(Literal::Bool(true)),
+            Expression::Literal(Literal::Nil),
         ].into()))));
     }
 
@@ -236,14 +1198,172 @@ mod tests {
         assert_eq!(definition("def inc { 1 + }"), Ok((
             "",
             Statement::Definition {
-                identifier: "inc".into(),
+                identifier: Symbol::new("inc"),
+                effect: None,
+                procedure: Procedure([
+                    Statement::Expression(
+                        Expression::Literal(Literal::Int(1))
+                    ),
+                    Statement::Builtin(Builtin::Add, Span::default()),
+                ].into())
+            })
+        ));
+        assert_eq!(definition("def inc ( n -- n ) { 1 + }"), Ok((
+            "",
+            Statement::Definition {
+                identifier: Symbol::new("inc"),
+                effect: Some(StackEffect {
+                    inputs: ["n".into()].into(),
+                    outputs: ["n".into()].into(),
+                }),
+                procedure: Procedure([
+                    Statement::Expression(
+                        Expression::Literal(Literal::Int(1))
+                    ),
+                    Statement::Builtin(Builtin::Add, Span::default()),
+                ].into())
+            })
+        ));
+        assert!(definition("def inc ( n n ) { 1 + }").is_err());
+        assert_eq!(definition("def answer 42"), Ok((
+            "",
+            Statement::Definition {
+                identifier: Symbol::new("answer"),
+                effect: None,
                 procedure: Procedure([
                     Statement::Expression(
-                        Expression::Literal(Literal::Number(1.0))
+                        Expression::Literal(Literal::Int(42))
                     ),
-                    Statement::Builtin(Builtin::Add),
                 ].into())
             })
         ));
     }
+
+    #[test]
+    fn imports() {
+        assert_eq!(
+            statement("import \"math.stk\""),
+            Ok(("", Statement::Import("math.stk".into())))
+        );
+        assert_eq!(
+            statement("imports"),
+            Ok(("", Statement::Word(Symbol::new("imports"), Span::default())))
+        );
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(identifier("größe"), Ok(("", "größe")));
+        assert_eq!(identifier("π"), Ok(("", "π")));
+        assert_eq!(identifier("_x_1-ü?"), Ok(("", "_x_1-ü?")));
+        assert_eq!(identifier("日本語 x"), Ok((" x", "日本語")));
+        assert!(identifier("1x").is_err());
+        assert_eq!(identifier("-x"), Ok(("", "-x")));
+        assert!(identifier("·x").is_err());
+        assert_eq!(identifier("list-sum"), Ok(("", "list-sum")));
+        assert_eq!(identifier("set!"), Ok(("", "set!")));
+        assert_eq!(identifier("->string"), Ok(("", "->string")));
+        assert_eq!(identifier("<point>"), Ok(("", "<point>")));
+        assert_eq!(identifier("a.b"), Ok((".b", "a")));
+        assert!(identifier("-").is_err());
+        assert!(identifier("--").is_err());
+        assert!(identifier("-1").is_err());
+        assert!(identifier("true").is_err());
+        assert!(identifier("nil").is_err());
+        assert_eq!(identifier("truest"), Ok(("", "truest")));
+        assert_eq!(
+            statement("dupe"),
+            Ok(("", Statement::Word(Symbol::new("dupe"), Span::default())))
+        );
+        assert_eq!(
+            statement("true"),
+            Ok(("", Statement::Expression(
+                Expression::Literal(Literal::Bool(true))
+            )))
+        );
+    }
+
+    #[test]
+    fn structs() {
+        assert_eq!(
+            statement("struct point x y"),
+            Ok(("", Statement::Struct {
+                name: "point".into(),
+                fields: ["x".into(), "y".into()].into(),
+            }))
+        );
+        assert!(matches!(
+            statement("struct point x y x"),
+            Err(nom::Err::Failure(_))
+        ));
+        assert_eq!(
+            statement("struct point x def f { 1 }"),
+            Ok((" def f { 1 }", Statement::Struct {
+                name: "point".into(),
+                fields: ["x".into()].into(),
+            }))
+        );
+        assert_eq!(
+            statement("point-x"),
+            Ok(("", Statement::Word(Symbol::new("point-x"), Span::default())))
+        );
+    }
+
+    #[test]
+    fn vocabs() {
+        assert_eq!(
+            statement("vocab math"),
+            Ok(("", Statement::Vocab("math".into())))
+        );
+        assert_eq!(
+            statement("math.square"),
+            Ok((
+                "",
+                Statement::Word(Symbol::new("math.square"), Span::default())
+            ))
+        );
+    }
+
+    #[test]
+    fn locals() {
+        assert_eq!(procedure("{| x y | y x }"), Ok(("", Procedure([
+            Statement::Locals {
+                names: ["x".into(), "y".into()].into(),
+                body: Procedure([
+                    Statement::Word(Symbol::new("y"), Span::default()),
+                    Statement::Word(Symbol::new("x"), Span::default()),
+                ].into()),
+            },
+        ].into()))));
+        assert_eq!(
+            procedure("{ |x| x }").map(|(_, p)| p.to_string()),
+            Ok("{ | x | x }".into())
+        );
+        assert!(procedure("{| x y }").is_err());
+    }
+
+    #[test]
+    fn stack_effects() {
+        assert_eq!(
+            stack_effect("( -- )"),
+            Ok(("", StackEffect { inputs: [].into(), outputs: [].into() }))
+        );
+        assert_eq!(
+            stack_effect("( x y -- y x )").map(|(_, e)| e.to_string()),
+            Ok("( x y -- y x )".into())
+        );
+        assert!(stack_effect("( x y )").is_err());
+        assert_eq!(
+            stack_effect("(n -- n)").map(|(_, e)| e.to_string()),
+            Ok("( n -- n )".into())
+        );
+        assert_eq!(
+            stack_effect("(--)").map(|(_, e)| e.to_string()),
+            Ok("( -- )".into())
+        );
+        assert_eq!(
+            stack_effect("(\n  a b\n  -- c\n)").map(|(_, e)| e.to_string()),
+            Ok("( a b -- c )".into())
+        );
+    }
 }
@@ -1,72 +1,741 @@
-use std::{fmt::Display, ops::{Add, Div, Mul, Not, Sub}};
+use std::{
+    cmp::Ordering,
+    collections::BTreeSet,
+    fmt::Display,
+    hash::Hash,
+    ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Not, Rem, Shl, Shr, Sub},
+    rc::Rc,
+    sync::Mutex,
+};
 
-use crate::{Expression, Literal, Procedure};
+#[cfg(feature = "num-bigint")]
+use num_bigint::BigInt;
+use num_rational::Rational64;
+use num_traits::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Zero};
+#[cfg(feature = "num-bigint")]
+use num_traits::{FromPrimitive, Signed, ToPrimitive};
 
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[cfg(feature = "tcp")]
+use crate::Socket;
+use crate::{Expression, InterpreterError, Literal, Procedure};
+
+/// A value on the stack. Strings, lists and sets are reference counted, so
+/// copying them with words like `dup` doesn't copy their contents.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Value {
+    /// The absence of a value.
+    Nil,
     /// A boolean value.
     Bool(bool),
+    /// An integer represented as an `i64`.
+    Int(i64),
+    /// An integer too large for an `i64`. Integer operations that overflow
+    /// are promoted to this automatically.
+    #[cfg(feature = "num-bigint")]
+    BigInt(BigInt),
+    /// An exact fraction of two integers. Never holds an integral value.
+    Rational(Rational64),
     /// A numerical value represented as an `f64`.
     Number(f64),
-    /// A textual value represented as a `String`.
-    String(String),
+    /// A textual value.
+    String(Rc<str>),
+    /// An interned name like `:foo`.
+    Symbol(Symbol),
     /// A procedure.
     Procedure(Procedure),
     /// A list.
-    List(Box<[Value]>),
+    List(Rc<[Value]>),
+    /// A set, kept sorted and without duplicates.
+    Set(Rc<[Value]>),
+    /// An instance of a type declared with `struct`.
+    Struct {
+        name: String,
+        fields: Box<[(String, Value)]>,
+    },
+    /// A TCP connection or listener. Can't be serialized.
+    #[cfg(feature = "tcp")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Socket(Socket),
+}
+
+/// An interned name, used for symbol values and for the names of words.
+/// Symbols with the same name share one allocation, so comparing or hashing
+/// them only looks at addresses.
+#[derive(Debug, Clone, Copy)]
+pub struct Symbol(&'static str);
+
+static SYMBOLS: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+impl Symbol {
+    pub fn new(name: &str) -> Self {
+        let mut symbols = SYMBOLS.lock().unwrap();
+
+        match symbols.get(name) {
+            Some(s) => Self(s),
+            None => {
+                let s: &'static str = Box::leak(name.into());
+
+                symbols.insert(s);
+                Self(s)
+            }
+        }
+    }
+
+    /// Returns the symbol with this name if it has been interned, without
+    /// interning it otherwise.
+    pub fn lookup(name: &str) -> Option<Self> {
+        SYMBOLS.lock().unwrap().get(name).map(|&s| Self(s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+/// Symbols are serialized as their names and interned again when
+/// deserialized.
+#[cfg(feature = "serde")]
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S)
+    -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+    -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|name| Self::new(&name))
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::ptr::hash(self.0, state)
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self == other {
+            true => Some(Ordering::Equal),
+            false => self.0.partial_cmp(other.0),
+        }
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, ":{}", self.0)
+    }
+}
+
+/// The operands of a binary numerical operation, converted to floats unless
+/// both are integers.
+enum Numbers {
+    Ints(i64, i64),
+    #[cfg(feature = "num-bigint")]
+    Big(BigInt, BigInt),
+    Rationals(Rational64, Rational64),
+    Floats(f64, f64),
+}
+
+impl Numbers {
+    fn new(a: &Value, b: &Value) -> Option<Self> {
+        match (a, b) {
+            (Value::Int(a), Value::Int(b)) => Some(Self::Ints(*a, *b)),
+            #[cfg(feature = "num-bigint")]
+            (
+                Value::Int(_) | Value::BigInt(_),
+                Value::Int(_) | Value::BigInt(_),
+            ) => Some(Self::Big(a.as_bigint()?, b.as_bigint()?)),
+            (
+                Value::Int(_) | Value::Rational(_),
+                Value::Int(_) | Value::Rational(_),
+            ) => Some(Self::Rationals(a.as_rational()?, b.as_rational()?)),
+            _ => Some(Self::Floats(a.as_f64()?, b.as_f64()?)),
+        }
+    }
+}
+
+impl Value {
+    /// Like `to_string`, but without quoting strings.
+    pub fn to_unquoted_string(&self) -> String {
+        match self {
+            Self::String(s) => s.to_string(),
+            v => v.to_string(),
+        }
+    }
+
+    /// Returns the value as a float if it is numerical.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Int(a) => Some(*a as f64),
+            #[cfg(feature = "num-bigint")]
+            Self::BigInt(a) => a.to_f64(),
+            Self::Rational(a) => Some(*a.numer() as f64 / *a.denom() as f64),
+            Self::Number(a) => Some(*a),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as an integer if it is an integer or an integral
+    /// float.
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Self::Int(a) => Some(*a),
+            Self::Number(a) if a.fract() == 0.0
+                && (i64::MIN as f64..=i64::MAX as f64).contains(a) =>
+                Some(*a as i64),
+            #[cfg(feature = "num-bigint")]
+            Self::BigInt(a) => a.to_i64(),
+            Self::Rational(a) if a.is_integer() => Some(a.to_integer()),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a fraction if it is an integer or a fraction.
+    fn as_rational(&self) -> Option<Rational64> {
+        match self {
+            Self::Int(a) => Some(Rational64::from_integer(*a)),
+            Self::Rational(a) => Some(*a),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a big integer if it is an integer.
+    #[cfg(feature = "num-bigint")]
+    fn as_bigint(&self) -> Option<BigInt> {
+        match self {
+            Self::Int(a) => Some(BigInt::from(*a)),
+            Self::BigInt(a) => Some(a.clone()),
+            _ => None,
+        }
+    }
+
+    /// Retries an integer operation that overflowed with big integers, or
+    /// fails if they aren't enabled.
+    fn overflow(
+        a: i64,
+        b: i64,
+        op: fn(Self, Self) -> Result<Self, InterpreterError>,
+        symbol: &str,
+    ) -> Result<Self, InterpreterError> {
+        #[cfg(feature = "num-bigint")]
+        {
+            let _ = symbol;
+            op(Self::BigInt(a.into()), Self::BigInt(b.into()))
+        }
+
+        #[cfg(not(feature = "num-bigint"))]
+        {
+            let _ = op;
+            Err(format!("Integer overflow in {a} {symbol} {b}").into())
+        }
+    }
+
+    /// Returns the value as the distance of a shift if it is a non-negative
+    /// integer.
+    fn shift(&self) -> Option<u32> {
+        match self {
+            Self::Int(n) => u32::try_from(*n).ok(),
+            #[cfg(feature = "num-bigint")]
+            Self::BigInt(n) => n.to_u32(),
+            _ => None,
+        }
+    }
+
+    /// Returns the smaller of two numbers, propagating `NaN`.
+    pub fn min(self, rhs: Self) -> Result<Self, InterpreterError> {
+        match Numbers::new(&self, &rhs) {
+            Some(Numbers::Ints(a, b)) => Ok(Self::Int(a.min(b))),
+            #[cfg(feature = "num-bigint")]
+            Some(Numbers::Big(a, b)) => Ok(Self::from(a.min(b))),
+            Some(Numbers::Rationals(a, b)) => Ok(Self::from(a.min(b))),
+            Some(Numbers::Floats(a, b)) if a.is_nan() || b.is_nan() =>
+                Ok(Self::Number(f64::NAN)),
+            Some(Numbers::Floats(a, b)) => Ok(Self::Number(a.min(b))),
+            None => Err(InterpreterError::TypeError(
+                format!("Can't take the minimum of {self} and {rhs}")
+            ))
+        }
+    }
+
+    /// Returns the larger of two numbers, propagating `NaN`.
+    pub fn max(self, rhs: Self) -> Result<Self, InterpreterError> {
+        match Numbers::new(&self, &rhs) {
+            Some(Numbers::Ints(a, b)) => Ok(Self::Int(a.max(b))),
+            #[cfg(feature = "num-bigint")]
+            Some(Numbers::Big(a, b)) => Ok(Self::from(a.max(b))),
+            Some(Numbers::Rationals(a, b)) => Ok(Self::from(a.max(b))),
+            Some(Numbers::Floats(a, b)) if a.is_nan() || b.is_nan() =>
+                Ok(Self::Number(f64::NAN)),
+            Some(Numbers::Floats(a, b)) => Ok(Self::Number(a.max(b))),
+            None => Err(InterpreterError::TypeError(
+                format!("Can't take the maximum of {self} and {rhs}")
+            ))
+        }
+    }
+
+    /// Raises a number to a power, staying exact for integers with
+    /// non-negative exponents and fractions with integral exponents.
+    pub fn pow(self, rhs: Self) -> Result<Self, InterpreterError> {
+        match Numbers::new(&self, &rhs) {
+            Some(Numbers::Ints(a, b)) if b >= 0 => match u32::try_from(b)
+                .ok()
+                .and_then(|b| a.checked_pow(b))
+            {
+                Some(c) => Ok(Self::Int(c)),
+                None => Self::overflow(a, b, Self::pow, "^"),
+            },
+            Some(Numbers::Ints(a, b)) =>
+                Ok(Self::Number((a as f64).powf(b as f64))),
+            #[cfg(feature = "num-bigint")]
+            Some(Numbers::Big(a, b)) => match b.to_u32() {
+                Some(b) => Ok(Self::from(a.pow(b))),
+                None if b.is_negative() => Ok(Self::Number(
+                    a.to_f64().unwrap_or(f64::NAN)
+                        .powf(b.to_f64().unwrap_or(f64::NAN))
+                )),
+                None => Err(format!("Integer overflow in {a} ^ {b}").into()),
+            },
+            Some(Numbers::Rationals(a, b)) if b.is_integer() => {
+                let e = b.to_integer();
+                let a = if e < 0 { a.recip() } else { a };
+
+                usize::try_from(e.unsigned_abs()).ok()
+                    .and_then(|e| num_traits::checked_pow(a, e))
+                    .map(Self::from)
+                    .ok_or_else(|| {
+                        format!("Integer overflow in {self} ^ {rhs}").into()
+                    })
+            },
+            Some(Numbers::Rationals(..)) => Ok(Self::Number(
+                self.as_f64().unwrap_or(f64::NAN)
+                    .powf(rhs.as_f64().unwrap_or(f64::NAN))
+            )),
+            Some(Numbers::Floats(a, b)) => Ok(Self::Number(a.powf(b))),
+            None => Err(InterpreterError::TypeError(
+                format!("Can't raise {self} to the power of {rhs}")
+            ))
+        }
+    }
+
+    pub fn abs(self) -> Result<Self, InterpreterError> {
+        match self {
+            Self::Int(a) => match a.checked_abs() {
+                Some(b) => Ok(Self::Int(b)),
+                #[cfg(feature = "num-bigint")]
+                None => Self::BigInt(a.into()).abs(),
+                #[cfg(not(feature = "num-bigint"))]
+                None => Err(format!("Integer overflow in |{a}|").into()),
+            },
+            #[cfg(feature = "num-bigint")]
+            Self::BigInt(a) => Ok(Self::from(a.abs())),
+            Self::Rational(a) => a.numer().checked_abs()
+                .map(|n| Self::Rational(Rational64::new_raw(n, *a.denom())))
+                .ok_or_else(|| format!("Integer overflow in |{a}|").into()),
+            Self::Number(a) => Ok(Self::Number(a.abs())),
+            v => Err(InterpreterError::TypeError(
+                format!("Can't take the absolute value of {v}")
+            ))
+        }
+    }
+
+    /// Orders the variants for comparisons between values of different
+    /// types.
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Bool(_) => 0,
+            Self::Int(_) | Self::Rational(_) | Self::Number(_) => 1,
+            #[cfg(feature = "num-bigint")]
+            Self::BigInt(_) => 1,
+            Self::String(_) => 2,
+            Self::Procedure(_) => 3,
+            Self::List(_) => 4,
+            Self::Set(_) => 5,
+            Self::Struct { .. } => 6,
+            Self::Symbol(_) => 7,
+            Self::Nil => 8,
+            #[cfg(feature = "tcp")]
+            Self::Socket(_) => 9,
+        }
+    }
+}
+
+/// Values are equal if they have the same type and contents, so `1` and
+/// `1.0` differ. The `=` builtin compares numbers of different types by
+/// their order instead.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Nil, Self::Nil) => true,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::Int(a), Self::Int(b)) => a == b,
+            #[cfg(feature = "num-bigint")]
+            (Self::BigInt(a), Self::BigInt(b)) => a == b,
+            (Self::Rational(a), Self::Rational(b)) => a == b,
+            (Self::Number(a), Self::Number(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Symbol(a), Self::Symbol(b)) => a == b,
+            (Self::Procedure(a), Self::Procedure(b)) => a == b,
+            (Self::List(a), Self::List(b)) | (Self::Set(a), Self::Set(b)) =>
+                a == b,
+            (
+                Self::Struct { name: a, fields: x },
+                Self::Struct { name: b, fields: y },
+            ) => a == b && x == y,
+            #[cfg(feature = "tcp")]
+            (Self::Socket(a), Self::Socket(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Compares an integer or fraction to a float without rounding either.
+fn cmp_float(a: &Value, b: f64) -> Option<Ordering> {
+    if b.is_nan() {
+        return None;
+    } else if b.is_infinite() {
+        return Some(if b > 0.0 { Ordering::Less } else { Ordering::Greater });
+    }
+
+    let floor = b.floor();
+    let whole = |a: i64| match floor {
+        f if f >= i64::MAX as f64 => Ordering::Less,
+        f if f < i64::MIN as f64 => Ordering::Greater,
+        f => a.cmp(&(f as i64)),
+    };
+    // The whole parts and the fractions `num / den` in [0, 1) are compared
+    // separately.
+    let (whole, num, den) = match a {
+        Value::Int(a) => (whole(*a), 0, 1),
+        #[cfg(feature = "num-bigint")]
+        Value::BigInt(a) => (a.cmp(&BigInt::from_f64(floor)?), 0, 1),
+        Value::Rational(a) => {
+            let w = a.floor().to_integer();
+            let den = *a.denom() as i128;
+
+            (whole(w), *a.numer() as i128 - w as i128 * den, den)
+        }
+        _ => return None,
+    };
+
+    Some(whole.then_with(|| cmp_fraction(num, den, b - floor)))
+}
+
+/// Compares `num / den` to `f`, both in [0, 1), one binary digit at a time.
+/// Doubling and subtracting one are exact for floats in this range, and `f`
+/// runs out of digits after at most 1074 of them.
+fn cmp_fraction(mut num: i128, den: i128, mut f: f64) -> Ordering {
+    while f != 0.0 {
+        num *= 2;
+        f *= 2.0;
+
+        let (a, b) = (num >= den, f >= 1.0);
+
+        if a != b {
+            return a.cmp(&b);
+        }
+
+        if a {
+            num -= den;
+            f -= 1.0;
+        }
+    }
+
+    num.cmp(&0)
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Bool(a), Self::Bool(b)) => a.partial_cmp(b),
+            (Self::Int(a), Self::Int(b)) => a.partial_cmp(b),
+            #[cfg(feature = "num-bigint")]
+            (
+                Self::Int(_) | Self::BigInt(_),
+                Self::Int(_) | Self::BigInt(_),
+            ) => self.as_bigint().partial_cmp(&other.as_bigint()),
+            (
+                Self::Int(_) | Self::Rational(_),
+                Self::Int(_) | Self::Rational(_),
+            ) => self.as_rational().partial_cmp(&other.as_rational()),
+            (Self::Number(a), Self::Number(b)) => a.partial_cmp(b),
+            (a, Self::Number(b)) if a.rank() == 1 => cmp_float(a, *b),
+            (Self::Number(a), b) if b.rank() == 1 =>
+                cmp_float(b, *a).map(Ordering::reverse),
+            #[cfg(feature = "num-bigint")]
+            (Self::BigInt(a), Self::Rational(b)) =>
+                (a * b.denom()).partial_cmp(&BigInt::from(*b.numer())),
+            #[cfg(feature = "num-bigint")]
+            (Self::Rational(a), Self::BigInt(b)) =>
+                BigInt::from(*a.numer()).partial_cmp(&(b * a.denom())),
+            (Self::String(a), Self::String(b)) => a.partial_cmp(b),
+            (Self::Symbol(a), Self::Symbol(b)) => a.partial_cmp(b),
+            (Self::Procedure(a), Self::Procedure(b)) => a.partial_cmp(b),
+            (Self::List(a), Self::List(b)) => a.partial_cmp(b),
+            (Self::Set(a), Self::Set(b)) => a.partial_cmp(b),
+            (
+                Self::Struct { name: a, fields: x },
+                Self::Struct { name: b, fields: y },
+            ) => (a, x).partial_cmp(&(b, y)),
+            #[cfg(feature = "tcp")]
+            (Self::Socket(a), Self::Socket(b)) =>
+                (a == b).then_some(Ordering::Equal),
+            (a, b) => a.rank().partial_cmp(&b.rank()),
+        }
+    }
 }
 
 impl Add for Value {
-    type Output = Result<Self, String>;
+    type Output = Result<Self, InterpreterError>;
 
     fn add(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Self::Number(a), Self::Number(b)) => Ok(Self::Number(a + b)),
-            (a, b) => Err(format!("Can't add {a} and {b}"))
+        match Numbers::new(&self, &rhs) {
+            Some(Numbers::Ints(a, b)) => match a.checked_add(b) {
+                Some(c) => Ok(Self::Int(c)),
+                None => Self::overflow(a, b, Self::add, "+"),
+            },
+            #[cfg(feature = "num-bigint")]
+            Some(Numbers::Big(a, b)) => Ok(Self::from(a + b)),
+            Some(Numbers::Rationals(a, b)) => a.checked_add(&b)
+                .map(Self::from)
+                .ok_or_else(|| format!("Integer overflow in {a} + {b}").into()),
+            Some(Numbers::Floats(a, b)) => Ok(Self::Number(a + b)),
+            None => match (self, rhs) {
+                (Self::String(a), Self::String(b)) =>
+                    Ok(Self::String(format!("{a}{b}").into())),
+                (a, b) => Err(InterpreterError::TypeError(
+                    format!("Can't add {a} and {b}")
+                ))
+            }
         }
     }
 }
 
 impl Sub for Value {
-    type Output = Result<Self, String>;
+    type Output = Result<Self, InterpreterError>;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Self::Number(a), Self::Number(b)) => Ok(Self::Number(a - b)),
-            (a, b) => Err(format!("Can't subtract {b} from {a}"))
+        match Numbers::new(&self, &rhs) {
+            Some(Numbers::Ints(a, b)) => match a.checked_sub(b) {
+                Some(c) => Ok(Self::Int(c)),
+                None => Self::overflow(a, b, Self::sub, "-"),
+            },
+            #[cfg(feature = "num-bigint")]
+            Some(Numbers::Big(a, b)) => Ok(Self::from(a - b)),
+            Some(Numbers::Rationals(a, b)) => a.checked_sub(&b)
+                .map(Self::from)
+                .ok_or_else(|| format!("Integer overflow in {a} - {b}").into()),
+            Some(Numbers::Floats(a, b)) => Ok(Self::Number(a - b)),
+            None => Err(InterpreterError::TypeError(
+                format!("Can't subtract {rhs} from {self}")
+            ))
         }
     }
 }
 
 impl Mul for Value {
-    type Output = Result<Self, String>;
+    type Output = Result<Self, InterpreterError>;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Self::Number(a), Self::Number(b)) => Ok(Self::Number(a * b)),
-            (a, b) => Err(format!("Can't multiply {a} and {b}"))
+        match Numbers::new(&self, &rhs) {
+            Some(Numbers::Ints(a, b)) => match a.checked_mul(b) {
+                Some(c) => Ok(Self::Int(c)),
+                None => Self::overflow(a, b, Self::mul, "*"),
+            },
+            #[cfg(feature = "num-bigint")]
+            Some(Numbers::Big(a, b)) => Ok(Self::from(a * b)),
+            Some(Numbers::Rationals(a, b)) => a.checked_mul(&b)
+                .map(Self::from)
+                .ok_or_else(|| format!("Integer overflow in {a} * {b}").into()),
+            Some(Numbers::Floats(a, b)) => Ok(Self::Number(a * b)),
+            None => Err(InterpreterError::TypeError(
+                format!("Can't multiply {self} and {rhs}")
+            ))
         }
     }
 }
 
 impl Div for Value {
-    type Output = Result<Self, String>;
+    type Output = Result<Self, InterpreterError>;
 
+    /// Divides two numbers. The quotient of two integers is only an integer
+    /// if the division is exact.
     fn div(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Self::Number(a), Self::Number(b)) => Ok(Self::Number(a / b)),
-            (a, b) => Err(format!("Can't divide {a} by {b}"))
+        match Numbers::new(&self, &rhs) {
+            Some(Numbers::Ints(a, b)) if b != 0 && a.wrapping_rem(b) == 0 =>
+                match a.checked_div(b) {
+                    Some(c) => Ok(Self::Int(c)),
+                    None => Self::overflow(a, b, Self::div, "/"),
+                },
+            Some(Numbers::Ints(a, b)) =>
+                Ok(Self::Number(a as f64 / b as f64)),
+            #[cfg(feature = "num-bigint")]
+            Some(Numbers::Big(a, b)) if !b.is_zero() && (&a % &b).is_zero() =>
+                Ok(Self::from(a / b)),
+            #[cfg(feature = "num-bigint")]
+            Some(Numbers::Big(a, b)) => Ok(Self::Number(
+                a.to_f64().unwrap_or(f64::NAN) / b.to_f64().unwrap_or(f64::NAN)
+            )),
+            Some(Numbers::Rationals(_, b)) if b.is_zero() => Ok(Self::Number(
+                self.as_f64().unwrap_or(f64::NAN) / 0.0
+            )),
+            Some(Numbers::Rationals(a, b)) => a.checked_div(&b)
+                .map(Self::from)
+                .ok_or_else(|| format!("Integer overflow in {a} / {b}").into()),
+            Some(Numbers::Floats(a, b)) => Ok(Self::Number(a / b)),
+            None => Err(InterpreterError::TypeError(
+                format!("Can't divide {self} by {rhs}")
+            ))
+        }
+    }
+}
+
+impl Rem for Value {
+    type Output = Result<Self, InterpreterError>;
+
+    fn rem(self, rhs: Self) -> Self::Output {
+        match Numbers::new(&self, &rhs) {
+            Some(Numbers::Ints(_, 0)) => Err("Division by zero".into()),
+            Some(Numbers::Ints(a, b)) => match a.checked_rem(b) {
+                Some(c) => Ok(Self::Int(c)),
+                None => Self::overflow(a, b, Self::rem, "mod"),
+            },
+            #[cfg(feature = "num-bigint")]
+            Some(Numbers::Big(_, b)) if b.is_zero() =>
+                Err("Division by zero".into()),
+            #[cfg(feature = "num-bigint")]
+            Some(Numbers::Big(a, b)) => Ok(Self::from(a % b)),
+            Some(Numbers::Rationals(_, b)) if b.is_zero() =>
+                Err("Division by zero".into()),
+            Some(Numbers::Rationals(a, b)) => a.checked_div(&b)
+                .and_then(|q| q.trunc().checked_mul(&b))
+                .and_then(|c| a.checked_sub(&c))
+                .map(Self::from)
+                .ok_or_else(|| {
+                    format!("Integer overflow in {a} mod {b}").into()
+                }),
+            Some(Numbers::Floats(a, b)) => Ok(Self::Number(a % b)),
+            None => Err(InterpreterError::TypeError(
+                format!("Can't take the remainder of {self} by {rhs}")
+            ))
         }
     }
 }
 
 impl Not for Value {
-    type Output = Result<Self, String>;
+    type Output = Result<Self, InterpreterError>;
 
     fn not(self) -> Self::Output {
         match self {
             Self::Bool(b) => Ok(Self::Bool(!b)),
-            v => Err(format!("Can't negate {v}"))
+            v => Err(InterpreterError::TypeError(
+                format!("Can't negate {v}")
+            ))
+        }
+    }
+}
+
+impl BitAnd for Value {
+    type Output = Result<Self, InterpreterError>;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        match Numbers::new(&self, &rhs) {
+            Some(Numbers::Ints(a, b)) => Ok(Self::Int(a & b)),
+            #[cfg(feature = "num-bigint")]
+            Some(Numbers::Big(a, b)) => Ok(Self::from(a & b)),
+            _ => Err(InterpreterError::TypeError(
+                format!("Can't apply `band` to {self} and {rhs}")
+            )),
+        }
+    }
+}
+
+impl BitOr for Value {
+    type Output = Result<Self, InterpreterError>;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        match Numbers::new(&self, &rhs) {
+            Some(Numbers::Ints(a, b)) => Ok(Self::Int(a | b)),
+            #[cfg(feature = "num-bigint")]
+            Some(Numbers::Big(a, b)) => Ok(Self::from(a | b)),
+            _ => Err(InterpreterError::TypeError(
+                format!("Can't apply `bor` to {self} and {rhs}")
+            )),
+        }
+    }
+}
+
+impl BitXor for Value {
+    type Output = Result<Self, InterpreterError>;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        match Numbers::new(&self, &rhs) {
+            Some(Numbers::Ints(a, b)) => Ok(Self::Int(a ^ b)),
+            #[cfg(feature = "num-bigint")]
+            Some(Numbers::Big(a, b)) => Ok(Self::from(a ^ b)),
+            _ => Err(InterpreterError::TypeError(
+                format!("Can't apply `bxor` to {self} and {rhs}")
+            )),
+        }
+    }
+}
+
+impl Shl for Value {
+    type Output = Result<Self, InterpreterError>;
+
+    fn shl(self, rhs: Self) -> Self::Output {
+        let Some(n) = rhs.shift() else {
+            return Err(InterpreterError::TypeError(
+                format!("Can't shift {self} left by {rhs}")
+            ));
+        };
+
+        match self {
+            Self::Int(a) if n < 64 && (a << n) >> n == a =>
+                Ok(Self::Int(a << n)),
+            Self::Int(a) => Self::overflow(a, n.into(), Self::shl, "<<"),
+            #[cfg(feature = "num-bigint")]
+            Self::BigInt(a) => Ok(Self::from(a << n)),
+            a => Err(InterpreterError::TypeError(
+                format!("Can't shift {a} left by {n}")
+            )),
+        }
+    }
+}
+
+impl Shr for Value {
+    type Output = Result<Self, InterpreterError>;
+
+    fn shr(self, rhs: Self) -> Self::Output {
+        let Some(n) = rhs.shift() else {
+            return Err(InterpreterError::TypeError(
+                format!("Can't shift {self} right by {rhs}")
+            ));
+        };
+
+        match self {
+            Self::Int(a) => Ok(Self::Int(a >> n.min(63))),
+            #[cfg(feature = "num-bigint")]
+            Self::BigInt(a) => Ok(Self::from(a >> n)),
+            a => Err(InterpreterError::TypeError(
+                format!("Can't shift {a} right by {n}")
+            )),
         }
     }
 }
@@ -74,11 +743,21 @@ impl Not for Value {
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::Nil => write!(f, "nil"),
             Self::Bool(b) => write!(f, "{b}"),
+            Self::Int(a) => write!(f, "{a}"),
+            #[cfg(feature = "num-bigint")]
+            Self::BigInt(a) => write!(f, "{a}"),
+            Self::Rational(a) => write!(f, "{a}"),
             Self::Number(a) => write!(f, "{a}"),
             Self::String(s) => write!(f, "{s:?}"),
+            Self::Symbol(s) => write!(f, "{s}"),
             Self::Procedure(s) => write!(f, "{s}"),
-            Self::List(s) => {
+            Self::List(s) | Self::Set(s) => {
+                if let Self::Set(_) = self {
+                    write!(f, "#")?;
+                }
+
                 write!(f, "[")?;
 
                 for x in s.iter() {
@@ -91,6 +770,38 @@ impl Display for Value {
 
                 write!(f, "]")
             }
+            #[cfg(feature = "tcp")]
+            Self::Socket(s) => write!(f, "{s}"),
+            Self::Struct { name, fields } => {
+                write!(f, "{name} {{")?;
+
+                for (field, x) in fields.iter() {
+                    write!(f, " {field}: {x}")?;
+                }
+
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "num-bigint")]
+impl From<BigInt> for Value {
+    /// Wraps a big integer, narrowing it to an `Int` if it fits.
+    fn from(a: BigInt) -> Self {
+        match a.to_i64() {
+            Some(a) => Self::Int(a),
+            None => Self::BigInt(a),
+        }
+    }
+}
+
+impl From<Rational64> for Value {
+    /// Wraps a fraction, narrowing it to an `Int` if it is integral.
+    fn from(a: Rational64) -> Self {
+        match a.is_integer() {
+            true => Self::Int(a.to_integer()),
+            false => Self::Rational(a),
         }
     }
 }
@@ -98,9 +809,15 @@ impl Display for Value {
 impl From<Literal> for Value {
     fn from(literal: Literal) -> Self {
         match literal {
+            Literal::Nil => Self::Nil,
             Literal::Bool(b) => Self::Bool(b),
-            Literal::Number(a) => Self::Number(a as f64),
-            Literal::String(s) => Self::String(s),
+            Literal::Int(a) => Self::Int(a),
+            #[cfg(feature = "num-bigint")]
+            Literal::BigInt(a) => Self::from(a),
+            Literal::Rational(a) => a.into(),
+            Literal::Number(a) => Self::Number(a),
+            Literal::String(s) => Self::String(s.into()),
+            Literal::Symbol(s) => Self::Symbol(s),
         }
     }
 }